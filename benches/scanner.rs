@@ -1,6 +1,8 @@
 use criterion::{criterion_group, criterion_main, Criterion};
-use image::io::Reader as ImageReader;
-use topcodes::Scanner;
+#[cfg(feature = "visualize")]
+use image::GrayImage;
+use image::ImageReader;
+use topcodes::{Scanner, TopCode};
 
 fn scan(scanner: &mut Scanner, buffer: &[u8]) {
     let topcodes = scanner.scan(buffer, |buffer, index| {
@@ -45,7 +47,221 @@ fn criterion_benchmark(c: &mut Criterion) {
             criterion::BatchSize::SmallInput,
         )
     });
+
+    // Same scan, with the intensities computed on the rayon thread pool when it has more than one
+    // thread
+    #[cfg(feature = "rayon")]
+    c.bench_function("Parallel intensities (source)", |b| {
+        let img = ImageReader::open("assets/source.png")
+            .unwrap()
+            .decode()
+            .unwrap();
+        let (width, height) = (img.width() as usize, img.height() as usize);
+        let buffer = img.into_rgb8().into_raw();
+        let mut scanner = Scanner::new(width, height);
+        b.iter(|| {
+            let _topcodes = scanner.scan_par(buffer.as_slice(), |buffer, index| {
+                (
+                    buffer[index * 3] as u32,
+                    buffer[index * 3 + 1] as u32,
+                    buffer[index * 3 + 2] as u32,
+                )
+            });
+        })
+    });
+
+    // Packing is done on the rayon thread pool when that feature is enabled as well
+    #[cfg(feature = "visualize")]
+    c.bench_function("Binary scanner (source)", |b| {
+        let img = ImageReader::open("assets/source.png")
+            .unwrap()
+            .decode()
+            .unwrap()
+            .into_luma8();
+        let mut scanner = Scanner::new(img.width() as usize, img.height() as usize);
+        b.iter(|| assert_eq!(3, scanner.scan_binary_image(&img).len()))
+    });
+}
+
+fn idle_benchmark(c: &mut Criterion) {
+    // The cost of a frame without markers, such as an idle camera, which is all thresholding
+    let (width, height) = (640, 480);
+    let buffer = vec![0xc0; width * height * 3];
+    let mut scanner = Scanner::new(width, height);
+    c.bench_function("Scanner (blank)", |b| {
+        b.iter(|| {
+            let topcodes = scanner.scan(buffer.as_slice(), |buffer, index| {
+                (
+                    buffer[index * 3] as u32,
+                    buffer[index * 3 + 1] as u32,
+                    buffer[index * 3 + 2] as u32,
+                )
+            });
+            assert!(topcodes.is_empty());
+        })
+    });
+}
+
+fn decode_benchmark(c: &mut Criterion) {
+    let img = ImageReader::open("assets/photo.png")
+        .unwrap()
+        .decode()
+        .unwrap();
+    let (width, height) = (img.width() as usize, img.height() as usize);
+    let buffer = img.into_rgb8().into_raw();
+    let mut scanner = Scanner::new(width, height);
+    let topcodes = scanner.scan(buffer.as_slice(), |buffer, index| {
+        (
+            buffer[index * 3] as u32,
+            buffer[index * 3 + 1] as u32,
+            buffer[index * 3 + 2] as u32,
+        )
+    });
+
+    // Decoding alone, without thresholding, from the centers of the codes found by the scan
+    c.bench_function("Decode (photo)", |b| {
+        b.iter(|| {
+            for topcode in &topcodes {
+                let (x, y) = (topcode.x as usize, topcode.y as usize);
+                assert!(TopCode::default().decode(&scanner, x, y).is_some());
+            }
+        })
+    });
 }
 
-criterion_group!(benches, criterion_benchmark);
+/// A way of thresholding and scanning an image, returning the codes found.
+#[cfg(feature = "visualize")]
+type ThresholdMethod<'a> = &'a dyn Fn(&mut Scanner) -> Vec<TopCode>;
+
+/// Global threshold that best separates the two classes of the histogram of an image, following
+/// Otsu's method.
+#[cfg(feature = "visualize")]
+fn otsu_threshold(img: &GrayImage) -> u8 {
+    let mut histogram = [0u64; 256];
+    for pixel in img.pixels() {
+        histogram[pixel[0] as usize] += 1;
+    }
+    let total = img.pixels().len() as f64;
+    let sum: f64 = (0..256).map(|i| i as f64 * histogram[i] as f64).sum();
+
+    let (mut best, mut best_variance) = (0, 0.0);
+    let (mut background, mut background_sum) = (0.0, 0.0);
+    for (i, &count) in histogram.iter().enumerate() {
+        background += count as f64;
+        background_sum += i as f64 * count as f64;
+        let foreground = total - background;
+        if background == 0.0 || foreground == 0.0 {
+            continue;
+        }
+        let difference = background_sum / background - (sum - background_sum) / foreground;
+        let variance = background * foreground * difference * difference;
+        if variance > best_variance {
+            (best, best_variance) = (i as u8, variance);
+        }
+    }
+    best
+}
+
+/// Compares the adaptive (Wellner) threshold of the scanner with global thresholds applied before
+/// scanning the binarized image: Otsu's threshold, and a fixed threshold halfway between black and
+/// white. The global thresholds are timed including the binarization. The number of codes each
+/// method finds is printed, since a faster method is only worth it if it still finds the codes.
+#[cfg(feature = "visualize")]
+fn threshold_benchmark(c: &mut Criterion) {
+    for asset in ["photo", "source"] {
+        let img = ImageReader::open(format!("assets/{}.png", asset))
+            .unwrap()
+            .decode()
+            .unwrap();
+        let (width, height) = (img.width() as usize, img.height() as usize);
+        let buffer = img.to_rgb8().into_raw();
+        let luma = img.into_luma8();
+        let mut scanner = Scanner::new(width, height);
+
+        let wellner = |scanner: &mut Scanner| {
+            scanner.scan(buffer.as_slice(), |buffer, index| {
+                (
+                    buffer[index * 3] as u32,
+                    buffer[index * 3 + 1] as u32,
+                    buffer[index * 3 + 2] as u32,
+                )
+            })
+        };
+        let global = |scanner: &mut Scanner, threshold: u8| {
+            let mut binary = luma.clone();
+            for pixel in binary.pixels_mut() {
+                pixel[0] = if pixel[0] < threshold { 0 } else { 0xff };
+            }
+            scanner.scan_binary_image(&binary)
+        };
+        let otsu = |scanner: &mut Scanner| global(scanner, otsu_threshold(&luma));
+        let fixed = |scanner: &mut Scanner| global(scanner, 128);
+
+        let mut group = c.benchmark_group(format!("Threshold ({})", asset));
+        let methods: [(&str, ThresholdMethod); 3] =
+            [("wellner", &wellner), ("otsu", &otsu), ("fixed", &fixed)];
+        for (name, method) in methods {
+            eprintln!(
+                "Threshold ({})/{}: {} codes found",
+                asset,
+                name,
+                method(&mut scanner).len()
+            );
+            group.bench_function(name, |b| b.iter(|| method(&mut scanner)));
+        }
+        group.finish();
+    }
+}
+
+#[cfg(feature = "std-parallel")]
+fn parallel_benchmark(c: &mut Criterion) {
+    let img = ImageReader::open("assets/source.png")
+        .unwrap()
+        .decode()
+        .unwrap();
+    let (width, height) = (img.width() as usize, img.height() as usize);
+    let buffer = img.into_rgb8().into_raw();
+    let decode = |buffer: &[u8], index: usize| {
+        (
+            buffer[index * 3] as u32,
+            buffer[index * 3 + 1] as u32,
+            buffer[index * 3 + 2] as u32,
+        )
+    };
+
+    let mut group = c.benchmark_group("Decode (source)");
+    group.bench_function("serial", |b| {
+        let mut scanner = Scanner::new(width, height);
+        b.iter(|| assert_eq!(3, scanner.scan(buffer.as_slice(), decode).len()))
+    });
+    for threads in [2, 4, 8] {
+        group.bench_function(format!("{} threads", threads), |b| {
+            let mut scanner = Scanner::new(width, height);
+            b.iter(|| {
+                let topcodes = scanner.scan_parallel(buffer.as_slice(), decode, threads);
+                assert_eq!(3, topcodes.len());
+            })
+        });
+    }
+    group.finish();
+}
+
+#[cfg(feature = "std-parallel")]
+criterion_group!(parallel, parallel_benchmark);
+#[cfg(feature = "visualize")]
+criterion_group!(thresholds, threshold_benchmark);
+criterion_group!(
+    benches,
+    criterion_benchmark,
+    idle_benchmark,
+    decode_benchmark
+);
+
+#[cfg(not(any(feature = "std-parallel", feature = "visualize")))]
 criterion_main!(benches);
+#[cfg(all(feature = "std-parallel", not(feature = "visualize")))]
+criterion_main!(benches, parallel);
+#[cfg(all(feature = "visualize", not(feature = "std-parallel")))]
+criterion_main!(benches, thresholds);
+#[cfg(all(feature = "std-parallel", feature = "visualize"))]
+criterion_main!(benches, parallel, thresholds);
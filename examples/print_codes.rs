@@ -1,6 +1,6 @@
 use topcodes::Scanner;
 
-use image::io::Reader as ImageReader;
+use image::ImageReader;
 
 fn main() {
     let (mut scanner, buffer) = {
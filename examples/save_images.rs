@@ -1,5 +1,7 @@
 #[cfg(feature = "visualize")]
-use image::io::Reader as ImageReader;
+use image::ImageReader;
+#[cfg(feature = "visualize")]
+use topcodes::Scanner;
 
 fn main() {
     #[cfg(feature = "visualize")]
@@ -14,14 +16,27 @@ fn main() {
             (Scanner::new(width, height), buffer)
         };
 
-        let _topcodes = scanner.scan(&buffer, |buffer, index| {
+        let decode = |buffer: &Vec<u8>, index: usize| {
             (
                 buffer[index * 3] as u32,
                 buffer[index * 3 + 1] as u32,
                 buffer[index * 3 + 2] as u32,
             )
-        });
+        };
+        let candidates = scanner.scan_candidates(&buffer, decode);
+        println!("Found {} candidates", candidates.len());
+        scanner
+            .candidate_heatmap(&candidates)
+            .save("target/candidate_heatmap.png")
+            .expect("Failed to save candidate heatmap");
+
+        let topcodes = scanner.scan(&buffer, decode);
+        println!("Found {} codes", topcodes.len());
         scanner.write_thresholding_image("target/thresholded.png");
+        scanner
+            .threshold_heatmap()
+            .save("target/threshold_heatmap.png")
+            .expect("Failed to save threshold heatmap");
     }
 
     #[cfg(not(feature = "visualize"))]
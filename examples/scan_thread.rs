@@ -0,0 +1,34 @@
+#[cfg(feature = "std")]
+use image::ImageReader;
+#[cfg(feature = "std")]
+use topcodes::{spawn_scan_thread, Scanner};
+
+fn main() {
+    #[cfg(feature = "std")]
+    {
+        let img = ImageReader::open("assets/photo.png")
+            .unwrap()
+            .decode()
+            .unwrap();
+        let (width, height) = (img.width() as usize, img.height() as usize);
+        let frame = img.into_rgb8().into_raw();
+
+        let worker = spawn_scan_thread(Scanner::new(width, height));
+
+        // A camera would send a new frame whenever one is captured, and collect results as
+        // they become ready
+        for i in 0..5 {
+            worker.send(frame.clone());
+            let topcodes = worker.recv().unwrap();
+            println!("Frame {}: {} TopCodes", i, topcodes.len());
+        }
+
+        println!("Dropped {} frames", worker.dropped_frames());
+        worker.join();
+    }
+
+    #[cfg(not(feature = "std"))]
+    {
+        eprintln!("The run target only works with the 'std' feature enabled. Use `cargo run --features std` instead.");
+    }
+}
@@ -0,0 +1,263 @@
+use crate::topcode::{Code, TopCode};
+
+/// Weights used by [primary_code_weighted] to score each TopCode. The score of a code is
+/// `size * radius - center * distance`, where `distance` is the distance (in pixels) from the
+/// center of the code to the center of the image. The code with the highest score wins.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PrimaryCodeWeights {
+    /// Weight applied to the distance from the image center
+    pub center: f64,
+    /// Weight applied to the radius of the code
+    pub size: f64,
+}
+
+impl Default for PrimaryCodeWeights {
+    fn default() -> Self {
+        Self {
+            center: 1.0,
+            size: 1.0,
+        }
+    }
+}
+
+/// Picks the dominant TopCode in an image, favouring large codes close to the center of the image.
+/// Returns `None` if there are no codes.
+pub fn primary_code(codes: &[TopCode], image_center: (f64, f64)) -> Option<&TopCode> {
+    primary_code_weighted(codes, image_center, PrimaryCodeWeights::default())
+}
+
+/// Picks the dominant TopCode in an image using the given weights. See [PrimaryCodeWeights].
+pub fn primary_code_weighted(
+    codes: &[TopCode],
+    image_center: (f64, f64),
+    weights: PrimaryCodeWeights,
+) -> Option<&TopCode> {
+    let score = |code: &TopCode| {
+        let distance = (code.x - image_center.0).hypot(code.y - image_center.1);
+        weights.size * code.radius() - weights.center * distance
+    };
+
+    codes.iter().max_by(|a, b| score(a).total_cmp(&score(b)))
+}
+
+/// Returns true if any of the codes has the given value, wherever it is in the image.
+pub fn contains_code(codes: &[TopCode], code: Code) -> bool {
+    find_code(codes, code).is_some()
+}
+
+/// Returns the first of the codes with the given value, or `None` if there is none.
+pub fn find_code(codes: &[TopCode], code: Code) -> Option<&TopCode> {
+    codes.iter().find(|topcode| topcode.code == Some(code))
+}
+
+/// Counts the codes in each quadrant of an image of the given size, in the order top-left,
+/// top-right, bottom-left and bottom-right, e.g. to tell the user which way to move the camera to
+/// frame the markers. Codes exactly on a center line are counted on its right or bottom side, and
+/// codes outside the image in the nearest quadrant. Expects image coordinates (see
+/// [Scanner::set_center_origin](crate::Scanner::set_center_origin)).
+pub fn quadrant_counts(codes: &[TopCode], width: usize, height: usize) -> [usize; 4] {
+    let (center_x, center_y) = (width as f64 / 2.0, height as f64 / 2.0);
+    let mut counts = [0; 4];
+    for code in codes {
+        let column = usize::from(code.x >= center_x);
+        let row = usize::from(code.y >= center_y);
+        counts[row * 2 + column] += 1;
+    }
+    counts
+}
+
+/// Estimates how the whole capture is rotated from the orientations of its codes, e.g. to turn a
+/// tabletop or document view that was captured upside down. Returns the circular mean of
+/// [TopCode::orientation] in radians, between -π and π: close to 0 if the markers were laid out
+/// upright, and close to ±π if the capture is rotated by 180°. Rotating the view by the opposite
+/// of this angle puts the markers upright.
+///
+/// The mean is taken on the unit circle, so orientations either side of ±π average to ±π rather
+/// than to 0. Returns 0 if there are no codes, or if their orientations cancel out.
+pub fn estimate_global_rotation(codes: &[TopCode]) -> f64 {
+    let (sin, cos) = codes.iter().fold((0.0, 0.0), |(sin, cos), code| {
+        let (s, c) = code.orientation.sin_cos();
+        (sin + s, cos + c)
+    });
+    if sin.hypot(cos) < 1e-9 {
+        return 0.0;
+    }
+    sin.atan2(cos)
+}
+
+/// Returns the scale of the scene in pixels per millimetre, from two markers whose centers are
+/// known to be `real_distance_mm` apart, e.g. markers fixed to the corners of a board. Dividing a
+/// length measured in the image by the scale gives its length in millimetres, as long as it lies
+/// in the plane of the markers and the camera looks straight at that plane.
+pub fn scene_scale(a: &TopCode, b: &TopCode, real_distance_mm: f64) -> f64 {
+    a.distance_to(b) / real_distance_mm
+}
+
+/// Groups codes whose centers are at most `max_gap` pixels apart, directly or through other codes
+/// of the group, e.g. to treat markers placed next to each other as a single compound token.
+/// Groups are ordered by their first code, and the codes of each group keep their input order.
+pub fn cluster_codes(codes: &[TopCode], max_gap: f64) -> Vec<Vec<TopCode>> {
+    // Union-find over the indices of the codes
+    let mut parents: Vec<usize> = (0..codes.len()).collect();
+
+    for i in 0..codes.len() {
+        for j in i + 1..codes.len() {
+            let distance = (codes[i].x - codes[j].x).hypot(codes[i].y - codes[j].y);
+            if distance <= max_gap {
+                let (a, b) = (root(&mut parents, i), root(&mut parents, j));
+                parents[a.max(b)] = a.min(b);
+            }
+        }
+    }
+
+    let mut clusters: Vec<Vec<TopCode>> = Vec::new();
+    let mut cluster_of_root = vec![usize::MAX; codes.len()];
+    for (i, code) in codes.iter().enumerate() {
+        let r = root(&mut parents, i);
+        if cluster_of_root[r] == usize::MAX {
+            cluster_of_root[r] = clusters.len();
+            clusters.push(Vec::new());
+        }
+        clusters[cluster_of_root[r]].push(*code);
+    }
+
+    clusters
+}
+
+/// Returns the root of the set containing `i`, halving the path on the way.
+fn root(parents: &mut [usize], mut i: usize) -> usize {
+    while parents[i] != i {
+        parents[i] = parents[parents[i]];
+        i = parents[i];
+    }
+    i
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f64::consts::PI;
+
+    use super::*;
+
+    #[test]
+    fn codes_are_found_by_value() {
+        let codes = [
+            TopCode::mock(31, 10.0, 0.0, 10.0, 10.0),
+            TopCode::mock(55, 10.0, 0.0, 45.0, 55.0),
+        ];
+
+        assert!(contains_code(&codes, 55));
+        assert!(!contains_code(&codes, 93));
+        assert_eq!(Some(&codes[1]), find_code(&codes, 55));
+        assert_eq!(None, find_code(&[], 31));
+    }
+
+    #[test]
+    fn global_rotation_is_the_circular_mean_of_orientations() {
+        let at = |orientation| TopCode::mock(31, 5.0, orientation, 10.0, 10.0);
+        let upside_down = [at(PI - 0.1), at(-PI + 0.1), at(PI - 0.05), at(-PI + 0.05)];
+
+        assert!((estimate_global_rotation(&upside_down).abs() - PI).abs() < 1e-9);
+        let tilted = estimate_global_rotation(&[at(0.1), at(0.3), at(-0.1)]);
+        assert!((tilted - 0.1).abs() < 1e-3);
+        assert_eq!(0.0, estimate_global_rotation(&[]));
+        assert_eq!(0.0, estimate_global_rotation(&[at(0.5), at(0.5 - PI)]));
+    }
+
+    #[test]
+    fn codes_are_counted_per_quadrant() {
+        let codes = [
+            TopCode::mock(31, 5.0, 0.0, 10.0, 10.0),
+            TopCode::mock(55, 5.0, 0.0, 90.0, 20.0),
+            TopCode::mock(93, 5.0, 0.0, 50.0, 20.0),
+            TopCode::mock(47, 5.0, 0.0, 100.0, 50.0),
+            TopCode::mock(61, 5.0, 0.0, -5.0, 45.0),
+        ];
+
+        assert_eq!([1, 2, 1, 1], quadrant_counts(&codes, 100, 50));
+        assert_eq!([0; 4], quadrant_counts(&[], 100, 50));
+    }
+
+    #[test]
+    fn scene_scale_is_pixels_per_millimetre() {
+        let a = TopCode::mock(31, 5.0, 0.0, 10.0, 20.0);
+        let b = TopCode::mock(55, 5.0, 1.0, 130.0, 180.0);
+
+        assert_eq!(200.0, a.distance_to(&b));
+        assert_eq!(2.0, scene_scale(&a, &b, 100.0));
+        assert_eq!(scene_scale(&a, &b, 100.0), scene_scale(&b, &a, 100.0));
+        // A 30 pixel long object is 15 mm long
+        assert_eq!(15.0, 30.0 / scene_scale(&a, &b, 100.0));
+    }
+
+    #[test]
+    fn separated_codes_form_their_own_clusters() {
+        let codes = [
+            TopCode::mock(31, 5.0, 0.0, 0.0, 0.0),
+            TopCode::mock(55, 5.0, 0.0, 100.0, 0.0),
+        ];
+
+        assert_eq!(
+            vec![vec![codes[0]], vec![codes[1]]],
+            cluster_codes(&codes, 50.0)
+        );
+        assert!(cluster_codes(&[], 50.0).is_empty());
+    }
+
+    #[test]
+    fn touching_codes_are_clustered_transitively() {
+        // 0 touches 2, and 2 touches 3, but 0 and 3 are far apart
+        let codes = [
+            TopCode::mock(31, 5.0, 0.0, 0.0, 0.0),
+            TopCode::mock(55, 5.0, 0.0, 200.0, 200.0),
+            TopCode::mock(93, 5.0, 0.0, 40.0, 0.0),
+            TopCode::mock(47, 5.0, 0.0, 80.0, 0.0),
+        ];
+        let clusters = cluster_codes(&codes, 40.0);
+
+        assert_eq!(vec![codes[0], codes[2], codes[3]], clusters[0]);
+        assert_eq!(vec![codes[1]], clusters[1]);
+        assert_eq!(2, clusters.len());
+    }
+
+    #[test]
+    fn no_primary_code_without_codes() {
+        assert_eq!(None, primary_code(&[], (50.0, 50.0)));
+    }
+
+    #[test]
+    fn primary_code_is_the_most_central_of_equal_sizes() {
+        let codes = [
+            TopCode::mock(31, 10.0, 0.0, 10.0, 10.0),
+            TopCode::mock(55, 10.0, 0.0, 45.0, 55.0),
+            TopCode::mock(93, 10.0, 0.0, 90.0, 90.0),
+        ];
+
+        assert_eq!(Some(55), primary_code(&codes, (50.0, 50.0)).unwrap().code);
+    }
+
+    #[test]
+    fn primary_code_prefers_a_much_larger_code() {
+        let codes = [
+            TopCode::mock(31, 2.0, 0.0, 50.0, 50.0),
+            TopCode::mock(55, 20.0, 0.0, 70.0, 50.0),
+        ];
+
+        assert_eq!(Some(55), primary_code(&codes, (50.0, 50.0)).unwrap().code);
+    }
+
+    #[test]
+    fn primary_code_can_ignore_size() {
+        let codes = [
+            TopCode::mock(31, 2.0, 0.0, 50.0, 50.0),
+            TopCode::mock(55, 20.0, 0.0, 70.0, 50.0),
+        ];
+        let weights = PrimaryCodeWeights {
+            center: 1.0,
+            size: 0.0,
+        };
+
+        let primary = primary_code_weighted(&codes, (50.0, 50.0), weights);
+        assert_eq!(Some(31), primary.unwrap().code);
+    }
+}
@@ -0,0 +1,136 @@
+use std::f64::consts::PI;
+
+use crate::{scanner::Scanner, topcode::TopCode};
+
+/// Number of directions sampled around a TopCode when measuring its rings.
+const DIRECTIONS: usize = 16;
+
+/// Average image intensities (0-255) measured in the rings of a detected TopCode.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RingContrast {
+    /// Average intensity of the white center and white ring
+    pub white: f64,
+    /// Average intensity of the black ring
+    pub black: f64,
+    /// Average local intensity the adaptive threshold compared these samples against
+    pub local_mean: f64,
+}
+
+impl RingContrast {
+    /// Michelson contrast between the white and black rings, between 0 (no contrast) and 1.
+    pub fn contrast(&self) -> f64 {
+        if self.white + self.black <= 0.0 {
+            return 0.0;
+        }
+        ((self.white - self.black) / (self.white + self.black)).max(0.0)
+    }
+
+    /// Threshold ratio that would place the black/white cut exactly halfway between the measured
+    /// ring intensities.
+    pub fn ideal_threshold_ratio(&self) -> Option<f64> {
+        if self.local_mean <= 0.0 {
+            return None;
+        }
+        Some((self.white + self.black) / (2.0 * self.local_mean))
+    }
+}
+
+impl Scanner {
+    /// Measures the intensities of the white and black rings of a TopCode found in the last scan
+    /// of `image_buffer`. The rings are sampled in several directions around the center of the
+    /// code, so this works regardless of its orientation. Returns `None` if the rings fall
+    /// outside of the image.
+    pub fn measure_contrast<T: ?Sized>(
+        &self,
+        image_buffer: &T,
+        decode_rgb: impl Fn(&T, usize) -> (u32, u32, u32),
+        code: &TopCode,
+    ) -> Option<RingContrast> {
+        let (width, height) = (self.image_width(), self.image_height());
+        let mut white = (0.0, 0);
+        let mut black = (0.0, 0);
+        let mut local_mean = 0.0;
+
+        for direction in 0..DIRECTIONS {
+            let angle = 2.0 * PI * direction as f64 / DIRECTIONS as f64;
+            // Middle of the white center, black ring and white ring
+            for (ring, distance) in [0.5, 1.5, 2.5].into_iter().enumerate() {
+                let x = (code.x + angle.cos() * distance * code.unit).round();
+                let y = (code.y + angle.sin() * distance * code.unit).round();
+                if x < 0.0 || y < 0.0 || x >= width as f64 || y >= height as f64 {
+                    return None;
+                }
+
+                let (x, y) = (x as usize, y as usize);
+                let (r, g, b) = decode_rgb(image_buffer, y * width + x);
+                let intensity = (r + g + b) as f64 / 3.0;
+                let samples = if ring == 1 { &mut black } else { &mut white };
+                samples.0 += intensity;
+                samples.1 += 1;
+                local_mean += self.adaptive_mean(x, y) as f64;
+            }
+        }
+
+        Some(RingContrast {
+            white: white.0 / white.1 as f64,
+            black: black.0 / black.1 as f64,
+            local_mean: local_mean / (white.1 + black.1) as f64,
+        })
+    }
+
+    /// Scans the image and suggests a threshold ratio (see [Scanner::set_threshold_ratio]) from
+    /// the ring contrast of every TopCode found, averaging the ratio that would split each code's
+    /// black and white rings halfway. Returns the current ratio if no codes were found. The
+    /// scanner's settings are left unchanged.
+    pub fn auto_tune_threshold<T: ?Sized>(
+        &mut self,
+        image_buffer: &T,
+        decode_rgb: impl Fn(&T, usize) -> (u32, u32, u32),
+    ) -> f64 {
+        let codes = self.scan(image_buffer, &decode_rgb);
+        let ratios: Vec<f64> = codes
+            .iter()
+            .filter_map(|code| self.measure_contrast(image_buffer, &decode_rgb, code))
+            .filter_map(|contrast| contrast.ideal_threshold_ratio())
+            .collect();
+
+        if ratios.is_empty() {
+            self.threshold_ratio()
+        } else {
+            ratios.iter().sum::<f64>() / ratios.len() as f64
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use image::ImageReader;
+
+    use super::*;
+    use crate::test_utils::rgb;
+
+    #[test]
+    fn suggested_threshold_still_finds_every_code() {
+        let img = ImageReader::open("assets/photo.png")
+            .unwrap()
+            .decode()
+            .unwrap();
+        let (width, height) = (img.width() as usize, img.height() as usize);
+        let buffer = img.into_rgb8().into_raw();
+        let mut scanner = Scanner::new(width, height);
+
+        let codes = scanner.scan(buffer.as_slice(), rgb);
+        for code in &codes {
+            let contrast = scanner
+                .measure_contrast(buffer.as_slice(), rgb, code)
+                .unwrap();
+            assert!(contrast.contrast() > 0.2);
+        }
+
+        let ratio = scanner.auto_tune_threshold(buffer.as_slice(), rgb);
+        assert!(ratio > 0.5 && ratio < 1.0);
+
+        scanner.set_threshold_ratio(ratio);
+        assert_eq!(3, scanner.scan(buffer.as_slice(), rgb).len());
+    }
+}
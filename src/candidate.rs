@@ -1,12 +1,32 @@
-/// A candidate [TopCode].
-#[derive(Clone, Copy, Debug)]
-pub(crate) struct Candidate {
+/// A candidate center of a TopCode, found where a row of the thresholded image crosses a black
+/// run, a white run and another black run whose widths are plausible for the rings of a bullseye.
+/// The same bullseye usually yields several candidates on neighbouring rows. See
+/// [Scanner::scan_candidates].
+///
+/// [Scanner::scan_candidates]: crate::Scanner::scan_candidates
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Candidate {
+    /// Horizontal position of the candidate, in the middle of the white run
     pub x: usize,
+    /// Row the candidate was found on
     pub y: usize,
+    /// Width in pixels of the first black run along the row
+    pub b1: usize,
+    /// Width in pixels of the white run along the row
+    pub w1: usize,
+    /// Width in pixels of the second black run along the row
+    pub b2: usize,
 }
 
 impl Candidate {
-    pub fn new(x: usize, y: usize) -> Self {
-        Self { x, y }
+    /// A candidate at (x, y) that was not measured along a row, e.g. proposed by another detector.
+    pub(crate) fn new(x: usize, y: usize) -> Self {
+        Self {
+            x,
+            y,
+            b1: 0,
+            w1: 0,
+            b2: 0,
+        }
     }
 }
@@ -0,0 +1,281 @@
+use crate::topcode::WIDTH;
+
+/// Default maximum width of a TopCode unit/ring in pixels. This is equivalent to 640 pixels.
+const DEFAULT_MAX_UNIT: usize = 80;
+
+/// Default ratio of the local average intensity below which a pixel is considered black.
+const DEFAULT_THRESHOLD_RATIO: f64 = 0.975;
+
+/// Number of fixed-point iterations used to invert the distortion model
+const UNDISTORT_ITERATIONS: usize = 8;
+
+/// The channel of an RGB image from which the scanner computes the intensity of each pixel.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Channel {
+    /// Average of the red, green and blue channels
+    #[default]
+    Luma,
+    /// Red channel only
+    Red,
+    /// Green channel only
+    Green,
+    /// Blue channel only
+    Blue,
+}
+
+/// What the scanner does with a candidate that lies within a code that was already found, see
+/// [Scanner::set_overlap_strategy](crate::Scanner::set_overlap_strategy).
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum OverlapStrategy {
+    /// Skip the candidate, taking it for another center of the code that was found
+    #[default]
+    Suppress,
+    /// Decode the candidate, and keep its code instead of the codes it overlaps if it is more
+    /// confident than all of them
+    KeepHigherConfidence,
+    /// Decode the candidate, and keep its code as well if it differs from the codes it overlaps
+    KeepBoth,
+}
+
+impl Channel {
+    /// Intensity of a pixel in this channel.
+    pub(crate) fn intensity(self, (r, g, b): (u32, u32, u32)) -> u32 {
+        match self {
+            Channel::Luma => (r + g + b) / 3,
+            Channel::Red => r,
+            Channel::Green => g,
+            Channel::Blue => b,
+        }
+    }
+}
+
+/// Radial lens distortion, following the radial part of the Brown-Conrady model. Coordinates are
+/// taken relative to the center of the image and normalized by half its diagonal, so that the
+/// corners are at a distance of 1. A point at a normalized distance `r` from the center of an
+/// undistorted image appears at a distance `r * (1 + k1 * r^2 + k2 * r^4)` in the image. Barrel
+/// distortion, typical of wide-angle lenses, has a negative `k1`.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Distortion {
+    /// Second order radial coefficient
+    pub k1: f64,
+    /// Fourth order radial coefficient
+    pub k2: f64,
+}
+
+impl Distortion {
+    /// Maps a point of the undistorted image to where it appears in an image of the given size.
+    pub(crate) fn distort(&self, (width, height): (usize, usize), x: f64, y: f64) -> (f64, f64) {
+        let (cx, cy, norm) = frame(width, height);
+        let (nx, ny) = ((x - cx) / norm, (y - cy) / norm);
+        let factor = self.factor(nx * nx + ny * ny);
+        (cx + nx * factor * norm, cy + ny * factor * norm)
+    }
+
+    /// Maps a point of an image of the given size to where it lies in the undistorted image.
+    pub(crate) fn undistort(&self, (width, height): (usize, usize), x: f64, y: f64) -> (f64, f64) {
+        let (cx, cy, norm) = frame(width, height);
+        let (dx, dy) = ((x - cx) / norm, (y - cy) / norm);
+        let (mut nx, mut ny) = (dx, dy);
+        for _ in 0..UNDISTORT_ITERATIONS {
+            let factor = self.factor(nx * nx + ny * ny);
+            nx = dx / factor;
+            ny = dy / factor;
+        }
+        (cx + nx * norm, cy + ny * norm)
+    }
+
+    /// Ratio of the distorted to the undistorted distance at the squared distance `r2`.
+    fn factor(&self, r2: f64) -> f64 {
+        1.0 + self.k1 * r2 + self.k2 * r2 * r2
+    }
+}
+
+/// Center and half diagonal of an image, which normalize coordinates for [Distortion].
+fn frame(width: usize, height: usize) -> (f64, f64, f64) {
+    let (width, height) = (width as f64, height as f64);
+    (width / 2.0, height / 2.0, width.hypot(height) / 2.0)
+}
+
+/// The parameters of a [Scanner](crate::Scanner) that can be changed between scans without
+/// reconstructing it. Each field has a matching setter on the scanner, which documents it in
+/// detail; [Scanner::set_config](crate::Scanner::set_config) replaces all of them at once.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ScannerConfig {
+    /// Maximum width of a TopCode unit in pixels
+    pub max_unit: usize,
+    /// Minimum confidence of a TopCode returned by the scanner
+    pub min_confidence: f64,
+    /// Maximum number of TopCodes returned by the scanner
+    pub max_codes: Option<usize>,
+    /// Minimum distance in pixels between the centers of the TopCodes returned by the scanner
+    pub min_code_spacing: Option<f64>,
+    /// What happens to candidates inside codes that were already found
+    pub overlap_strategy: OverlapStrategy,
+    /// Whether the returned coordinates are relative to the image center, with y pointing up
+    pub center_origin: bool,
+    /// Whether decoded orientations are cross-checked against the image
+    pub check_orientation: bool,
+    /// Channel from which the intensity of each pixel is computed
+    pub channel: Channel,
+    /// Ratio of the local average intensity below which a pixel is considered black
+    pub threshold_ratio: f64,
+    /// Whether the threshold alternates the scan direction between rows
+    pub bidirectional: bool,
+    /// Number of samples taken across the diameter of a marker for every sector
+    pub radial_samples: usize,
+    /// Angle in radians between each sample ray and the extra rays either side of it
+    pub angular_spread: f64,
+    /// Precision in radians to which the orientation of a code is refined, if at all
+    pub orientation_precision: Option<f64>,
+    /// Whether the center of a code is estimated from edge distances to a fraction of a pixel
+    pub subpixel_centering: bool,
+    /// Number of times an asymmetric bullseye is re-centered before the candidate is rejected
+    pub max_recenter_iterations: usize,
+    /// Whether the unit may be estimated from three ring edges when the fourth is not found
+    pub allow_missing_edge: bool,
+    /// Whether a code with a single unreadable sector is recovered from the checksum
+    pub recover_sector: bool,
+    /// Whether the unit is estimated from the white core of the bullseye when the rings fail
+    pub recover_unit: bool,
+    /// Whether the unit of the first codes found limits the candidates decoded afterwards
+    pub auto_unit: bool,
+    /// Largest difference between the horizontal and vertical bullseye diameters, in units
+    pub max_ring_asymmetry: f64,
+    /// Whether the threshold of every pixel is kept in a buffer of its own after each scan
+    pub keep_thresholds: bool,
+    /// Whether small markers that fail to decode are decoded again from an upsampled region
+    pub upsample_small_markers: bool,
+    /// Lens distortion corrected when sampling codes, if any
+    pub distortion: Option<Distortion>,
+    /// Width of a pixel divided by its height, corrected when sampling codes
+    pub pixel_aspect_ratio: f64,
+}
+
+impl ScannerConfig {
+    /// Whether the scanner can work with this config: units of at least 2 pixels, a positive
+    /// threshold ratio, at least one radial sample, a positive pixel aspect ratio, a positive
+    /// orientation precision, and finite confidence, spacing, spread and asymmetry values.
+    pub fn is_valid(&self) -> bool {
+        self.max_unit >= 2
+            && self.min_confidence.is_finite()
+            && self.threshold_ratio.is_finite()
+            && self.threshold_ratio > 0.0
+            && self.radial_samples > 0
+            && self.angular_spread.is_finite()
+            && self.angular_spread >= 0.0
+            && self
+                .orientation_precision
+                .is_none_or(|precision| precision.is_finite() && precision > 0.0)
+            && self
+                .min_code_spacing
+                .is_none_or(|spacing| spacing.is_finite() && spacing >= 0.0)
+            && self
+                .distortion
+                .is_none_or(|distortion| distortion.k1.is_finite() && distortion.k2.is_finite())
+            && self.pixel_aspect_ratio.is_finite()
+            && self.pixel_aspect_ratio > 0.0
+            && self.max_ring_asymmetry.is_finite()
+            && self.max_ring_asymmetry >= 0.0
+    }
+}
+
+impl Default for ScannerConfig {
+    fn default() -> Self {
+        Self {
+            max_unit: DEFAULT_MAX_UNIT,
+            min_confidence: 0.0,
+            max_codes: None,
+            min_code_spacing: None,
+            overlap_strategy: OverlapStrategy::Suppress,
+            center_origin: false,
+            check_orientation: false,
+            channel: Channel::Luma,
+            threshold_ratio: DEFAULT_THRESHOLD_RATIO,
+            bidirectional: true,
+            radial_samples: WIDTH,
+            angular_spread: 0.0,
+            orientation_precision: None,
+            subpixel_centering: false,
+            max_recenter_iterations: 0,
+            allow_missing_edge: false,
+            recover_sector: false,
+            recover_unit: false,
+            auto_unit: false,
+            max_ring_asymmetry: 1.0,
+            keep_thresholds: false,
+            upsample_small_markers: false,
+            distortion: None,
+            pixel_aspect_ratio: 1.0,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn the_default_config_is_valid() {
+        assert!(ScannerConfig::default().is_valid());
+    }
+
+    #[test]
+    fn channels_select_the_intensity_of_a_pixel() {
+        let pixel = (30, 60, 120);
+        assert_eq!(70, Channel::Luma.intensity(pixel));
+        assert_eq!(30, Channel::Red.intensity(pixel));
+        assert_eq!(60, Channel::Green.intensity(pixel));
+        assert_eq!(120, Channel::Blue.intensity(pixel));
+    }
+
+    #[test]
+    fn undistort_inverts_distort() {
+        let distortion = Distortion { k1: -0.2, k2: 0.05 };
+        let (x, y) = distortion.distort((640, 480), 600.0, 50.0);
+        assert!(x < 600.0 && y > 50.0);
+
+        let (ux, uy) = distortion.undistort((640, 480), x, y);
+        assert!((ux - 600.0).abs() < 1e-3 && (uy - 50.0).abs() < 1e-3);
+        assert_eq!((320.0, 240.0), distortion.distort((640, 480), 320.0, 240.0));
+    }
+
+    #[test]
+    fn it_rejects_degenerate_values() {
+        let default = ScannerConfig::default();
+        assert!(!ScannerConfig {
+            max_unit: 1,
+            ..default
+        }
+        .is_valid());
+        assert!(!ScannerConfig {
+            threshold_ratio: f64::NAN,
+            ..default
+        }
+        .is_valid());
+        assert!(!ScannerConfig {
+            angular_spread: -0.1,
+            ..default
+        }
+        .is_valid());
+        assert!(!ScannerConfig {
+            min_code_spacing: Some(f64::INFINITY),
+            ..default
+        }
+        .is_valid());
+        assert!(!ScannerConfig {
+            pixel_aspect_ratio: 0.0,
+            ..default
+        }
+        .is_valid());
+        assert!(!ScannerConfig {
+            orientation_precision: Some(0.0),
+            ..default
+        }
+        .is_valid());
+        assert!(!ScannerConfig {
+            max_ring_asymmetry: -1.0,
+            ..default
+        }
+        .is_valid());
+    }
+}
@@ -0,0 +1,105 @@
+//! Conversions from [TopCode] into the types of common geometry crates. Each crate is behind a
+//! feature of the same name.
+//!
+//! The center of a TopCode converts into a point (or vector) and its orientation into a rotation.
+//! Both stay in image coordinates, where y points down, so a positive rotation is clockwise on
+//! screen.
+
+#[cfg(feature = "euclid")]
+mod euclid_impls {
+    use euclid::{Angle, Point2D, Rotation2D};
+
+    use crate::topcode::TopCode;
+
+    impl<U> From<&TopCode> for Point2D<f64, U> {
+        fn from(code: &TopCode) -> Self {
+            Point2D::new(code.x, code.y)
+        }
+    }
+
+    impl<Src, Dst> From<&TopCode> for Rotation2D<f64, Src, Dst> {
+        fn from(code: &TopCode) -> Self {
+            Rotation2D::new(Angle::radians(code.orientation))
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use euclid::default::{Point2D, Rotation2D};
+
+        use super::*;
+
+        #[test]
+        fn converts_center_and_orientation() {
+            let code = TopCode::mock(31, 5.0, 0.5, 10.0, 20.0);
+
+            assert_eq!(Point2D::new(10.0, 20.0), Point2D::from(&code));
+            assert_eq!(0.5, Rotation2D::from(&code).angle);
+        }
+    }
+}
+
+#[cfg(feature = "glam")]
+mod glam_impls {
+    use glam::{DMat2, DVec2};
+
+    use crate::topcode::TopCode;
+
+    impl From<&TopCode> for DVec2 {
+        fn from(code: &TopCode) -> Self {
+            DVec2::new(code.x, code.y)
+        }
+    }
+
+    impl From<&TopCode> for DMat2 {
+        fn from(code: &TopCode) -> Self {
+            DMat2::from_angle(code.orientation)
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        #[test]
+        fn converts_center_and_orientation() {
+            let code = TopCode::mock(31, 5.0, std::f64::consts::FRAC_PI_2, 10.0, 20.0);
+
+            assert_eq!(DVec2::new(10.0, 20.0), DVec2::from(&code));
+            let rotated = DMat2::from(&code) * DVec2::X;
+            assert!(rotated.abs_diff_eq(DVec2::Y, 1e-12));
+        }
+    }
+}
+
+#[cfg(feature = "nalgebra")]
+mod nalgebra_impls {
+    use nalgebra::{Point2, Rotation2};
+
+    use crate::topcode::TopCode;
+
+    impl From<&TopCode> for Point2<f64> {
+        fn from(code: &TopCode) -> Self {
+            Point2::new(code.x, code.y)
+        }
+    }
+
+    impl From<&TopCode> for Rotation2<f64> {
+        fn from(code: &TopCode) -> Self {
+            Rotation2::new(code.orientation)
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        #[test]
+        fn converts_center_and_orientation() {
+            let code = TopCode::mock(31, 5.0, 0.5, 10.0, 20.0);
+
+            assert_eq!(Point2::new(10.0, 20.0), Point2::from(&code));
+            assert!((Rotation2::from(&code).angle() - 0.5).abs() < 1e-12);
+        }
+    }
+}
@@ -0,0 +1,130 @@
+use std::borrow::Cow;
+
+use image::{DynamicImage, RgbImage};
+
+use crate::{config::ScannerConfig, scanner::Scanner, topcode::TopCode};
+
+/// The simplest way to find TopCodes in images decoded with the `image` crate. A detector takes
+/// images of any size and color type, converts them for the scanner, and keeps a [Scanner] for
+/// the size of the last image, so detecting codes in the frames of a video does not allocate once
+/// the first frame is scanned.
+///
+/// The scanner uses the default [ScannerConfig] unless another one is given with
+/// [Detector::with_config]. For anything the detector does not cover, such as streaming rows or
+/// diagnostics, use a [Scanner] directly.
+///
+/// ```no_run
+/// use topcodes::Detector;
+///
+/// let img = image::open("photo.png")?;
+/// let topcodes = Detector::new().detect(&img);
+/// # Ok::<(), image::ImageError>(())
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct Detector {
+    config: ScannerConfig,
+    scanner: Option<Scanner>,
+}
+
+impl Detector {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Creates a detector whose scanner uses the given config.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the config is not [valid](ScannerConfig::is_valid).
+    pub fn with_config(config: ScannerConfig) -> Self {
+        assert!(config.is_valid(), "Invalid scanner config: {:?}", config);
+        Self {
+            config,
+            scanner: None,
+        }
+    }
+
+    /// Returns the config used to scan images.
+    pub fn config(&self) -> &ScannerConfig {
+        &self.config
+    }
+
+    /// Finds the TopCodes in an image. Images that are not 8-bit RGB are converted first.
+    pub fn detect(&mut self, img: &DynamicImage) -> Vec<TopCode> {
+        let rgb = match img {
+            DynamicImage::ImageRgb8(rgb) => Cow::Borrowed(rgb),
+            img => Cow::<RgbImage>::Owned(img.to_rgb8()),
+        };
+        let (width, height) = (rgb.width() as usize, rgb.height() as usize);
+
+        if !matches!(&self.scanner, Some(s) if s.dimensions() == (width, height)) {
+            self.scanner = None;
+        }
+        let config = self.config;
+        let scanner = self.scanner.get_or_insert_with(|| {
+            let mut scanner = Scanner::new(width, height);
+            scanner.set_config(config);
+            scanner
+        });
+
+        scanner.scan(rgb.as_raw(), |buffer, index| {
+            (
+                buffer[index * 3] as u32,
+                buffer[index * 3 + 1] as u32,
+                buffer[index * 3 + 2] as u32,
+            )
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::render;
+
+    fn image(width: usize, height: usize, codes: &[(u32, f64, f64, f64, f64)]) -> DynamicImage {
+        let buffer = render(width, height, codes);
+        DynamicImage::ImageRgb8(RgbImage::from_raw(width as u32, height as u32, buffer).unwrap())
+    }
+
+    #[test]
+    fn it_detects_codes_in_images_of_any_size_and_type() {
+        let mut detector = Detector::new();
+        let small = image(100, 100, &[(31, 50.0, 50.0, 5.0, 0.0)]);
+        let large = image(
+            200,
+            100,
+            &[(55, 60.0, 50.0, 5.0, 0.0), (93, 150.0, 50.0, 4.0, 1.0)],
+        );
+
+        let codes = |topcodes: Vec<TopCode>| {
+            let mut codes: Vec<_> = topcodes
+                .iter()
+                .map(|topcode| topcode.code.unwrap())
+                .collect();
+            codes.sort();
+            codes
+        };
+        assert_eq!(vec![31], codes(detector.detect(&small)));
+        assert_eq!(vec![55, 93], codes(detector.detect(&large)));
+        let gray = DynamicImage::ImageLuma8(large.to_luma8());
+        assert_eq!(vec![55, 93], codes(detector.detect(&gray)));
+    }
+
+    #[test]
+    fn the_config_is_kept_across_sizes() {
+        let config = ScannerConfig {
+            max_codes: Some(1),
+            ..ScannerConfig::default()
+        };
+        let mut detector = Detector::with_config(config);
+        let img = image(
+            200,
+            100,
+            &[(55, 60.0, 50.0, 5.0, 0.0), (93, 150.0, 50.0, 4.0, 1.0)],
+        );
+
+        assert_eq!(1, detector.detect(&img).len());
+        assert_eq!(Some(1), detector.config().max_codes);
+    }
+}
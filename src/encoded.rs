@@ -0,0 +1,173 @@
+use crate::{errors::ScanError, scanner::Scanner, topcode::TopCode};
+
+/// Decodes an encoded image held in memory, header included, and returns the TopCodes found in
+/// it. Binary PPM (`P6`) images are always supported. With the `image` feature, PNG and the other
+/// formats enabled in the `image` crate are supported as well.
+///
+/// This is meant for quick prototyping and tests. Scanning a stream of frames is faster with a
+/// [Scanner] that is reused between frames.
+pub fn scan_bytes(bytes: &[u8]) -> Result<Vec<TopCode>, ScanError> {
+    let (width, height, pixels) = decode(bytes)?;
+    let mut scanner = Scanner::new(width, height);
+
+    Ok(scanner.scan(pixels.as_slice(), |buffer, index| {
+        (
+            buffer[index * 3] as u32,
+            buffer[index * 3 + 1] as u32,
+            buffer[index * 3 + 2] as u32,
+        )
+    }))
+}
+
+/// Decodes an image into its dimensions and RGB pixels.
+fn decode(bytes: &[u8]) -> Result<(usize, usize, Vec<u8>), ScanError> {
+    if bytes.starts_with(b"P6") {
+        return decode_ppm(bytes);
+    }
+
+    #[cfg(feature = "image")]
+    {
+        let img = image::load_from_memory(bytes).map_err(|err| match err {
+            image::ImageError::Unsupported(_) => ScanError::UnsupportedFormat,
+            err => ScanError::Image(err),
+        })?;
+        let (width, height) = (img.width() as usize, img.height() as usize);
+        Ok((width, height, img.into_rgb8().into_raw()))
+    }
+
+    #[cfg(not(feature = "image"))]
+    Err(ScanError::UnsupportedFormat)
+}
+
+/// Decodes a binary PPM image. The header holds the `P6` magic number, the width, the height and
+/// the maximum sample value, separated by whitespace and optionally interleaved with `#`
+/// comments, followed by a single whitespace character and the samples.
+fn decode_ppm(bytes: &[u8]) -> Result<(usize, usize, Vec<u8>), ScanError> {
+    let mut pos = 2;
+    let mut fields = [0usize; 3];
+
+    for field in &mut fields {
+        // Skip whitespace and comments before each field
+        loop {
+            match bytes.get(pos) {
+                Some(b) if b.is_ascii_whitespace() => pos += 1,
+                Some(b'#') => {
+                    while bytes.get(pos).is_some_and(|&b| b != b'\n') {
+                        pos += 1;
+                    }
+                }
+                _ => break,
+            }
+        }
+
+        let start = pos;
+        while bytes.get(pos).is_some_and(u8::is_ascii_digit) {
+            pos += 1;
+        }
+        *field = std::str::from_utf8(&bytes[start..pos])
+            .ok()
+            .and_then(|digits| digits.parse().ok())
+            .ok_or(ScanError::MalformedHeader)?;
+    }
+
+    let [width, height, max_value] = fields;
+    if width == 0 || height == 0 || max_value == 0 || max_value > 0xff {
+        return Err(ScanError::MalformedHeader);
+    }
+    if !bytes.get(pos).is_some_and(u8::is_ascii_whitespace) {
+        return Err(ScanError::MalformedHeader);
+    }
+
+    let len = width
+        .checked_mul(height)
+        .and_then(|pixels| pixels.checked_mul(3))
+        .ok_or(ScanError::MalformedHeader)?;
+    let samples = bytes
+        .get(pos + 1..pos + 1 + len)
+        .ok_or(ScanError::Truncated)?;
+
+    // Stretch samples with a smaller maximum value to the full 0-255 range
+    let pixels = samples
+        .iter()
+        .map(|&sample| (sample as usize * 0xff / max_value).min(0xff) as u8)
+        .collect();
+
+    Ok((width, height, pixels))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::render;
+
+    fn ppm(width: usize, height: usize, pixels: &[u8]) -> Vec<u8> {
+        let mut bytes = format!("P6\n# synthetic\n{} {}\n255\n", width, height).into_bytes();
+        bytes.extend_from_slice(pixels);
+        bytes
+    }
+
+    #[test]
+    fn it_scans_a_ppm_image() {
+        let pixels = render(100, 100, &[(31, 50.0, 50.0, 5.0, 0.0)]);
+        let topcodes = scan_bytes(&ppm(100, 100, &pixels)).unwrap();
+
+        assert_eq!(
+            vec![Some(31)],
+            topcodes.iter().map(|t| t.code).collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn it_rejects_malformed_ppm_images() {
+        let pixels = render(100, 100, &[]);
+        let bytes = ppm(100, 100, &pixels);
+
+        assert!(matches!(
+            scan_bytes(b"P6 100"),
+            Err(ScanError::MalformedHeader)
+        ));
+        assert!(matches!(
+            scan_bytes(b"P6 0 0 255\n"),
+            Err(ScanError::MalformedHeader)
+        ));
+        assert!(matches!(
+            scan_bytes(b"P6 1 1 65535\n"),
+            Err(ScanError::MalformedHeader)
+        ));
+        assert!(matches!(
+            scan_bytes(&bytes[..100]),
+            Err(ScanError::Truncated)
+        ));
+    }
+
+    #[test]
+    #[cfg(not(feature = "image"))]
+    fn it_rejects_other_formats_without_the_image_feature() {
+        assert!(matches!(
+            scan_bytes(b"GIF89a"),
+            Err(ScanError::UnsupportedFormat)
+        ));
+    }
+
+    #[test]
+    #[cfg(feature = "image")]
+    fn it_scans_a_png_image() {
+        use std::io::Cursor;
+
+        let pixels = render(100, 100, &[(93, 50.0, 50.0, 5.0, 0.0)]);
+        let img = image::RgbImage::from_raw(100, 100, pixels).unwrap();
+        let mut png = Vec::new();
+        img.write_to(&mut Cursor::new(&mut png), image::ImageFormat::Png)
+            .unwrap();
+
+        let topcodes = scan_bytes(&png).unwrap();
+        assert_eq!(
+            vec![Some(93)],
+            topcodes.iter().map(|t| t.code).collect::<Vec<_>>()
+        );
+        assert!(matches!(
+            scan_bytes(b"not an image"),
+            Err(ScanError::UnsupportedFormat)
+        ));
+    }
+}
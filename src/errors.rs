@@ -1,4 +1,37 @@
-#[derive(Clone, Copy, Debug)]
-pub enum TopCodeError {
-    IncorrectBufferSize,
+use std::{error::Error, fmt};
+
+/// Errors returned by [scan_bytes](crate::scan_bytes).
+#[derive(Debug)]
+pub enum ScanError {
+    /// The data is not in a supported image format.
+    UnsupportedFormat,
+    /// The header of a PPM image is malformed or announces an empty image.
+    MalformedHeader,
+    /// The image holds fewer pixels than its header announces.
+    Truncated,
+    /// The image could not be decoded by the `image` crate.
+    #[cfg(feature = "image")]
+    Image(image::ImageError),
+}
+
+impl fmt::Display for ScanError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ScanError::UnsupportedFormat => write!(f, "unsupported image format"),
+            ScanError::MalformedHeader => write!(f, "malformed PPM header"),
+            ScanError::Truncated => write!(f, "image data is truncated"),
+            #[cfg(feature = "image")]
+            ScanError::Image(err) => write!(f, "failed to decode image: {}", err),
+        }
+    }
+}
+
+impl Error for ScanError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            #[cfg(feature = "image")]
+            ScanError::Image(err) => Some(err),
+            _ => None,
+        }
+    }
 }
@@ -0,0 +1,185 @@
+use std::io::{self, BufRead, Write};
+
+use serde_json::{json, Value};
+
+use crate::topcode::TopCode;
+
+/// The detections of one frame of a scanning session, as saved by [record] and read back by
+/// [replay], e.g. to reproduce an issue seen in the field without the camera.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ScanEvent {
+    /// Index of the frame in the session, counting from 0
+    pub frame_index: usize,
+    /// Time at which the frame was captured, in whatever unit the session uses
+    pub timestamp: Option<f64>,
+    /// Codes found in the frame
+    pub codes: Vec<TopCode>,
+}
+
+/// Saves the events of a session as newline-delimited JSON, one object per event, in the format of
+/// [JsonLinesWriter](crate::JsonLinesWriter) extended with the `center_intensity`,
+/// `orientation_consistent`, `ring_uniformity`, `scanline` and family `width` of every code, so
+/// that [replay] returns the events as they were recorded. Only the samples a code keeps of its
+/// last sector are lost.
+pub fn record(events: &[ScanEvent], mut writer: impl Write) -> io::Result<()> {
+    for event in events {
+        let codes: Vec<Value> = event
+            .codes
+            .iter()
+            .map(|code| {
+                json!({
+                    "code": code.code,
+                    "x": code.x,
+                    "y": code.y,
+                    "unit": code.unit,
+                    "orientation": code.orientation,
+                    "confidence": code.confidence,
+                    "center_intensity": code.center_intensity,
+                    "orientation_consistent": code.orientation_consistent,
+                    "ring_uniformity": code.ring_uniformity,
+                    "scanline": code.scanline,
+                    "width": code.width,
+                })
+            })
+            .collect();
+        let line = json!({
+            "frame": event.frame_index,
+            "timestamp": event.timestamp,
+            "codes": codes,
+        });
+
+        serde_json::to_writer(&mut writer, &line)?;
+        writer.write_all(b"\n")?;
+    }
+    writer.flush()
+}
+
+/// Reads back the events saved by [record], in order, one per line. Sessions written by
+/// [JsonLinesWriter](crate::JsonLinesWriter) can be replayed as well, with the fields it does not
+/// write left at their defaults. Empty lines are skipped, and a line that is not a valid event
+/// fails with [io::ErrorKind::InvalidData].
+pub fn replay(reader: impl BufRead) -> impl Iterator<Item = io::Result<ScanEvent>> {
+    reader
+        .lines()
+        .filter(|line| !matches!(line, Ok(line) if line.trim().is_empty()))
+        .map(|line| {
+            let value: Value = serde_json::from_str(&line?)?;
+            parse_event(&value)
+                .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "Malformed scan event"))
+        })
+}
+
+fn parse_event(value: &Value) -> Option<ScanEvent> {
+    let timestamp = match &value["timestamp"] {
+        Value::Null => None,
+        timestamp => Some(timestamp.as_f64()?),
+    };
+    let codes = value["codes"]
+        .as_array()?
+        .iter()
+        .map(parse_code)
+        .collect::<Option<_>>()?;
+
+    Some(ScanEvent {
+        frame_index: value["frame"].as_u64()? as usize,
+        timestamp,
+        codes,
+    })
+}
+
+fn parse_code(value: &Value) -> Option<TopCode> {
+    let default = TopCode::default();
+    let code = match &value["code"] {
+        Value::Null => None,
+        code => Some(code.as_u64()?.try_into().ok()?),
+    };
+    let center_intensity = match &value["center_intensity"] {
+        Value::Null => default.center_intensity,
+        intensity => intensity.as_u64()?.try_into().ok()?,
+    };
+    let orientation_consistent = match &value["orientation_consistent"] {
+        Value::Null => None,
+        consistent => Some(consistent.as_bool()?),
+    };
+    let scanline = match &value["scanline"] {
+        Value::Null => None,
+        row => Some(row.as_u64()? as usize),
+    };
+
+    Some(TopCode {
+        code,
+        x: value["x"].as_f64()?,
+        y: value["y"].as_f64()?,
+        unit: value["unit"].as_f64()?,
+        orientation: value["orientation"].as_f64()?,
+        confidence: value["confidence"].as_f64()?,
+        center_intensity,
+        orientation_consistent,
+        scanline,
+        width: value["width"]
+            .as_u64()
+            .map_or(default.width, |width| width as usize),
+        ring_uniformity: value["ring_uniformity"]
+            .as_f64()
+            .unwrap_or(default.ring_uniformity),
+        ..default
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::jsonl::JsonLinesWriter;
+
+    #[test]
+    fn recorded_sessions_replay_unchanged() {
+        let mut code = TopCode::mock(31, 5.0, 0.5, 10.25, 20.0);
+        code.center_intensity = 230;
+        code.confidence = 0.875;
+        code.orientation_consistent = Some(false);
+        code.scanline = Some(17);
+        code.width = 12;
+        let events = vec![
+            ScanEvent {
+                frame_index: 0,
+                timestamp: Some(0.25),
+                codes: vec![code, TopCode::mock(47, 4.0, -3.0, 1.0, 2.0)],
+            },
+            ScanEvent {
+                frame_index: 1,
+                timestamp: None,
+                codes: vec![],
+            },
+            ScanEvent {
+                frame_index: 2,
+                timestamp: Some(0.75),
+                codes: vec![TopCode::default()],
+            },
+        ];
+
+        let mut log = Vec::new();
+        record(&events, &mut log).unwrap();
+        let replayed: Vec<_> = replay(log.as_slice()).collect::<io::Result<_>>().unwrap();
+
+        assert_eq!(events, replayed);
+    }
+
+    #[test]
+    fn json_lines_sessions_can_be_replayed() {
+        let mut writer = JsonLinesWriter::new(Vec::new());
+        let code = TopCode::mock(55, 6.0, 1.0, 30.0, 40.0);
+        writer.write_frame(&[code], Some(1.5)).unwrap();
+        let mut log = writer.into_inner();
+        log.extend_from_slice(b"\n{\"frame\": 1}\n");
+
+        let mut events = replay(log.as_slice());
+
+        let event = events.next().unwrap().unwrap();
+        assert_eq!((0, Some(1.5)), (event.frame_index, event.timestamp));
+        assert_eq!(Some(55), event.codes[0].code);
+        assert_eq!((30.0, 40.0), (event.codes[0].x, event.codes[0].y));
+        let error = events.next().unwrap().unwrap_err();
+        assert_eq!(io::ErrorKind::InvalidData, error.kind());
+        assert!(events.next().is_none());
+    }
+}
@@ -0,0 +1,111 @@
+use std::io::{BufRead, Seek};
+
+use image::{DynamicImage, ImageDecoder, ImageReader, ImageResult};
+
+/// Decodes an image and rotates or flips it upright according to the orientation recorded in its
+/// Exif metadata, so that codes are scanned as the photo is meant to be viewed. Phones usually
+/// store photos as the sensor captured them and only record how to turn them, so without this,
+/// codes can be found rotated by a quarter turn, and mirrored codes either fail the checksum or
+/// decode as another code.
+///
+/// All eight Exif orientations (rotations by 90, 180 and 270 degrees, horizontal and vertical
+/// flips, and the two rotations combined with a flip) are handled. The orientation is read for
+/// the formats whose decoder in the `image` crate reads Exif metadata, such as JPEG, PNG, TIFF and
+/// WebP. Images without an orientation are returned as they are stored.
+///
+/// ```no_run
+/// use image::ImageReader;
+/// use topcodes::{decode_oriented, Scanner};
+///
+/// let img = decode_oriented(ImageReader::open("photo.jpg")?)?;
+/// let (width, height) = (img.width() as usize, img.height() as usize);
+/// let buffer = img.into_rgb8().into_raw();
+/// let topcodes = Scanner::new(width, height).scan(&buffer, |buffer, index| {
+///     (
+///         buffer[index * 3] as u32,
+///         buffer[index * 3 + 1] as u32,
+///         buffer[index * 3 + 2] as u32,
+///     )
+/// });
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+pub fn decode_oriented<R: BufRead + Seek>(reader: ImageReader<R>) -> ImageResult<DynamicImage> {
+    let mut decoder = reader.with_guessed_format()?.into_decoder()?;
+    let orientation = decoder.orientation()?;
+    let mut img = DynamicImage::from_decoder(decoder)?;
+    img.apply_orientation(orientation);
+    Ok(img)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use image::{codecs::png::PngEncoder, ExtendedColorType, ImageEncoder};
+
+    use super::*;
+    use crate::{
+        scanner::Scanner,
+        test_utils::{render, rgb},
+    };
+
+    /// Encodes an RGB buffer as a PNG holding the given Exif orientation.
+    fn png_with_orientation(buffer: &[u8], width: u32, height: u32, orientation: u8) -> Vec<u8> {
+        // A little-endian TIFF header followed by a single orientation entry
+        let mut exif = vec![0x49, 0x49, 42, 0, 8, 0, 0, 0, 1, 0];
+        exif.extend_from_slice(&[0x12, 0x01, 3, 0, 1, 0, 0, 0, orientation, 0, 0, 0]);
+        exif.extend_from_slice(&[0, 0, 0, 0]);
+
+        let mut png = Vec::new();
+        let mut encoder = PngEncoder::new(&mut png);
+        encoder.set_exif_metadata(exif).unwrap();
+        encoder
+            .write_image(buffer, width, height, ExtendedColorType::Rgb8)
+            .unwrap();
+        png
+    }
+
+    fn scan(img: DynamicImage) -> Vec<(Option<u32>, f64, f64)> {
+        let (width, height) = (img.width() as usize, img.height() as usize);
+        let buffer = img.into_rgb8().into_raw();
+        let mut scanner = Scanner::new(width, height);
+        scanner
+            .scan(buffer.as_slice(), rgb)
+            .iter()
+            .map(|topcode| (topcode.code, topcode.x.round(), topcode.y.round()))
+            .collect()
+    }
+
+    #[test]
+    fn mirrored_photos_are_flipped_back() {
+        let (width, height) = (200, 100);
+        let buffer = render(width, height, &[(55, 60.0, 50.0, 5.0, 0.4)]);
+        let mut mirrored = buffer.clone();
+        for (row, source) in mirrored.chunks_mut(width * 3).zip(buffer.chunks(width * 3)) {
+            for (pixel, source) in row.chunks_mut(3).zip(source.chunks(3).rev()) {
+                pixel.copy_from_slice(source);
+            }
+        }
+        let png = png_with_orientation(&mirrored, width as u32, height as u32, 2);
+
+        // Read as stored, the mirrored code decodes as another code
+        let stored = image::load_from_memory(&png).unwrap();
+        assert_eq!(vec![(Some(59), 139.0, 50.0)], scan(stored));
+
+        let img = decode_oriented(ImageReader::new(Cursor::new(&png))).unwrap();
+        assert_eq!(vec![(Some(55), 60.0, 50.0)], scan(img));
+    }
+
+    #[test]
+    fn rotated_photos_are_turned_upright() {
+        let (width, height) = (200, 100);
+        let buffer = render(width, height, &[(31, 60.0, 50.0, 5.0, 0.4)]);
+        let png = png_with_orientation(&buffer, width as u32, height as u32, 6);
+
+        let img = decode_oriented(ImageReader::new(Cursor::new(&png))).unwrap();
+
+        // Turned a quarter clockwise, the code moves from the left to the top
+        assert_eq!((100, 200), (img.width(), img.height()));
+        assert_eq!(vec![(Some(31), 49.0, 60.0)], scan(img));
+    }
+}
@@ -0,0 +1,193 @@
+use std::f64::consts::PI;
+
+use crate::topcode::{Code, TopCode, SECTORS, WIDTH};
+
+/// Largest supported width of a marker in units (ring widths).
+pub(crate) const MAX_WIDTH: usize = 16;
+
+/// Describes the layout of a family of circular markers that share the TopCode bullseye: a white
+/// center surrounded by alternating black and white rings, with a single data ring split into
+/// equal sectors.
+///
+/// Each sector is read by taking `width` samples across the diameter of the marker, one in the
+/// middle of each ring. The samples between the center and the data ring must alternate between
+/// white and black, starting with white in the center. Samples further out than the data ring are
+/// ignored.
+#[derive(Clone, Copy, Debug)]
+pub struct MarkerFamily {
+    /// Number of sectors in the data ring. At most 32.
+    pub sectors: usize,
+    /// Width of the marker in units (ring widths). At most 16.
+    pub width: usize,
+    /// Index of the data ring sample among the `width` samples taken across the diameter. It must
+    /// be in the outer half, and the sample mirrored across the center reads the opposite side of
+    /// the data ring.
+    pub data_ring: usize,
+    /// Returns true if a code, as mapped by `bit_mapping`, is valid.
+    pub checksum: fn(Code) -> bool,
+    /// Maps the bits read from the data ring to a code, e.g. [MarkerFamily::from_gray] for rings
+    /// printed as Gray codes. The rotation of the marker is not known when its ring is read, so
+    /// every rotation of the bits is mapped and checked, and the lowest valid code is reported.
+    /// Defaults to the identity.
+    pub bit_mapping: fn(Code) -> Code,
+}
+
+impl MarkerFamily {
+    /// The standard TopCode: 13 sectors, 8 units wide, data ring on the outer edge and a
+    /// checksum requiring exactly five 1 bits.
+    pub const TOPCODE: MarkerFamily = MarkerFamily {
+        sectors: SECTORS,
+        width: WIDTH,
+        data_ring: WIDTH - 1,
+        checksum: TopCode::checksum,
+        bit_mapping: identity,
+    };
+
+    /// A family laid out like the standard TopCode, but with a different number of sectors in the
+    /// data ring and its own checksum, e.g. a 15-sector variant with more valid codes.
+    pub const fn with_sectors(sectors: usize, checksum: fn(Code) -> bool) -> MarkerFamily {
+        MarkerFamily {
+            sectors,
+            checksum,
+            ..MarkerFamily::TOPCODE
+        }
+    }
+
+    /// This family with its data ring bits mapped to codes by `mapping`, e.g. a permutation of the
+    /// sectors or [MarkerFamily::from_gray]. The checksum then applies to the mapped codes.
+    /// Markers printed for the standard mapping report other codes, or none, so markers must be
+    /// printed with the inverse of the mapping, and codes scanned with and without it are not
+    /// interchangeable.
+    pub const fn with_bit_mapping(self, mapping: fn(Code) -> Code) -> MarkerFamily {
+        MarkerFamily {
+            bit_mapping: mapping,
+            ..self
+        }
+    }
+
+    /// Converts the Gray code of a number back to the number, i.e. reverses `n ^ (n >> 1)`.
+    /// Neighbouring numbers differ by a single bit in their Gray codes.
+    pub fn from_gray(mut bits: Code) -> Code {
+        let mut shift = 1;
+        while shift < Code::BITS {
+            bits ^= bits >> shift;
+            shift <<= 1;
+        }
+        bits
+    }
+
+    /// Returns true if the layout can be decoded.
+    pub fn is_valid(&self) -> bool {
+        self.sectors > 0
+            && self.sectors <= Code::BITS as usize
+            && self.width <= MAX_WIDTH
+            && self.data_ring < self.width
+            && 2 * self.data_ring > self.width
+    }
+
+    /// Span of a data sector in radians.
+    pub(crate) fn arc(&self) -> f64 {
+        2.0 * PI / (self.sectors as f64)
+    }
+
+    /// Maps every rotation of the bits read from the data ring, starting at any sector, and
+    /// returns the lowest of the mapped values that pass the checksum together with the number of
+    /// sectors the bits were rotated left by to reach it, or `None` if no rotation is valid.
+    pub(crate) fn lowest_code(&self, bits: Code) -> Option<(Code, usize)> {
+        let mask = self.mask();
+        let mut rotated = bits;
+        let mut lowest: Option<(Code, usize)> = None;
+        for rotation in 0..self.sectors {
+            let code = (self.bit_mapping)(rotated);
+            if (self.checksum)(code) && lowest.is_none_or(|(min, _)| code < min) {
+                lowest = Some((code, rotation));
+            }
+            rotated = ((rotated << 1) & mask) | (rotated >> (self.sectors - 1));
+        }
+        lowest
+    }
+
+    /// Returns true if some rotation of the bits read from the data ring is a valid code.
+    pub(crate) fn is_code(&self, bits: Code) -> bool {
+        let mask = self.mask();
+        let mut rotated = bits;
+        (0..self.sectors).any(|_| {
+            let valid = (self.checksum)((self.bit_mapping)(rotated));
+            rotated = ((rotated << 1) & mask) | (rotated >> (self.sectors - 1));
+            valid
+        })
+    }
+
+    /// Mask covering the bits of every sector.
+    pub(crate) fn mask(&self) -> Code {
+        Code::MAX >> (Code::BITS as usize - self.sectors)
+    }
+
+    /// Number of rings between a sample and the center of the marker, where 0 is the center.
+    pub(crate) fn ring(&self, sample: usize) -> usize {
+        (2 * sample).abs_diff(self.width - 1) / 2
+    }
+
+    /// Highest score [TopCode::read_code] can return for this family. Each sector scores up to 255
+    /// for each ring sample inside the data ring and for the data ring sample, and up to 510 for
+    /// the opposite data ring sample.
+    pub(crate) fn max_confidence(&self) -> f64 {
+        let rings = (0..self.width)
+            .filter(|&i| self.ring(i) < self.ring(self.data_ring))
+            .count();
+        (self.sectors * (rings + 3) * 0xff) as f64
+    }
+}
+
+fn identity(bits: Code) -> Code {
+    bits
+}
+
+impl Default for MarkerFamily {
+    fn default() -> Self {
+        Self::TOPCODE
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn topcode_family_matches_the_constants() {
+        let family = MarkerFamily::TOPCODE;
+        assert!(family.is_valid());
+        assert_eq!(0x1fff, family.mask());
+        assert_eq!(
+            [3, 2, 1, 0, 0, 1, 2, 3],
+            [0, 1, 2, 3, 4, 5, 6, 7].map(|i| family.ring(i))
+        );
+        assert_eq!((SECTORS * 9 * 0xff) as f64, family.max_confidence());
+    }
+
+    #[test]
+    fn families_can_have_more_sectors() {
+        let family = MarkerFamily::with_sectors(15, |bits| bits.count_ones() == 6);
+        assert!(family.is_valid());
+        assert_eq!(0x7fff, family.mask());
+        assert_eq!(WIDTH, family.width);
+        assert!((family.arc() - 2.0 * PI / 15.0).abs() < 1e-12);
+    }
+
+    #[test]
+    fn gray_codes_are_decoded() {
+        for n in [0, 1, 2, 31, 0x1000, 0x1fff, Code::MAX] {
+            assert_eq!(n, MarkerFamily::from_gray(n ^ (n >> 1)));
+        }
+        assert_eq!(0b10, MarkerFamily::from_gray(0b11));
+    }
+
+    #[test]
+    fn data_ring_must_be_in_the_outer_half() {
+        let family = MarkerFamily {
+            data_ring: 3,
+            ..MarkerFamily::TOPCODE
+        };
+        assert!(!family.is_valid());
+    }
+}
@@ -0,0 +1,159 @@
+//! C ABI for scanning from languages other than Rust.
+//!
+//! A scanner is created with [scanner_new_ffi], used with [scan_into_ffi] for any number of frames
+//! of its dimensions, and released with [scanner_free_ffi]. The results are written into an array
+//! owned by the caller, so no memory allocated by Rust crosses the boundary other than the scanner
+//! itself. To link from C, build the crate as a `cdylib` or `staticlib` with the `ffi` feature,
+//! e.g. `cargo rustc --release --features ffi --crate-type cdylib`.
+
+use std::slice;
+
+use crate::{scanner::Scanner, topcode::TopCode};
+
+/// A TopCode laid out for C, matching:
+///
+/// ```c
+/// typedef struct {
+///     int32_t code;
+///     double x;
+///     double y;
+///     double unit;
+///     double orientation;
+/// } CTopCode;
+/// ```
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct CTopCode {
+    /// The decoded code, or -1 if the code could not be decoded
+    pub code: i32,
+    pub x: f64,
+    pub y: f64,
+    pub unit: f64,
+    pub orientation: f64,
+}
+
+impl From<&TopCode> for CTopCode {
+    fn from(topcode: &TopCode) -> Self {
+        Self {
+            code: topcode
+                .code
+                .and_then(|code| i32::try_from(code).ok())
+                .unwrap_or(-1),
+            x: topcode.x,
+            y: topcode.y,
+            unit: topcode.unit,
+            orientation: topcode.orientation,
+        }
+    }
+}
+
+/// Creates a scanner for RGB images of the given dimensions. The scanner must be released with
+/// [scanner_free_ffi].
+#[no_mangle]
+pub extern "C" fn scanner_new_ffi(width: usize, height: usize) -> *mut Scanner {
+    Box::into_raw(Box::new(Scanner::new(width, height)))
+}
+
+/// Releases a scanner created with [scanner_new_ffi]. Passing a null pointer does nothing.
+///
+/// # Safety
+///
+/// `scanner` must be null or a pointer returned by [scanner_new_ffi] that was not released yet.
+#[no_mangle]
+pub unsafe extern "C" fn scanner_free_ffi(scanner: *mut Scanner) {
+    if !scanner.is_null() {
+        drop(Box::from_raw(scanner));
+    }
+}
+
+/// Scans an image and writes the codes found into `out`, in the order [Scanner::scan] returns
+/// them. At most `cap` codes are written, and the number of codes found is returned, so a return
+/// value above `cap` means the array was too small and the remaining codes were dropped. Returns
+/// 0 without scanning if `scanner` or `buffer` is null.
+///
+/// # Safety
+///
+/// - `scanner` must be a pointer returned by [scanner_new_ffi] that was not released yet, and must
+///   not be used by another thread during the call.
+/// - `buffer` must point to `width * height * 3` readable bytes, holding the image as packed RGB
+///   rows, where `width` and `height` are the dimensions the scanner was created with.
+/// - `out` must point to `cap` writable `CTopCode`s, or may be null if `cap` is 0.
+#[no_mangle]
+pub unsafe extern "C" fn scan_into_ffi(
+    scanner: *mut Scanner,
+    buffer: *const u8,
+    out: *mut CTopCode,
+    cap: usize,
+) -> usize {
+    let Some(scanner) = scanner.as_mut() else {
+        return 0;
+    };
+    if buffer.is_null() {
+        return 0;
+    }
+    let (width, height) = scanner.dimensions();
+    let buffer = slice::from_raw_parts(buffer, width * height * 3);
+    let topcodes = scanner.scan(buffer, |buffer, index| {
+        (
+            buffer[index * 3] as u32,
+            buffer[index * 3 + 1] as u32,
+            buffer[index * 3 + 2] as u32,
+        )
+    });
+
+    if cap > 0 && !out.is_null() {
+        let out = slice::from_raw_parts_mut(out, cap);
+        for (slot, topcode) in out.iter_mut().zip(&topcodes) {
+            *slot = CTopCode::from(topcode);
+        }
+    }
+    topcodes.len()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ptr;
+
+    use super::*;
+    use crate::test_utils::render;
+
+    #[test]
+    fn writes_codes_into_the_callers_array() {
+        let buffer = render(
+            200,
+            100,
+            &[(31, 50.0, 50.0, 5.0, 0.0), (93, 150.0, 50.0, 4.0, 1.0)],
+        );
+        let scanner = scanner_new_ffi(200, 100);
+        let mut out = [CTopCode::default(); 4];
+
+        let found = unsafe { scan_into_ffi(scanner, buffer.as_ptr(), out.as_mut_ptr(), out.len()) };
+
+        assert_eq!(2, found);
+        let mut codes = [out[0].code, out[1].code];
+        codes.sort();
+        assert_eq!([31, 93], codes);
+        assert_eq!(CTopCode::default(), out[2]);
+
+        // Too small an array keeps the first codes and still reports every code found
+        let mut small = [CTopCode::default(); 1];
+        let found = unsafe { scan_into_ffi(scanner, buffer.as_ptr(), small.as_mut_ptr(), 1) };
+        assert_eq!(2, found);
+        assert_eq!(out[0], small[0]);
+        assert_eq!(2, unsafe {
+            scan_into_ffi(scanner, buffer.as_ptr(), ptr::null_mut(), 0)
+        });
+
+        unsafe { scanner_free_ffi(scanner) };
+    }
+
+    #[test]
+    fn invalid_codes_are_negative() {
+        let topcode = TopCode::default();
+
+        assert_eq!(-1, CTopCode::from(&topcode).code);
+        assert_eq!(0, unsafe {
+            scan_into_ffi(ptr::null_mut(), ptr::null(), ptr::null_mut(), 0)
+        });
+    }
+}
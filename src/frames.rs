@@ -0,0 +1,70 @@
+use image::{AnimationDecoder, ImageResult};
+
+use crate::{scanner::Scanner, topcode::TopCode};
+
+/// Scans every frame of a multi-frame image (such as an animated GIF, APNG or WebP) and returns
+/// the TopCodes found in each frame, in order.
+///
+/// A single scanner is reused while consecutive frames have the same dimensions. Frames are
+/// usually composited onto the full canvas by the decoder, but if the size of a frame does differ
+/// from the previous one, a new scanner (and buffer) is allocated for it.
+pub fn scan_frames<'a>(decoder: impl AnimationDecoder<'a>) -> ImageResult<Vec<Vec<TopCode>>> {
+    let mut scanner: Option<Scanner> = None;
+    let mut topcodes = Vec::new();
+
+    for frame in decoder.into_frames() {
+        let buffer = frame?.into_buffer();
+        let (width, height) = (buffer.width() as usize, buffer.height() as usize);
+
+        if !matches!(&scanner, Some(s) if s.image_width() == width && s.image_height() == height) {
+            scanner = None;
+        }
+        let scanner = scanner.get_or_insert_with(|| Scanner::new(width, height));
+
+        topcodes.push(scanner.scan(buffer.as_raw(), |buffer, index| {
+            (
+                buffer[index * 4] as u32,
+                buffer[index * 4 + 1] as u32,
+                buffer[index * 4 + 2] as u32,
+            )
+        }));
+    }
+
+    Ok(topcodes)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use image::{
+        codecs::gif::{GifDecoder, GifEncoder},
+        Frame, RgbImage,
+    };
+
+    use super::*;
+    use crate::test_utils::render;
+
+    fn frame(width: usize, height: usize, code: u32) -> Frame {
+        let (x, y) = (width as f64 / 2.0, height as f64 / 2.0);
+        let buffer = render(width, height, &[(code, x, y, 6.0, 0.5)]);
+        let img = RgbImage::from_raw(width as u32, height as u32, buffer).unwrap();
+        Frame::new(image::DynamicImage::ImageRgb8(img).into_rgba8())
+    }
+
+    #[test]
+    fn it_scans_each_frame_of_a_gif() {
+        let mut gif = Vec::new();
+        GifEncoder::new(&mut gif)
+            .encode_frames([frame(80, 80, 31), frame(80, 80, 55), frame(80, 80, 93)])
+            .unwrap();
+
+        let topcodes = scan_frames(GifDecoder::new(Cursor::new(gif)).unwrap()).unwrap();
+        let codes: Vec<_> = topcodes
+            .iter()
+            .map(|frame| frame.iter().map(|topcode| topcode.code).collect::<Vec<_>>())
+            .collect();
+
+        assert_eq!(vec![vec![Some(31)], vec![Some(55)], vec![Some(93)]], codes);
+    }
+}
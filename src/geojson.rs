@@ -0,0 +1,56 @@
+use serde_json::{json, Value};
+
+use crate::topcode::TopCode;
+
+/// Exports codes as a GeoJSON-like feature collection for web visualization libraries. Each code
+/// is a point feature at its center, in image coordinates, with `code`, `orientation` and
+/// `diameter` properties. The code of an invalid TopCode is `null`.
+pub fn to_feature_collection(codes: &[TopCode]) -> Value {
+    let features: Vec<Value> = codes
+        .iter()
+        .map(|code| {
+            json!({
+                "type": "Feature",
+                "geometry": {
+                    "type": "Point",
+                    "coordinates": [code.x, code.y],
+                },
+                "properties": {
+                    "code": code.code,
+                    "orientation": code.orientation,
+                    "diameter": 2.0 * code.radius(),
+                },
+            })
+        })
+        .collect();
+
+    json!({
+        "type": "FeatureCollection",
+        "features": features,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn codes_are_exported_as_point_features() {
+        let codes = [TopCode::mock(31, 5.0, 0.5, 10.0, 20.0), TopCode::default()];
+        let collection = to_feature_collection(&codes);
+
+        // The structure survives a round trip through its text representation
+        let text = collection.to_string();
+        assert_eq!(collection, serde_json::from_str::<Value>(&text).unwrap());
+
+        assert_eq!("FeatureCollection", collection["type"]);
+        let feature = &collection["features"][0];
+        assert_eq!("Point", feature["geometry"]["type"]);
+        assert_eq!(json!([10.0, 20.0]), feature["geometry"]["coordinates"]);
+        assert_eq!(
+            json!({"code": 31, "orientation": 0.5, "diameter": 40.0}),
+            feature["properties"]
+        );
+        assert_eq!(Value::Null, collection["features"][1]["properties"]["code"]);
+    }
+}
@@ -0,0 +1,147 @@
+use crate::{scanner::Scanner, topcode::TopCode};
+
+impl Scanner {
+    /// Scans an exposure bracket of the same scene and returns the TopCodes found in any of the
+    /// frames, for scenes where some markers lie in shadow and others in bright light, so that no
+    /// single exposure sees all of them. Each frame is given with its exposure, in any unit that
+    /// grows with the exposure, such as the exposure time.
+    ///
+    /// Detections of the same code in different frames are merged like [dedupe_codes] does, keeping
+    /// the read with the highest [TopCode::confidence], and the read of the longer exposure, which
+    /// is less noisy, between reads that are equally confident. Codes are returned in the order
+    /// they were first found, scanning the frames in the order given. This takes as long as
+    /// scanning every frame with [Scanner::scan].
+    ///
+    /// The frames must be aligned, i.e. taken by a static camera of a static scene, since the
+    /// detections are matched by their position. All frames must have the dimensions of the
+    /// scanner and be decoded by `decode_rgb`.
+    #[must_use = "the codes found are only returned, not kept by the scanner"]
+    pub fn scan_hdr<T: ?Sized>(
+        &mut self,
+        frames: &[(&T, f64)],
+        decode_rgb: impl Fn(&T, usize) -> (u32, u32, u32),
+    ) -> Vec<TopCode> {
+        let mut detections = Vec::new();
+        for &(image_buffer, exposure) in frames {
+            for topcode in self.scan(image_buffer, &decode_rgb) {
+                detections.push((topcode, exposure));
+            }
+        }
+
+        merge_detections(detections)
+    }
+}
+
+/// Merges the detections of the same marker in a list of codes, e.g. gathered from several scans
+/// of the same scene, and returns one code per marker. Detections are of the same marker when
+/// they have the same code and the center of either lies within the other. Codes without a value
+/// are never merged. The detection with the highest [TopCode::confidence] is kept, and the first
+/// of those that are equally confident. Markers are returned in the order they were first
+/// detected.
+pub fn dedupe_codes(codes: &[TopCode]) -> Vec<TopCode> {
+    merge_detections(codes.iter().map(|&topcode| (topcode, 0.0)))
+}
+
+/// Keeps the most confident detection of every marker, and the one of the longest exposure between
+/// equally confident detections.
+fn merge_detections(detections: impl IntoIterator<Item = (TopCode, f64)>) -> Vec<TopCode> {
+    let mut kept: Vec<(TopCode, f64)> = Vec::new();
+    for (topcode, exposure) in detections {
+        let same = kept.iter_mut().find(|(other, _)| {
+            topcode.code.is_some()
+                && other.code == topcode.code
+                && (other.covers(topcode.x, topcode.y) || topcode.covers(other.x, other.y))
+        });
+        match same {
+            Some((other, other_exposure)) => {
+                let better = topcode
+                    .confidence
+                    .total_cmp(&other.confidence)
+                    .then(exposure.total_cmp(other_exposure))
+                    .is_gt();
+                if better {
+                    (*other, *other_exposure) = (topcode, exposure);
+                }
+            }
+            None => kept.push((topcode, exposure)),
+        }
+    }
+
+    kept.into_iter().map(|(topcode, _)| topcode).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{render, rgb};
+
+    #[test]
+    fn every_exposure_adds_the_codes_it_sees() {
+        let (width, height) = (300, 100);
+        let scene = render(
+            width,
+            height,
+            &[
+                (31, 50.0, 50.0, 5.0, 0.0),
+                (55, 150.0, 50.0, 5.0, 0.5),
+                (93, 250.0, 50.0, 4.0, 1.0),
+            ],
+        );
+        // The short exposure loses the shadow on the left to black, and the long one the glare on
+        // the right to white
+        let expose = |from: usize, to: usize, value: u8| {
+            let mut frame = scene.clone();
+            for (i, pixel) in frame.chunks_mut(3).enumerate() {
+                if (from..to).contains(&(i % width)) {
+                    pixel.fill(value);
+                }
+            }
+            frame
+        };
+        let short = expose(0, 100, 0);
+        let long = expose(200, 300, 0xff);
+        let mut scanner = Scanner::new(width, height);
+        assert_eq!(2, scanner.scan(short.as_slice(), rgb).len());
+        assert_eq!(2, scanner.scan(long.as_slice(), rgb).len());
+
+        let topcodes = scanner.scan_hdr(&[(short.as_slice(), 1.0), (long.as_slice(), 4.0)], rgb);
+
+        let mut codes: Vec<_> = topcodes.iter().map(|topcode| topcode.code).collect();
+        codes.sort();
+        assert_eq!(vec![Some(31), Some(55), Some(93)], codes);
+    }
+
+    #[test]
+    fn the_most_confident_detection_is_kept() {
+        let mut weak = TopCode::mock(31, 5.0, 0.0, 50.0, 50.0);
+        weak.confidence = 0.5;
+        let mut strong = TopCode::mock(31, 5.0, 0.0, 51.0, 50.0);
+        strong.confidence = 0.9;
+        let elsewhere = TopCode::mock(31, 5.0, 0.0, 150.0, 50.0);
+        let other = TopCode::mock(55, 5.0, 0.0, 50.0, 50.0);
+
+        let codes = dedupe_codes(&[weak, other, strong, elsewhere]);
+
+        assert_eq!(vec![strong, other, elsewhere], codes);
+
+        // Equally confident reads favour the longer exposure
+        let shifted = TopCode { x: 52.0, ..strong };
+        let merged = merge_detections([(weak, 2.0), (shifted, 1.0), (strong, 3.0)]);
+        assert_eq!(vec![strong], merged);
+        let merged = merge_detections([(strong, 3.0), (shifted, 1.0)]);
+        assert_eq!(vec![strong], merged);
+    }
+
+    #[test]
+    fn codes_without_a_value_are_not_merged() {
+        let unread = TopCode {
+            x: 50.0,
+            y: 50.0,
+            unit: 5.0,
+            ..TopCode::default()
+        };
+        let shifted = TopCode { x: 51.0, ..unread };
+
+        assert_eq!(vec![unread, shifted], dedupe_codes(&[unread, shifted]));
+    }
+}
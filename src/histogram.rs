@@ -0,0 +1,94 @@
+use std::collections::BTreeMap;
+
+use crate::topcode::{Code, TopCode};
+
+/// Counts how often each code was detected across many scans, e.g. for usage analytics in an
+/// installation or to find flaky markers that drop in and out of view. The histogram is only
+/// updated when [CodeHistogram::record] is called with the result of a scan.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct CodeHistogram {
+    counts: BTreeMap<Code, usize>,
+    scans: usize,
+}
+
+impl CodeHistogram {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Records the codes found by one scan. Codes that were not decoded are ignored.
+    pub fn record(&mut self, codes: &[TopCode]) {
+        self.scans += 1;
+        for code in codes.iter().filter_map(|topcode| topcode.code) {
+            *self.counts.entry(code).or_default() += 1;
+        }
+    }
+
+    /// Number of scans recorded so far.
+    pub fn scans(&self) -> usize {
+        self.scans
+    }
+
+    /// Number of times the code was detected.
+    pub fn count(&self, code: Code) -> usize {
+        self.counts.get(&code).copied().unwrap_or(0)
+    }
+
+    /// Returns every code detected at least once with the number of times it was detected, in
+    /// ascending order of code.
+    pub fn counts(&self) -> impl Iterator<Item = (Code, usize)> + '_ {
+        self.counts.iter().map(|(&code, &count)| (code, count))
+    }
+
+    /// Returns the most frequently detected code and its count, preferring the lowest code on a
+    /// tie, or `None` if no code was detected.
+    pub fn most_common(&self) -> Option<(Code, usize)> {
+        self.counts()
+            .reduce(|best, entry| if entry.1 > best.1 { entry } else { best })
+    }
+
+    /// Forgets every recorded scan.
+    pub fn clear(&mut self) {
+        self.counts.clear();
+        self.scans = 0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn counts_codes_across_scans() {
+        let mut histogram = CodeHistogram::new();
+        histogram.record(&[
+            TopCode::mock(31, 5.0, 0.0, 10.0, 10.0),
+            TopCode::mock(55, 5.0, 0.0, 50.0, 10.0),
+        ]);
+        histogram.record(&[TopCode::mock(55, 5.0, 0.0, 50.0, 12.0), TopCode::default()]);
+        histogram.record(&[]);
+
+        assert_eq!(3, histogram.scans());
+        assert_eq!(
+            vec![(31, 1), (55, 2)],
+            histogram.counts().collect::<Vec<_>>()
+        );
+        assert_eq!(Some((55, 2)), histogram.most_common());
+        assert_eq!(0, histogram.count(93));
+
+        histogram.clear();
+        assert_eq!(CodeHistogram::new(), histogram);
+    }
+
+    #[test]
+    fn ties_prefer_the_lowest_code() {
+        let mut histogram = CodeHistogram::new();
+        assert_eq!(None, histogram.most_common());
+
+        histogram.record(&[
+            TopCode::mock(55, 5.0, 0.0, 50.0, 10.0),
+            TopCode::mock(31, 5.0, 0.0, 10.0, 10.0),
+        ]);
+        assert_eq!(Some((31, 1)), histogram.most_common());
+    }
+}
@@ -0,0 +1,202 @@
+use crate::topcode::{Code, TopCode};
+
+/// Pivots smaller than this are treated as zero when solving for the homography.
+const SINGULAR_PIVOT: f64 = 1e-12;
+
+/// Computes the homography that maps image coordinates to world coordinates from markers at known
+/// positions, e.g. to calibrate a tabletop or a projection surface. `correspondences` gives the
+/// world position of each reference code, and the first detection of each code in `detected` is
+/// used as its image position.
+///
+/// The homography is returned as a row-major 3x3 matrix `h`, normalized so that `h[8]` is 1. An
+/// image point `(x, y)` maps to `(X / W, Y / W)` in the world, where `[X, Y, W]` is `h` applied to
+/// `[x, y, 1]`. It is solved with the direct linear transform on coordinates normalized around
+/// their centroid, in the least squares sense when more than four markers are found.
+///
+/// Returns `None` if fewer than four of the codes were detected, or if their positions do not
+/// determine a homography, e.g. because three of them lie on a line.
+pub fn compute_homography(
+    correspondences: &[(Code, (f64, f64))],
+    detected: &[TopCode],
+) -> Option<[f64; 9]> {
+    let pairs: Vec<((f64, f64), (f64, f64))> = correspondences
+        .iter()
+        .filter_map(|&(code, world)| {
+            detected
+                .iter()
+                .find(|topcode| topcode.code == Some(code))
+                .map(|topcode| ((topcode.x, topcode.y), world))
+        })
+        .collect();
+    if pairs.len() < 4 {
+        return None;
+    }
+
+    let image = Normalization::new(pairs.iter().map(|&(image, _)| image));
+    let world = Normalization::new(pairs.iter().map(|&(_, world)| world));
+
+    // Normal equations of the DLT with the last entry fixed to 1
+    let mut ata = [[0.0; 8]; 8];
+    let mut atb = [0.0; 8];
+    for &(p, q) in &pairs {
+        let (x, y) = image.apply(p);
+        let (u, v) = world.apply(q);
+        let rows = [
+            ([x, y, 1.0, 0.0, 0.0, 0.0, -u * x, -u * y], u),
+            ([0.0, 0.0, 0.0, x, y, 1.0, -v * x, -v * y], v),
+        ];
+        for (row, b) in rows {
+            for i in 0..8 {
+                for j in 0..8 {
+                    ata[i][j] += row[i] * row[j];
+                }
+                atb[i] += row[i] * b;
+            }
+        }
+    }
+    let h = solve(ata, atb)?;
+    let normalized = [h[0], h[1], h[2], h[3], h[4], h[5], h[6], h[7], 1.0];
+
+    // Undo the normalization: H = T_world^-1 * H' * T_image
+    let h = multiply(world.inverse(), multiply(normalized, image.matrix()));
+    if h[8].abs() < SINGULAR_PIVOT {
+        return None;
+    }
+    Some(h.map(|value| value / h[8]))
+}
+
+/// Similarity transform that moves points to their centroid and scales them to an average
+/// distance of √2 from it, which keeps the DLT well conditioned.
+struct Normalization {
+    cx: f64,
+    cy: f64,
+    scale: f64,
+}
+
+impl Normalization {
+    fn new(points: impl Iterator<Item = (f64, f64)> + Clone) -> Self {
+        let n = points.clone().count() as f64;
+        let (sx, sy) = points
+            .clone()
+            .fold((0.0, 0.0), |(sx, sy), (x, y)| (sx + x, sy + y));
+        let (cx, cy) = (sx / n, sy / n);
+        let mean = points.map(|(x, y)| (x - cx).hypot(y - cy)).sum::<f64>() / n;
+        let scale = if mean > 0.0 {
+            std::f64::consts::SQRT_2 / mean
+        } else {
+            1.0
+        };
+        Self { cx, cy, scale }
+    }
+
+    fn apply(&self, (x, y): (f64, f64)) -> (f64, f64) {
+        ((x - self.cx) * self.scale, (y - self.cy) * self.scale)
+    }
+
+    fn matrix(&self) -> [f64; 9] {
+        let s = self.scale;
+        [s, 0.0, -s * self.cx, 0.0, s, -s * self.cy, 0.0, 0.0, 1.0]
+    }
+
+    fn inverse(&self) -> [f64; 9] {
+        let s = 1.0 / self.scale;
+        [s, 0.0, self.cx, 0.0, s, self.cy, 0.0, 0.0, 1.0]
+    }
+}
+
+/// Multiplies two row-major 3x3 matrices.
+fn multiply(a: [f64; 9], b: [f64; 9]) -> [f64; 9] {
+    let mut product = [0.0; 9];
+    for row in 0..3 {
+        for col in 0..3 {
+            product[row * 3 + col] = (0..3).map(|k| a[row * 3 + k] * b[k * 3 + col]).sum();
+        }
+    }
+    product
+}
+
+/// Solves `a * x = b` by Gaussian elimination with partial pivoting, or returns `None` if `a` is
+/// singular.
+fn solve(mut a: [[f64; 8]; 8], mut b: [f64; 8]) -> Option<[f64; 8]> {
+    for col in 0..8 {
+        let pivot = (col..8).max_by(|&i, &j| a[i][col].abs().total_cmp(&a[j][col].abs()))?;
+        if a[pivot][col].abs() < SINGULAR_PIVOT {
+            return None;
+        }
+        a.swap(col, pivot);
+        b.swap(col, pivot);
+
+        let pivot_row = a[col];
+        for row in col + 1..8 {
+            let factor = a[row][col] / pivot_row[col];
+            for (value, above) in a[row].iter_mut().zip(pivot_row).skip(col) {
+                *value -= factor * above;
+            }
+            b[row] -= factor * b[col];
+        }
+    }
+
+    let mut x = [0.0; 8];
+    for row in (0..8).rev() {
+        let sum: f64 = (row + 1..8).map(|k| a[row][k] * x[k]).sum();
+        x[row] = (b[row] - sum) / a[row][row];
+    }
+    Some(x)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn project(h: &[f64; 9], (x, y): (f64, f64)) -> (f64, f64) {
+        let w = h[6] * x + h[7] * y + h[8];
+        (
+            (h[0] * x + h[1] * y + h[2]) / w,
+            (h[3] * x + h[4] * y + h[5]) / w,
+        )
+    }
+
+    #[test]
+    fn recovers_the_homography_of_a_planar_layout() {
+        // A table seen at an angle, measured in centimeters
+        let truth = [0.12, 0.03, -20.0, -0.01, 0.15, -8.0, 0.0002, 0.0004, 1.0];
+        let image = [
+            (120.0, 90.0),
+            (510.0, 110.0),
+            (530.0, 400.0),
+            (90.0, 380.0),
+            (300.0, 250.0),
+        ];
+        let codes = [31, 47, 55, 59, 61];
+        let detected: Vec<_> = codes
+            .iter()
+            .zip(image)
+            .map(|(&code, (x, y))| TopCode::mock(code, 5.0, 0.0, x, y))
+            .collect();
+        let correspondences: Vec<_> = codes
+            .iter()
+            .zip(image)
+            .map(|(&code, point)| (code, project(&truth, point)))
+            .collect();
+
+        let h = compute_homography(&correspondences, &detected).unwrap();
+
+        for (value, expected) in h.iter().zip(truth) {
+            assert!((value - expected).abs() < 1e-9);
+        }
+        let (x, y) = project(&h, (200.0, 300.0));
+        let (ex, ey) = project(&truth, (200.0, 300.0));
+        assert!((x - ex).abs() < 1e-6 && (y - ey).abs() < 1e-6);
+    }
+
+    #[test]
+    fn needs_four_markers_off_a_line() {
+        let detected: Vec<_> = (0..4)
+            .map(|i| TopCode::mock(31 + i, 5.0, 0.0, 100.0 * i as f64, 50.0 * i as f64))
+            .collect();
+        let world: Vec<_> = (0..4).map(|i| (31 + i, (i as f64, 0.0))).collect();
+
+        assert_eq!(None, compute_homography(&world[..3], &detected));
+        assert_eq!(None, compute_homography(&world, &detected));
+    }
+}
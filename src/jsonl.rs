@@ -0,0 +1,121 @@
+use std::io::{self, Write};
+
+use serde_json::{json, Value};
+
+use crate::topcode::TopCode;
+
+/// Records the detections of a scanning session as newline-delimited JSON, one object per frame,
+/// e.g. to analyze a session later. Each line has a `frame` index counting from 0, a `timestamp`
+/// (`null` unless given) and the `codes` of the frame, each with its `code`, `x`, `y`, `unit`,
+/// `orientation` and `confidence`.
+///
+/// Writes are not buffered by the writer itself, so wrap files in a [std::io::BufWriter] and call
+/// [JsonLinesWriter::flush] before the session ends.
+pub struct JsonLinesWriter<W: Write> {
+    writer: W,
+    frame: usize,
+}
+
+impl<W: Write> JsonLinesWriter<W> {
+    pub fn new(writer: W) -> Self {
+        Self { writer, frame: 0 }
+    }
+
+    /// Appends the codes of the next frame, with an optional timestamp in whatever unit the
+    /// session uses. The frame index advances even if writing fails.
+    pub fn write_frame(&mut self, codes: &[TopCode], timestamp: Option<f64>) -> io::Result<()> {
+        let codes: Vec<Value> = codes
+            .iter()
+            .map(|code| {
+                json!({
+                    "code": code.code,
+                    "x": code.x,
+                    "y": code.y,
+                    "unit": code.unit,
+                    "orientation": code.orientation,
+                    "confidence": code.confidence,
+                })
+            })
+            .collect();
+        let line = json!({
+            "frame": self.frame,
+            "timestamp": timestamp,
+            "codes": codes,
+        });
+        self.frame += 1;
+
+        serde_json::to_writer(&mut self.writer, &line)?;
+        self.writer.write_all(b"\n")
+    }
+
+    /// Number of frames written so far, which is also the index of the next frame.
+    pub fn frames(&self) -> usize {
+        self.frame
+    }
+
+    /// Flushes the underlying writer.
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+
+    /// Returns the underlying writer, without flushing it.
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn frames_are_written_one_per_line() {
+        let mut writer = JsonLinesWriter::new(Vec::new());
+        writer
+            .write_frame(&[TopCode::mock(31, 5.0, 0.5, 10.0, 20.0)], Some(0.25))
+            .unwrap();
+        writer.write_frame(&[], None).unwrap();
+        writer
+            .write_frame(
+                &[TopCode::mock(47, 4.0, 0.0, 1.0, 2.0), TopCode::default()],
+                None,
+            )
+            .unwrap();
+        assert_eq!(3, writer.frames());
+
+        let text = String::from_utf8(writer.into_inner()).unwrap();
+        let frames: Vec<Value> = text
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+
+        assert_eq!(3, frames.len());
+        assert_eq!(json!(0), frames[0]["frame"]);
+        assert_eq!(json!(0.25), frames[0]["timestamp"]);
+        assert_eq!(json!(31), frames[0]["codes"][0]["code"]);
+        assert_eq!(json!(10.0), frames[0]["codes"][0]["x"]);
+        assert_eq!(json!([]), frames[1]["codes"]);
+        assert_eq!(Value::Null, frames[1]["timestamp"]);
+        assert_eq!(json!(2), frames[2]["frame"]);
+        assert_eq!(Value::Null, frames[2]["codes"][1]["code"]);
+    }
+
+    #[test]
+    fn write_errors_are_propagated() {
+        struct Full;
+
+        impl Write for Full {
+            fn write(&mut self, _: &[u8]) -> io::Result<usize> {
+                Err(io::Error::new(io::ErrorKind::StorageFull, "disk full"))
+            }
+
+            fn flush(&mut self) -> io::Result<()> {
+                Ok(())
+            }
+        }
+
+        let mut writer = JsonLinesWriter::new(Full);
+        let error = writer.write_frame(&[], None).unwrap_err();
+        assert_eq!(io::ErrorKind::StorageFull, error.kind());
+    }
+}
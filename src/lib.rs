@@ -1,7 +1,91 @@
+mod analysis;
+mod calibration;
 mod candidate;
+mod config;
+mod convert;
+#[cfg(feature = "image")]
+mod detector;
+mod encoded;
+mod errors;
+#[cfg(feature = "serde")]
+mod events;
+#[cfg(feature = "image")]
+mod exif;
+mod family;
+#[cfg(feature = "ffi")]
+mod ffi;
+#[cfg(feature = "image")]
+mod frames;
+#[cfg(feature = "serde")]
+mod geojson;
+mod hdr;
+mod histogram;
+mod homography;
+#[cfg(feature = "serde")]
+mod jsonl;
+mod math;
+mod pixel;
+mod region;
 mod scanner;
+mod sheet;
+mod small;
+mod source;
+mod stable;
+mod stats;
+#[cfg(test)]
+mod test_utils;
+mod tiles;
 mod topcode;
+mod tracker;
+mod transform;
 mod utils;
+#[cfg(feature = "visualize")]
+mod visualize;
+#[cfg(feature = "std")]
+mod worker;
 
+pub use analysis::{
+    cluster_codes, contains_code, estimate_global_rotation, find_code, primary_code,
+    primary_code_weighted, quadrant_counts, scene_scale, PrimaryCodeWeights,
+};
+pub use calibration::RingContrast;
+pub use candidate::Candidate;
+pub use config::{Channel, Distortion, OverlapStrategy, ScannerConfig};
+#[cfg(feature = "image")]
+pub use detector::Detector;
+pub use encoded::scan_bytes;
+pub use errors::ScanError;
+#[cfg(feature = "serde")]
+pub use events::{record, replay, ScanEvent};
+#[cfg(feature = "image")]
+pub use exif::decode_oriented;
+pub use family::MarkerFamily;
+#[cfg(feature = "ffi")]
+pub use ffi::{scan_into_ffi, scanner_free_ffi, scanner_new_ffi, CTopCode};
+#[cfg(feature = "image")]
+pub use frames::scan_frames;
+#[cfg(feature = "serde")]
+pub use geojson::to_feature_collection;
+pub use hdr::dedupe_codes;
+pub use histogram::CodeHistogram;
+pub use homography::compute_homography;
+#[cfg(feature = "serde")]
+pub use jsonl::JsonLinesWriter;
+pub use region::{code_at, codes_in, Circle, Polygon, Rect, Region};
 pub use scanner::Scanner;
-pub use topcode::TopCode;
+#[cfg(feature = "smallvec")]
+pub use scanner::INLINE_CODES;
+pub use sheet::{validate_sheet, SheetReport};
+#[cfg(feature = "image")]
+pub use source::DirectorySource;
+pub use source::{scan_source, FrameSource, SliceSource};
+pub use stats::ScanStats;
+pub use tiles::scan_tiles;
+pub use topcode::{Code, TopCode};
+pub use tracker::{TrackEvent, Tracker};
+pub use transform::ImageTransform;
+pub use utils::ToBitString;
+#[cfg(feature = "visualize")]
+pub use visualize::{annotate, crop_marker, rectify, AnnotationStyle};
+#[cfg(feature = "std")]
+pub use worker::{spawn_scan_thread, ScanThread};
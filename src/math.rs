@@ -0,0 +1,59 @@
+/// Sine and cosine of an angle in radians, as used to sample codes while decoding.
+///
+/// The trigonometric functions of the standard library call the platform's math library, whose
+/// results may differ in the last bits between targets, and which can tip a sample onto another
+/// pixel. With the `libm` feature, the bundled `libm` implementation is used instead, so decoding
+/// gives bit-identical results on every target.
+#[cfg(feature = "libm")]
+pub(crate) fn sin_cos(angle: f64) -> (f64, f64) {
+    (libm::sin(angle), libm::cos(angle))
+}
+
+/// Sine and cosine of an angle in radians, from the platform's math library.
+#[cfg(not(feature = "libm"))]
+pub(crate) fn sin_cos(angle: f64) -> (f64, f64) {
+    angle.sin_cos()
+}
+
+/// Length of the hypotenuse of a right triangle, as used to compare the distances between codes
+/// while scanning.
+///
+/// Like the trigonometric functions, the platform's `hypot` is not required to be correctly
+/// rounded, so a distance right at a threshold can compare differently between targets. With the
+/// `libm` feature, the bundled `libm` implementation is used instead.
+#[cfg(feature = "libm")]
+pub(crate) fn hypot(x: f64, y: f64) -> f64 {
+    libm::hypot(x, y)
+}
+
+/// Length of the hypotenuse of a right triangle, from the platform's math library.
+#[cfg(not(feature = "libm"))]
+pub(crate) fn hypot(x: f64, y: f64) -> f64 {
+    x.hypot(y)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f64::consts::PI;
+
+    use super::*;
+
+    #[test]
+    fn sin_cos_matches_the_standard_library() {
+        for i in 0..64 {
+            let angle = i as f64 * PI / 16.0 - 2.0 * PI;
+            let (sin, cos) = sin_cos(angle);
+            assert!((sin - angle.sin()).abs() < 1e-15 && (cos - angle.cos()).abs() < 1e-15);
+        }
+        assert_eq!((0.0, 1.0), sin_cos(0.0));
+    }
+
+    #[test]
+    fn hypot_matches_the_standard_library() {
+        for i in 0..64 {
+            let (x, y) = (i as f64 * 0.37 - 11.0, 7.0 - i as f64 * 0.61);
+            assert!((hypot(x, y) - x.hypot(y)).abs() < 1e-12);
+        }
+        assert_eq!(5.0, hypot(3.0, -4.0));
+    }
+}
@@ -0,0 +1,62 @@
+/// Bit holding whether a packed pixel is white
+const WHITE_BIT: u32 = 1 << 24;
+
+/// Bits holding the running sum of a packed pixel
+const SUM_MASK: u32 = WHITE_BIT - 1;
+
+/// A pixel after adaptive thresholding. It stores whether the pixel is white together with the
+/// running sum of the threshold at the pixel, which the next row and [Scanner::intensity_at] read
+/// back. Both are packed into a single u32 to keep the thresholded image small: the sum in the low
+/// 24 bits and the binary value in bit 24.
+///
+/// [Scanner::intensity_at]: crate::Scanner::intensity_at
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[repr(transparent)]
+pub(crate) struct PackedPixel(u32);
+
+impl PackedPixel {
+    /// Packs a pixel. Only the low 24 bits of the sum are kept, which is plenty for the sum of a
+    /// threshold window of 8-bit intensities.
+    pub(crate) fn new(white: bool, sum: isize) -> Self {
+        let bit = if white { WHITE_BIT } else { 0 };
+        Self(bit | (sum as u32 & SUM_MASK))
+    }
+
+    /// Whether the pixel was thresholded as white.
+    pub(crate) fn is_white(self) -> bool {
+        self.0 & WHITE_BIT != 0
+    }
+
+    /// The binary value of the pixel: 0 (black) or 1 (white).
+    pub(crate) fn bit(self) -> u32 {
+        u32::from(self.is_white())
+    }
+
+    /// The running sum of the adaptive threshold at the pixel.
+    pub(crate) fn sum(self) -> u32 {
+        self.0 & SUM_MASK
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bit_and_sum_are_independent() {
+        let white = PackedPixel::new(true, 8160);
+        assert!(white.is_white());
+        assert_eq!((1, 8160), (white.bit(), white.sum()));
+
+        let black = PackedPixel::new(false, 8160);
+        assert!(!black.is_white());
+        assert_eq!((0, 8160), (black.bit(), black.sum()));
+    }
+
+    #[test]
+    fn a_sum_that_overflows_does_not_change_the_bit() {
+        let pixel = PackedPixel::new(false, (SUM_MASK as isize) + 2);
+        assert!(!pixel.is_white());
+        assert_eq!(1, pixel.sum());
+    }
+}
@@ -0,0 +1,173 @@
+use crate::topcode::TopCode;
+
+/// An area of the image that can be tested for whether it contains a point, e.g. to hit-test a
+/// tap against the codes found, or to keep only the codes within a region of interest. Regions
+/// are in image coordinates, like the centers of the codes.
+///
+/// The shapes below, TopCodes and slices of any of them implement it, so they can be mixed: a
+/// slice contains the points contained by any of its regions. Slices also have an inherent
+/// `contains` method, so call it as `Region::contains(&codes[..], x, y)` on a slice.
+pub trait Region {
+    /// Returns true if the point (x, y) lies within the region.
+    fn contains(&self, x: f64, y: f64) -> bool;
+}
+
+/// An axis-aligned rectangle, given by its top-left corner and its size. It contains its top and
+/// left edges but not its bottom and right ones, so that rectangles tiling the image do not
+/// overlap.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Rect {
+    pub x: f64,
+    pub y: f64,
+    pub width: f64,
+    pub height: f64,
+}
+
+/// A circle, given by its center and radius. It contains its boundary.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Circle {
+    pub x: f64,
+    pub y: f64,
+    pub radius: f64,
+}
+
+/// A polygon, given by its vertices in order. The last vertex connects back to the first. Points
+/// are tested with the even-odd rule, so a self-intersecting polygon does not contain the areas it
+/// covers twice.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Polygon {
+    pub vertices: Vec<(f64, f64)>,
+}
+
+impl Region for Rect {
+    fn contains(&self, x: f64, y: f64) -> bool {
+        x >= self.x && x < self.x + self.width && y >= self.y && y < self.y + self.height
+    }
+}
+
+impl Region for Circle {
+    fn contains(&self, x: f64, y: f64) -> bool {
+        (x - self.x).hypot(y - self.y) <= self.radius
+    }
+}
+
+impl Region for Polygon {
+    fn contains(&self, x: f64, y: f64) -> bool {
+        let vertices = &self.vertices;
+        let mut inside = false;
+        for (i, &(x1, y1)) in vertices.iter().enumerate() {
+            let (x2, y2) = vertices[(i + 1) % vertices.len()];
+            // Count the edges crossed by a ray from the point to the right
+            if (y1 > y) != (y2 > y) && x < x1 + (y - y1) / (y2 - y1) * (x2 - x1) {
+                inside = !inside;
+            }
+        }
+        inside
+    }
+}
+
+/// A TopCode contains the points strictly within its [radius](TopCode::radius).
+impl Region for TopCode {
+    fn contains(&self, x: f64, y: f64) -> bool {
+        self.covers(x, y)
+    }
+}
+
+impl<R: Region + ?Sized> Region for &R {
+    fn contains(&self, x: f64, y: f64) -> bool {
+        (**self).contains(x, y)
+    }
+}
+
+impl<R: Region> Region for [R] {
+    fn contains(&self, x: f64, y: f64) -> bool {
+        self.iter().any(|region| region.contains(x, y))
+    }
+}
+
+/// Returns the first of the codes that contains the point (x, y), e.g. the code a user tapped, or
+/// `None` if the point is outside all of them.
+pub fn code_at(codes: &[TopCode], x: f64, y: f64) -> Option<&TopCode> {
+    codes.iter().find(|topcode| topcode.contains(x, y))
+}
+
+/// Returns the codes whose centers lie within the region, in their original order.
+pub fn codes_in<'a>(codes: &'a [TopCode], region: &(impl Region + ?Sized)) -> Vec<&'a TopCode> {
+    codes
+        .iter()
+        .filter(|topcode| region.contains(topcode.x, topcode.y))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn shapes_contain_their_inside() {
+        let rect = Rect {
+            x: 10.0,
+            y: 20.0,
+            width: 30.0,
+            height: 10.0,
+        };
+        assert!(rect.contains(10.0, 20.0) && rect.contains(39.9, 29.9));
+        assert!(!rect.contains(40.0, 25.0) && !rect.contains(20.0, 30.0));
+
+        let circle = Circle {
+            x: 0.0,
+            y: 0.0,
+            radius: 5.0,
+        };
+        assert!(circle.contains(3.0, 4.0) && !circle.contains(3.0, 4.1));
+
+        // An L shape, whose notch at the top right is outside
+        let polygon = Polygon {
+            vertices: vec![
+                (0.0, 0.0),
+                (10.0, 0.0),
+                (10.0, 5.0),
+                (20.0, 5.0),
+                (20.0, 10.0),
+                (0.0, 10.0),
+            ],
+        };
+        assert!(polygon.contains(5.0, 2.0) && polygon.contains(15.0, 8.0));
+        assert!(!polygon.contains(15.0, 2.0) && !polygon.contains(-1.0, 5.0));
+        assert!(!Polygon::default().contains(0.0, 0.0));
+    }
+
+    #[test]
+    fn regions_can_be_mixed() {
+        let codes = [
+            TopCode::mock(31, 5.0, 0.0, 50.0, 50.0),
+            TopCode::mock(47, 5.0, 0.0, 150.0, 50.0),
+            TopCode::mock(55, 5.0, 0.0, 250.0, 50.0),
+        ];
+
+        assert_eq!(Some(47), code_at(&codes, 160.0, 55.0).and_then(|t| t.code));
+        assert_eq!(None, code_at(&codes, 100.0, 50.0));
+
+        let rect = Rect {
+            x: 0.0,
+            y: 0.0,
+            width: 100.0,
+            height: 100.0,
+        };
+        let circle = Circle {
+            x: 250.0,
+            y: 40.0,
+            radius: 20.0,
+        };
+        let regions: [&dyn Region; 2] = [&rect, &circle];
+        let found: Vec<_> = codes_in(&codes, &regions[..])
+            .iter()
+            .map(|topcode| topcode.code)
+            .collect();
+        assert_eq!(vec![Some(31), Some(55)], found);
+
+        // The codes themselves are a region
+        assert!(Region::contains(&codes[..], 248.0, 52.0));
+        assert!(!Region::contains(&codes[..], 100.0, 50.0));
+    }
+}
@@ -1,10 +1,156 @@
+use std::{
+    borrow::Cow,
+    fmt,
+    ops::DerefMut,
+    time::{Duration, Instant},
+};
+
+#[cfg(feature = "visualize")]
+use image::{GrayImage, Rgb, RgbImage};
+#[cfg(feature = "smallvec")]
+use smallvec::SmallVec;
+
+use crate::{
+    candidate::Candidate,
+    config::{Channel, Distortion, OverlapStrategy, ScannerConfig},
+    family::MarkerFamily,
+    math,
+    pixel::PackedPixel,
+    region::Region,
+    stats::ScanStats,
+    topcode::TopCode,
+};
+
+/// Smallest diameter of a TopCode in pixels that can be detected. Candidates need rings that are
+/// at least 2 pixels wide, and a TopCode is 8 rings wide.
+const MIN_CODE_DIAMETER: usize = 16;
+
+/// Number of pixels from which [Scanner::scan_par] computes the intensities of an image, or
+/// [Scanner::scan_binary_image] packs the rows of a binary image, in parallel. Smaller images are
+/// processed faster than the thread pool can split them.
+#[cfg(any(feature = "rayon", feature = "visualize"))]
+const PARALLEL_PACK_PIXELS: usize = 1 << 18;
+
+/// Number of codes [Scanner::scan_small] keeps inline before spilling to the heap. Most scenes
+/// hold a handful of markers, and a code takes 120 bytes, so eight of them cover the common
+/// case while keeping the result small enough to return on the stack.
+#[cfg(feature = "smallvec")]
+pub const INLINE_CODES: usize = 8;
+
+/// Number of codes [Scanner::set_auto_unit] waits for before it limits the unit of candidates.
+const AUTO_UNIT_CODES: usize = 3;
+
+/// Largest factor by which the unit of a candidate may differ from the expected unit when
+/// [Scanner::set_auto_unit] is enabled.
+const AUTO_UNIT_RANGE: f64 = 1.5;
+
+/// Colors of the heatmap drawn by [Scanner::threshold_heatmap] at evenly spaced thresholds from 0
+/// to 255, from black through purple, red and yellow to white.
 #[cfg(feature = "visualize")]
-use image::{GrayImage, ImageBuffer};
+const HEATMAP_STOPS: [[u8; 3]; 5] = [
+    [0, 0, 0],
+    [90, 20, 130],
+    [220, 50, 40],
+    [250, 200, 30],
+    [255, 255, 255],
+];
+
+/// Number of candidates [Scanner::scan_with_deadline] decodes between two readings of the clock.
+const DEADLINE_CHECK_CANDIDATES: usize = 16;
+
+/// Number of pixels the running sum used for adaptive thresholding approximately averages over.
+const THRESHOLD_WINDOW: isize = 32;
+
+/// Number of rows above each band that [Scanner::scan_banded] thresholds to re-prime the running
+/// sum.
+#[cfg(feature = "rayon")]
+const BAND_OVERLAP: usize = 2;
+
+/// A growable list of decoded codes, so that the same decoding loop can fill a [Vec] or a
+/// `SmallVec`.
+pub(crate) trait CodeList: Default + DerefMut<Target = [TopCode]> {
+    fn push(&mut self, code: TopCode);
+    fn retain(&mut self, f: impl FnMut(&mut TopCode) -> bool);
+    fn truncate(&mut self, len: usize);
+}
+
+impl CodeList for Vec<TopCode> {
+    fn push(&mut self, code: TopCode) {
+        Vec::push(self, code);
+    }
+
+    fn retain(&mut self, f: impl FnMut(&mut TopCode) -> bool) {
+        self.retain_mut(f);
+    }
+
+    fn truncate(&mut self, len: usize) {
+        Vec::truncate(self, len);
+    }
+}
+
+#[cfg(feature = "smallvec")]
+impl CodeList for SmallVec<[TopCode; INLINE_CODES]> {
+    fn push(&mut self, code: TopCode) {
+        SmallVec::push(self, code);
+    }
+
+    fn retain(&mut self, f: impl FnMut(&mut TopCode) -> bool) {
+        SmallVec::retain(self, f);
+    }
+
+    fn truncate(&mut self, len: usize) {
+        SmallVec::truncate(self, len);
+    }
+}
+
+/// Progress of the adaptive threshold through an image.
+#[derive(Clone, Debug)]
+struct ThresholdState {
+    /// Next row to threshold
+    row: usize,
+    /// Running sum of the intensities, carried across rows
+    sum: isize,
+    saturated_pixels: usize,
+    candidates: Vec<Candidate>,
+}
+
+impl Default for ThresholdState {
+    fn default() -> Self {
+        Self {
+            row: 0,
+            sum: 128,
+            saturated_pixels: 0,
+            candidates: Vec::with_capacity(50),
+        }
+    }
+}
+
+impl ThresholdState {
+    /// Forgets the candidates and statistics gathered so far, keeping the running sum.
+    #[cfg(feature = "rayon")]
+    fn discard_results(&mut self) {
+        self.saturated_pixels = 0;
+        self.candidates.clear();
+    }
 
-use crate::{candidate::Candidate, topcode::TopCode};
+    /// Adds the candidates and statistics of the rows below this state.
+    #[cfg(feature = "rayon")]
+    fn append(&mut self, mut other: ThresholdState) {
+        self.row = other.row;
+        self.saturated_pixels += other.saturated_pixels;
+        self.candidates.append(&mut other.candidates);
+    }
 
-/// Default maximum width of a TopCode unit/ring in pixels. This is equivalent to 640 pixels.
-const DEFAULT_MAX_UNIT: usize = 80;
+    fn add_candidate(&mut self, x: usize, y: usize, run: &RingRun) {
+        self.candidates.push(Candidate {
+            x,
+            y,
+            b1: run.b1 as usize,
+            w1: run.w1 as usize,
+            b2: run.b2 as usize,
+        });
+    }
+}
 
 #[repr(u8)]
 enum UnitLevel {
@@ -24,10 +170,122 @@ pub struct Scanner {
     width: usize,
     /// Expected image height
     height: usize,
-    /// Holds processed binary pixel data as a single u32 in the ARGB format.
-    data: Vec<u32>,
-    /// Maximum width of a TopCode unit in pixels
-    max_unit: usize,
+    /// Thresholded pixels of the last scan
+    data: Vec<PackedPixel>,
+    /// Parameters that can be changed between scans
+    config: ScannerConfig,
+    /// Sine and cosine of the start angle of every sector of the last family scanned
+    sector_directions: Vec<(f64, f64)>,
+    /// Statistics gathered during the last scan
+    stats: ScanStats,
+    /// Ring measurements of the candidates of the last scan
+    #[cfg(feature = "diagnostics")]
+    candidate_features: Vec<Candidate>,
+    /// Threshold progress of the rows fed with [Scanner::feed_row]
+    stream: ThresholdState,
+    /// Pixels where no candidate is kept, if any
+    ignore_mask: Option<Vec<bool>>,
+    /// Threshold of every pixel of the last scan, if kept
+    thresholds: Vec<u8>,
+}
+
+/// Run-length state machine that follows a row of binary pixels looking for the black, white,
+/// black pattern of a bullseye.
+struct RingPattern {
+    level: UnitLevel,
+    b1: isize,
+    b2: isize,
+    w1: isize,
+}
+
+impl Default for RingPattern {
+    fn default() -> Self {
+        Self {
+            level: UnitLevel::WhiteRegion,
+            b1: 0,
+            b2: 0,
+            w1: 0,
+        }
+    }
+}
+
+/// Widths in pixels of the black, white and black runs of a bullseye pattern found on a row.
+struct RingRun {
+    b1: isize,
+    w1: isize,
+    b2: isize,
+}
+
+impl RingRun {
+    /// Number of pixels to step back from the end of the pattern to reach the middle of its white
+    /// region.
+    fn step_back(&self) -> usize {
+        1 + self.b2 as usize + (self.w1 as usize >> 1)
+    }
+}
+
+impl RingPattern {
+    /// Feeds the next pixel of the row (0 for black, 1 for white), with rings at most `max_u`
+    /// pixels wide. Returns the runs of the pattern when the pixel completes a bullseye.
+    fn push(&mut self, a: isize, max_u: isize) -> Option<RingRun> {
+        let mut found = None;
+
+        match self.level {
+            UnitLevel::WhiteRegion => {
+                if a == 0 {
+                    // First black pixel encountered
+                    self.level = UnitLevel::BlackRegion;
+                    self.b1 = 1;
+                    self.w1 = 0;
+                    self.b2 = 0;
+                }
+            }
+            UnitLevel::BlackRegion => {
+                if a == 0 {
+                    self.b1 += 1;
+                } else {
+                    self.level = UnitLevel::WhiteRegionSecond;
+                    self.w1 = 1;
+                }
+            }
+            UnitLevel::WhiteRegionSecond => {
+                if a == 0 {
+                    self.level = UnitLevel::BlackRegionSecond;
+                    self.b2 = 1;
+                } else {
+                    self.w1 += 1;
+                }
+            }
+            UnitLevel::BlackRegionSecond => {
+                if a == 0 {
+                    self.b2 += 1;
+                } else {
+                    if self.b1 >= 2
+                        && self.b2 >= 2
+                        && self.b1 <= max_u
+                        && self.b2 <= max_u
+                        && self.w1 <= (max_u + max_u)
+                        && (self.b1 + self.b2 - self.w1).abs() <= (self.b1 + self.b2)
+                        && (self.b1 + self.b2 - self.w1).abs() <= self.w1
+                        && (self.b1 - self.b2).abs() <= self.b1
+                        && (self.b1 - self.b2).abs() <= self.b2
+                    {
+                        found = Some(RingRun {
+                            b1: self.b1,
+                            w1: self.w1,
+                            b2: self.b2,
+                        });
+                    }
+                    self.b1 = self.b2;
+                    self.w1 = 1;
+                    self.b2 = 0;
+                    self.level = UnitLevel::WhiteRegionSecond;
+                }
+            }
+        }
+
+        found
+    }
 }
 
 impl Scanner {
@@ -35,8 +293,15 @@ impl Scanner {
         Self {
             width,
             height,
-            data: vec![0; width * height],
-            max_unit: DEFAULT_MAX_UNIT,
+            data: vec![PackedPixel::default(); width * height],
+            config: ScannerConfig::default(),
+            sector_directions: Vec::new(),
+            stats: ScanStats::default(),
+            #[cfg(feature = "diagnostics")]
+            candidate_features: Vec::new(),
+            stream: ThresholdState::default(),
+            ignore_mask: None,
+            thresholds: Vec::new(),
         }
     }
 
@@ -48,341 +313,2957 @@ impl Scanner {
         self.height
     }
 
+    /// Returns the statistics gathered during the last scan.
+    pub fn stats(&self) -> &ScanStats {
+        &self.stats
+    }
+
+    /// Returns every candidate found during the last scan with its ring measurements, in the order
+    /// they were found. These are the features the detector thresholds on, so they can be used to
+    /// train data-driven candidate filters.
+    #[cfg(feature = "diagnostics")]
+    pub fn candidate_features(&self) -> &[Candidate] {
+        &self.candidate_features
+    }
+
+    /// Returns the expected image dimensions as `(width, height)`.
+    pub fn dimensions(&self) -> (usize, usize) {
+        (self.width, self.height)
+    }
+
+    /// Returns the number of bytes the scanner holds on the heap, e.g. to budget memory on an
+    /// embedded or mobile device when choosing the resolution to scan at. This counts the
+    /// allocated capacity of every buffer the scanner keeps between scans: the thresholded image,
+    /// which takes 4 bytes per pixel and dominates for any real image, the table of sector
+    /// directions, the candidates of rows fed with [Scanner::feed_row], the
+    /// [ignore mask](Scanner::set_ignore_mask), the [kept thresholds](Scanner::set_keep_thresholds)
+    /// and, with the `diagnostics` feature, the [candidate features](Scanner::candidate_features).
+    /// Memory allocated only for the duration of a scan, such as the candidate list, is not
+    /// included.
+    pub fn memory_usage(&self) -> usize {
+        use std::mem::size_of;
+
+        let bytes = self.data.capacity() * size_of::<PackedPixel>()
+            + self.sector_directions.capacity() * size_of::<(f64, f64)>()
+            + self.stream.candidates.capacity() * size_of::<Candidate>()
+            + self.ignore_mask.as_ref().map_or(0, Vec::capacity)
+            + self.thresholds.capacity();
+        #[cfg(feature = "diagnostics")]
+        let bytes = bytes + self.candidate_features.capacity() * size_of::<Candidate>();
+        bytes
+    }
+
+    /// Returns the parameters the scanner currently uses.
+    pub fn config(&self) -> &ScannerConfig {
+        &self.config
+    }
+
+    /// Replaces every parameter of the scanner at once, e.g. to adapt to a camera that zooms
+    /// between frames, without reallocating the scanner. Every parameter takes effect from the next
+    /// scan; the results and [stats](Scanner::stats) of the last scan are kept until then, except
+    /// for the [kept thresholds](Scanner::set_keep_thresholds), which are dropped when the new
+    /// config does not keep them.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the config is not [valid](ScannerConfig::is_valid).
+    pub fn set_config(&mut self, config: ScannerConfig) {
+        assert!(config.is_valid(), "Invalid scanner config: {:?}", config);
+        self.config = config;
+        if !config.keep_thresholds {
+            self.thresholds = Vec::new();
+        }
+    }
+
+    /// Changes some of the parameters of the scanner, checking that they remain
+    /// [valid](ScannerConfig::is_valid) like [Scanner::set_config] does.
+    fn update_config(&mut self, update: impl FnOnce(&mut ScannerConfig)) {
+        let mut config = self.config;
+        update(&mut config);
+        self.set_config(config);
+    }
+
+    /// Restores the [default](ScannerConfig::default) parameters.
+    pub fn reset_config(&mut self) {
+        self.set_config(ScannerConfig::default());
+    }
+
+    /// Sets the pixels to ignore, one per pixel in row-major order, e.g. to exclude a logo or a
+    /// busy part of the scene that never holds markers but keeps producing false candidates.
+    /// Candidates centered on an ignored pixel are dropped after thresholding, so they are
+    /// neither decoded nor counted in [ScanStats::candidates]. The image is still thresholded as a
+    /// whole, and codes centered outside the mask are decoded even if they extend into it.
+    ///
+    /// The mask applies to every way of scanning, including [Scanner::scan_candidates] and the
+    /// rows fed with [Scanner::feed_row]. It is kept until it is replaced or cleared with
+    /// [Scanner::clear_ignore_mask].
+    ///
+    /// # Panics
+    ///
+    /// Panics if the length of the mask is not width * height.
+    pub fn set_ignore_mask(&mut self, mask: &[bool]) {
+        assert_eq!(
+            self.width * self.height,
+            mask.len(),
+            "Ignore mask length must be width * height"
+        );
+        self.ignore_mask = Some(mask.to_vec());
+    }
+
+    /// Adds a rectangle of `width` by `height` pixels with its top-left corner at (x, y) to the
+    /// pixels to ignore (see [Scanner::set_ignore_mask]). The parts of the rectangle outside the
+    /// image are left out.
+    pub fn ignore_rect(&mut self, x: usize, y: usize, width: usize, height: usize) {
+        let image_width = self.width;
+        let mask = self
+            .ignore_mask
+            .get_or_insert_with(|| vec![false; self.width * self.height]);
+        for row in y..y.saturating_add(height).min(self.height) {
+            let start = row * image_width;
+            let columns = x.min(image_width)..x.saturating_add(width).min(image_width);
+            mask[start + columns.start..start + columns.end].fill(true);
+        }
+    }
+
+    /// Stops ignoring any pixels.
+    pub fn clear_ignore_mask(&mut self) {
+        self.ignore_mask = None;
+    }
+
     /// Scan the image and return a list of all TopCodes found in it.
+    #[must_use = "the codes found are only returned, not kept by the scanner"]
     pub fn scan<T: ?Sized>(
         &mut self,
         image_buffer: &T,
         decode_rgb: impl Fn(&T, usize) -> (u32, u32, u32),
     ) -> Vec<TopCode> {
-        let candidates = self.threshold(image_buffer, decode_rgb);
-        self.find_codes(&candidates)
+        let candidates = self.threshold(image_buffer, &decode_rgb);
+        self.warmup(&MarkerFamily::TOPCODE);
+        if self.config.upsample_small_markers {
+            return self.find_small_codes(image_buffer, decode_rgb, &candidates);
+        }
+        self.find_codes(&candidates, &MarkerFamily::TOPCODE)
     }
 
-    /// Sets the maximum allowable diameter (in pixels) for a TopCode identified by the scanner.
-    /// Setting this to a reasonable value for your application will reduce false positives
-    /// (recognizing codes that aren't actually there) and improve performance (because fewer
-    /// candidate codes will be tested). Setting this value to as low as 50 or 60 pixels could be
-    /// advisable for some applications. However, setting the maximum diameter too low will prevent
-    /// valid codes from being recognized.
-    pub fn set_max_code_diameter(&mut self, diameter: usize) {
-        let f = diameter as f64 / 8.0;
-        self.max_unit = f.ceil() as usize;
+    /// Scan the image like [Scanner::scan], but stop decoding candidates once `deadline` has passed
+    /// since the call, e.g. to stay within the frame budget of a real-time app when a pathological
+    /// image yields thousands of candidates. Returns the codes decoded so far, and whether
+    /// decoding was cut short, in which case codes further down the image may be missing.
+    ///
+    /// The deadline is best effort: the image is always thresholded in full, and the clock is only
+    /// read every 16 candidates, so the scan can overrun the deadline by the time it takes to
+    /// threshold the image and decode a few candidates.
+    #[must_use = "the codes found are only returned, not kept by the scanner"]
+    pub fn scan_with_deadline<T: ?Sized>(
+        &mut self,
+        image_buffer: &T,
+        decode_rgb: impl Fn(&T, usize) -> (u32, u32, u32),
+        deadline: Duration,
+    ) -> (Vec<TopCode>, bool) {
+        let deadline = Instant::now() + deadline;
+        let candidates = self.threshold(image_buffer, decode_rgb);
+        self.warmup(&MarkerFamily::TOPCODE);
+        self.find_codes_until(&candidates, Some(deadline), |c| {
+            self.decode_candidate(c, &MarkerFamily::TOPCODE)
+        })
     }
 
-    /// Average of thresholded pixels in a 3x3 region around (x, y). Returned value is between 0
-    /// (black) and 255 (white).
-    pub(crate) fn get_sample_3x3(&self, x: usize, y: usize) -> usize {
-        if x < 1 || x >= self.width - 1 || y < 1 || y >= self.height - 1 {
-            return 0;
-        }
+    /// Scan the image and return a list of all TopCodes found in it, like [Scanner::scan], but
+    /// without allocating on the heap when at most [INLINE_CODES] codes are found.
+    #[cfg(feature = "smallvec")]
+    #[must_use = "the codes found are only returned, not kept by the scanner"]
+    pub fn scan_small<T: ?Sized>(
+        &mut self,
+        image_buffer: &T,
+        decode_rgb: impl Fn(&T, usize) -> (u32, u32, u32),
+    ) -> SmallVec<[TopCode; INLINE_CODES]> {
+        let candidates = self.threshold(image_buffer, decode_rgb);
+        self.warmup(&MarkerFamily::TOPCODE);
+        self.find_codes(&candidates, &MarkerFamily::TOPCODE)
+    }
 
-        let mut sum = 0;
-        for j in y - 1..=y + 1 {
-            for i in x - 1..=x + 1 {
-                let pixel = self.data[j * self.width + i];
-                sum += 0xff * (pixel >> 24 & 0x01);
-            }
-        }
+    /// Scan an image given as one intensity byte per pixel, e.g. luminance computed on the GPU, and
+    /// return a list of all TopCodes found in it. Each byte is thresholded as is, so the
+    /// [channel](Scanner::set_channel) is ignored. This is the leanest input, since nothing is
+    /// decoded or converted per pixel.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the buffer is not `width * height` bytes long.
+    #[must_use = "the codes found are only returned, not kept by the scanner"]
+    pub fn scan_intensity(&mut self, intensity: &[u8]) -> Vec<TopCode> {
+        assert_eq!(
+            intensity.len(),
+            self.width * self.height,
+            "Intensity buffer length must be width * height"
+        );
 
-        (sum / 9) as usize
+        let candidates = self.threshold_intensity(|k| intensity[k] as u32);
+        self.warmup(&MarkerFamily::TOPCODE);
+        self.find_codes(&candidates, &MarkerFamily::TOPCODE)
     }
 
-    /// Average of thresholded pixels in a 3x3 region around (x, y). Returned value is either 0
-    /// (black) or 1 (white).
-    pub(crate) fn get_bw_3x3(&self, x: usize, y: usize) -> u32 {
-        if x < 1 || x >= self.width - 1 || y < 1 || y >= self.height - 1 {
-            return 0;
-        }
+    /// Thresholds the image and returns the candidate centers of TopCodes found in it, without
+    /// decoding them, e.g. to build a custom decoder or filter on top of the candidate layer.
+    /// Candidates are listed row by row from the top. The thresholded image is kept, so
+    /// [Scanner::decode_at] can decode a chosen candidate afterwards.
+    #[must_use = "the candidates found are only returned, not kept by the scanner"]
+    pub fn scan_candidates<T: ?Sized>(
+        &mut self,
+        image_buffer: &T,
+        decode_rgb: impl Fn(&T, usize) -> (u32, u32, u32),
+    ) -> Vec<Candidate> {
+        self.threshold(image_buffer, decode_rgb)
+    }
 
-        let mut sum = 0;
-        for j in y - 1..=y + 1 {
-            for i in x - 1..=x + 1 {
-                let pixel = self.data[j * self.width + i];
-                sum += pixel >> 24 & 0x01;
-            }
-        }
+    /// Scan the image and return a list of all markers of the given [MarkerFamily] found in it.
+    /// Scanning with [MarkerFamily::TOPCODE] is the same as [Scanner::scan].
+    ///
+    /// # Panics
+    ///
+    /// Panics if the family is not [valid](MarkerFamily::is_valid).
+    #[must_use = "the codes found are only returned, not kept by the scanner"]
+    pub fn scan_family<T: ?Sized>(
+        &mut self,
+        image_buffer: &T,
+        decode_rgb: impl Fn(&T, usize) -> (u32, u32, u32),
+        family: &MarkerFamily,
+    ) -> Vec<TopCode> {
+        assert!(family.is_valid(), "Invalid marker family: {:?}", family);
 
-        if sum >= 5 {
-            1
-        } else {
-            0
-        }
+        let candidates = self.threshold(image_buffer, decode_rgb);
+        self.warmup(family);
+        self.find_codes(&candidates, family)
     }
 
-    /// Perform Wellner adaptive thresholding to produce binary pixel data. Also mark candidate
-    /// SpotCode locations.
+    /// Scan the image and return a list of all TopCodes found in it, decoding the candidates on
+    /// `threads` scoped threads. Thresholding still runs on the calling thread.
     ///
-    /// "Adaptive Thresholding for the DigitalDesk"
-    /// EuroPARC Technical Report EPC-93-110
-    fn threshold<T: ?Sized>(
+    /// The result is identical to [Scanner::scan]. This avoids a dependency on a thread pool, but
+    /// spawns new threads on every call, so it only pays off for images with many candidates.
+    #[cfg(feature = "std-parallel")]
+    #[must_use = "the codes found are only returned, not kept by the scanner"]
+    pub fn scan_parallel<T: ?Sized>(
         &mut self,
         image_buffer: &T,
         decode_rgb: impl Fn(&T, usize) -> (u32, u32, u32),
-    ) -> Vec<Candidate> {
-        let mut candidates = Vec::with_capacity(50);
-        let mut sum = 128;
-        let s = 32;
-
-        for j in 0..self.height {
-            let mut level = UnitLevel::WhiteRegion;
-            let mut b1: isize = 0;
-            let mut b2: isize = 0;
-            let mut w1: isize = 0;
-
-            let mut k = if j % 2 == 0 { 0 } else { self.width - 1 };
-            k += j * self.width;
-
-            for _i in 0..self.width {
-                // Calculate pixel intensity (0-255)
-                let (r, g, b) = decode_rgb(image_buffer, k);
-                let mut a: isize = (r + g + b) as isize / 3;
-
-                // Calculate the average sum as an approximate sum of the last s pixels
-                sum += a - (sum / s);
-
-                // Factor in sum from the previous row
-                let threshold = if k >= self.width {
-                    (sum + (self.data[k - self.width] as isize & 0xffffff)) / (2 * s)
-                } else {
-                    sum / s
-                };
-
-                // Compare the average sum to current pixel to decide black or white
-                a = if (a as f64) < (threshold as f64 * 0.975) {
-                    0
-                } else {
-                    1
-                };
+        threads: usize,
+    ) -> Vec<TopCode> {
+        let candidates = self.threshold(image_buffer, decode_rgb);
+        self.warmup(&MarkerFamily::TOPCODE);
+        self.find_codes_parallel(&candidates, &MarkerFamily::TOPCODE, threads)
+    }
 
-                // Repack pixel data with binary data in the alpha channel, and the running some
-                // for this pixel in the RGB channels.
-                self.data[k] = ((a << 24) + (sum & 0xffffff)) as u32;
-
-                match level {
-                    UnitLevel::WhiteRegion => {
-                        if a == 0 {
-                            // First black pixel encountered
-                            level = UnitLevel::BlackRegion;
-                            b1 = 1;
-                            w1 = 0;
-                            b2 = 0;
-                        }
-                    }
-                    UnitLevel::BlackRegion => {
-                        if a == 0 {
-                            b1 += 1;
-                        } else {
-                            level = UnitLevel::WhiteRegionSecond;
-                            w1 = 1;
-                        }
-                    }
-                    UnitLevel::WhiteRegionSecond => {
-                        if a == 0 {
-                            level = UnitLevel::BlackRegionSecond;
-                            b2 = 1;
-                        } else {
-                            w1 += 1;
-                        }
-                    }
-                    UnitLevel::BlackRegionSecond => {
-                        let max_u = self.max_unit as isize;
-                        if a == 0 {
-                            b2 += 1;
-                        } else {
-                            if b1 >= 2
-                                && b2 >= 2
-                                && b1 <= max_u
-                                && b2 <= max_u
-                                && w1 <= (max_u + max_u)
-                                && (b1 + b2 - w1).abs() <= (b1 + b2)
-                                && (b1 + b2 - w1).abs() <= w1
-                                && (b1 - b2).abs() <= b1
-                                && (b1 - b2).abs() <= b2
-                            {
-                                let mut dk: usize = 1 + b2 as usize + (w1 as usize >> 1);
-                                dk = if j % 2 == 0 { k - dk } else { k + dk };
-
-                                candidates.push(Candidate::new(dk % self.width, j));
-                            }
-                            b1 = b2;
-                            w1 = 1;
-                            b2 = 0;
-                            level = UnitLevel::WhiteRegionSecond;
-                        }
-                    }
-                }
-                if j % 2 == 0 {
-                    k += 1
-                } else {
-                    k -= 1
-                };
-            }
+    /// Scan the image and return a list of all TopCodes found in it, like [Scanner::scan], but
+    /// compute the intensities of the pixels of large images on the rayon thread pool before
+    /// thresholding, which is why the buffer and `decode_rgb` must be [Sync]. The running sum of
+    /// the adaptive threshold is still computed on the calling thread, so the result is identical
+    /// to [Scanner::scan].
+    #[cfg(feature = "rayon")]
+    #[must_use = "the codes found are only returned, not kept by the scanner"]
+    pub fn scan_par<T: ?Sized + Sync>(
+        &mut self,
+        image_buffer: &T,
+        decode_rgb: impl Fn(&T, usize) -> (u32, u32, u32) + Sync,
+    ) -> Vec<TopCode> {
+        let candidates = self.threshold_par(image_buffer, &decode_rgb);
+        self.warmup(&MarkerFamily::TOPCODE);
+        if self.config.upsample_small_markers {
+            return self.find_small_codes(image_buffer, decode_rgb, &candidates);
         }
+        self.find_codes(&candidates, &MarkerFamily::TOPCODE)
+    }
 
-        candidates
+    /// Scan the image and return a list of all TopCodes found in it, thresholding `bands`
+    /// horizontal bands of the image in parallel on the rayon thread pool.
+    ///
+    /// The running sum of the adaptive threshold carries over from one row to the next, so each
+    /// band re-primes it by thresholding the two rows above the band first. The result is close
+    /// to, but not guaranteed to be the same as [Scanner::scan]: pixels at the start of the first
+    /// row of each band can be classified differently, which may move or drop a candidate on the
+    /// seam. On the photo and source images in `assets/`, the binary maps are identical for up to
+    /// 16 bands. Re-priming from a single row instead left up to 14 pixels different per image.
+    #[cfg(feature = "rayon")]
+    #[must_use = "the codes found are only returned, not kept by the scanner"]
+    pub fn scan_banded<T: ?Sized + Sync>(
+        &mut self,
+        image_buffer: &T,
+        decode_rgb: impl Fn(&T, usize) -> (u32, u32, u32) + Sync,
+        bands: usize,
+    ) -> Vec<TopCode> {
+        let candidates = self.threshold_banded(image_buffer, decode_rgb, bands);
+        self.warmup(&MarkerFamily::TOPCODE);
+        self.find_codes(&candidates, &MarkerFamily::TOPCODE)
     }
 
-    /// Scan the image line by line looking for TopCodes.
-    fn find_codes(&self, candidates: &Vec<Candidate>) -> Vec<TopCode> {
-        let mut spots = Vec::with_capacity(candidates.len());
+    /// Precomputes the lookup tables used to decode markers of the given family, so that the first
+    /// scan does not pay for them. Scanning does this automatically when the family changes.
+    pub fn warmup(&mut self, family: &MarkerFamily) {
+        if self.sector_directions.len() != family.sectors {
+            let arc = family.arc();
+            self.sector_directions = (0..family.sectors)
+                .map(|sector| math::sin_cos(arc * sector as f64))
+                .collect();
+        }
+    }
 
-        for c in candidates {
-            if !self.overlaps(&spots, c.x, c.y) {
-                let mut spot = TopCode::default();
-                spot.decode(self, c.x, c.y);
-                if spot.is_valid() {
-                    spots.push(spot);
-                }
-            }
+    /// Decodes a single TopCode whose bullseye contains the pixel (x, y) of the image thresholded
+    /// by the last scan, e.g. at a center proposed by an external detector. The code goes through
+    /// the same filters as the codes returned by a scan, so the returned coordinates follow
+    /// [Scanner::set_center_origin], although (x, y) are always image coordinates, and the
+    /// [scanline](TopCode::scanline) of the code is `y`. Returns `None` if no valid code is found
+    /// there.
+    pub fn decode_at(&self, x: usize, y: usize) -> Option<TopCode> {
+        if x >= self.width || y >= self.height {
+            return None;
         }
 
-        spots
+        let spot = self.decode_candidate(&Candidate::new(x, y), &MarkerFamily::TOPCODE);
+        if !spot.is_valid() {
+            return None;
+        }
+        self.filter_codes(vec![spot]).pop()
     }
 
-    fn overlaps(&self, spots: &Vec<TopCode>, x: usize, y: usize) -> bool {
-        for top in spots {
-            if top.in_bullseye(x as f64, y as f64) {
-                return true;
-            }
+    /// Returns the sine and cosine of the start angle of every sector of the family, from the
+    /// table built by [Scanner::warmup] when it matches.
+    pub(crate) fn sector_directions(&self, family: &MarkerFamily) -> Cow<'_, [(f64, f64)]> {
+        if self.sector_directions.len() == family.sectors {
+            Cow::Borrowed(&self.sector_directions)
+        } else {
+            let arc = family.arc();
+            (0..family.sectors)
+                .map(|sector| math::sin_cos(arc * sector as f64))
+                .collect()
+        }
+    }
+
+    /// Scan an image that was already binarized, e.g. with the `imageproc` crate, and return a list
+    /// of all TopCodes found in it. Pixels of 128 and above are white and darker pixels are black.
+    /// The adaptive threshold is skipped, so [Scanner::intensity_at] only reports 0 or 255.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the image dimensions do not match the dimensions of the scanner.
+    #[cfg(feature = "visualize")]
+    #[must_use = "the codes found are only returned, not kept by the scanner"]
+    pub fn scan_binary_image(&mut self, img: &GrayImage) -> Vec<TopCode> {
+        let dimensions = (img.width() as usize, img.height() as usize);
+        assert_eq!(
+            self.dimensions(),
+            dimensions,
+            "Image dimensions must match the scanner"
+        );
+
+        self.stats = ScanStats::default();
+        if self.width < MIN_CODE_DIAMETER || self.height < MIN_CODE_DIAMETER {
+            return Vec::new();
         }
 
-        false
+        let parallel = cfg!(feature = "rayon") && self.data.len() >= PARALLEL_PACK_PIXELS;
+        let state = self.pack_binary_image(img, parallel);
+        let candidates = self.finish_threshold(state);
+        self.warmup(&MarkerFamily::TOPCODE);
+        self.find_codes(&candidates, &MarkerFamily::TOPCODE)
     }
 
-    /// Counts the number of pixels from (x, y) until a color change is perceived.
-    pub(crate) fn dist(&self, x: usize, y: usize, dx: isize, dy: isize) -> isize {
-        let start = self.get_bw_3x3(x, y);
+    /// Packs a binary image into the thresholded pixels and finds the candidates in it. Rows do not
+    /// depend on each other, so they are packed on the rayon thread pool when `parallel` is set and
+    /// the feature is enabled. The result is the same either way.
+    #[cfg(feature = "visualize")]
+    #[cfg_attr(not(feature = "rayon"), allow(unused_variables))]
+    fn pack_binary_image(&mut self, img: &GrayImage, parallel: bool) -> ThresholdState {
+        let width = self.width;
+        let max_unit = self.effective_max_unit();
+        let pixels = img.as_raw();
+        let pack_row = |state: &mut ThresholdState, j: usize, row: &mut [PackedPixel]| {
+            let mut pattern = RingPattern::default();
+            for (i, packed) in row.iter_mut().enumerate() {
+                let a = isize::from(pixels[j * width + i] >= 0x80);
 
-        let mut i = x as isize + dx;
-        let mut j = y as isize + dy;
+                // Store the running sum a uniform area of this color would have
+                let sum = a * 0xff * THRESHOLD_WINDOW;
+                *packed = PackedPixel::new(a == 1, sum);
 
-        loop {
-            if i <= 1 || i >= self.width as isize - 1 || j <= 1 || j >= self.height as isize {
-                break;
+                if let Some(run) = pattern.push(a, max_unit) {
+                    if let Some(x) = i.checked_sub(run.step_back()) {
+                        state.add_candidate(x, j, &run);
+                    }
+                }
             }
+        };
 
-            let sample = self.get_bw_3x3(i as usize, j as usize);
-            if start + sample == 1 {
-                let x_dist = (i - x as isize).abs();
-                let y_dist = (j - y as isize).abs();
-                return x_dist + y_dist;
-            }
+        #[cfg(feature = "rayon")]
+        if parallel {
+            use rayon::prelude::*;
 
-            i += dx;
-            j += dy;
+            return self
+                .data
+                .par_chunks_mut(width)
+                .enumerate()
+                .fold(ThresholdState::default, |mut state, (j, row)| {
+                    pack_row(&mut state, j, row);
+                    state
+                })
+                .reduce(ThresholdState::default, |mut merged, state| {
+                    merged.append(state);
+                    merged
+                });
         }
 
-        -1
+        let mut state = ThresholdState::default();
+        for (j, row) in self.data.chunks_mut(width).enumerate() {
+            pack_row(&mut state, j, row);
+        }
+        state
     }
 
-    #[cfg(feature = "visualize")]
-    pub fn write_thresholding_image(&self, path: &str) {
-        let img = GrayImage::from_fn(self.width as u32, self.height as u32, |x, y| {
-            let index = (y * self.width as u32 + x) as usize;
-            let pixel = self.data[index];
-            let a = ((pixel >> 24) * 0xff) as u8;
-            image::Luma([a])
-        });
-        img.save(path).expect("Failed to save png image");
-    }
-}
+    /// Feeds the next row of an RGB image (3 bytes per pixel) to the scanner, so that the image is
+    /// thresholded as it arrives, e.g. from a socket or a slow sensor, instead of being buffered
+    /// whole first. Once every row has been fed, [Scanner::finish] decodes the TopCodes. The result
+    /// is the same as calling [Scanner::scan] on the complete image.
+    ///
+    /// Rows must be fed in order from the top of the image, because the adaptive threshold of
+    /// every row depends on the running sum of the rows before it. Like [Scanner::scan], images too
+    /// small to hold a TopCode are not thresholded, so their rows are only counted.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the row is not `3 * width` bytes long, or if every row was already fed.
+    pub fn feed_row(&mut self, row: &[u8]) {
+        assert_eq!(row.len(), self.width * 3, "Row length must be 3 * width");
+        assert!(self.stream.row < self.height, "Every row was already fed");
+
+        if self.stream.row == 0 {
+            self.stats = ScanStats::default();
+        }
+
+        // Images smaller than the smallest possible TopCode cannot contain any
+        if self.width < MIN_CODE_DIAMETER || self.height < MIN_CODE_DIAMETER {
+            self.stream.row += 1;
+            return;
+        }
+
+        let mut state = std::mem::take(&mut self.stream);
+        let mut data = std::mem::take(&mut self.data);
+        let offset = state.row * self.width;
+        let channel = self.config.channel;
+        self.threshold_row(&mut data, 0, &mut state, |k| {
+            let i = (k - offset) * 3;
+            channel.intensity((row[i] as u32, row[i + 1] as u32, row[i + 2] as u32))
+        });
+        self.data = data;
+        self.stream = state;
+    }
+
+    /// Decodes the TopCodes in the rows fed with [Scanner::feed_row], after which the next image
+    /// can be fed.
+    ///
+    /// # Panics
+    ///
+    /// Panics if fewer than `height` rows were fed.
+    #[must_use = "the codes found are only returned, not kept by the scanner"]
+    pub fn finish(&mut self) -> Vec<TopCode> {
+        assert_eq!(self.stream.row, self.height, "Every row must be fed first");
+
+        let state = std::mem::take(&mut self.stream);
+        if self.width < MIN_CODE_DIAMETER || self.height < MIN_CODE_DIAMETER {
+            return Vec::new();
+        }
+
+        let candidates = self.finish_threshold(state);
+        self.warmup(&MarkerFamily::TOPCODE);
+        self.find_codes(&candidates, &MarkerFamily::TOPCODE)
+    }
+
+    /// Sets the maximum allowable diameter (in pixels) for a TopCode identified by the scanner.
+    /// Setting this to a reasonable value for your application will reduce false positives
+    /// (recognizing codes that aren't actually there) and improve performance (because fewer
+    /// candidate codes will be tested). Setting this value to as low as 50 or 60 pixels could be
+    /// advisable for some applications. However, setting the maximum diameter too low will prevent
+    /// valid codes from being recognized.
+    ///
+    /// A code must fit entirely inside the image to be decoded, so the effective maximum is never
+    /// larger than the smaller image dimension, however large the configured diameter.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the diameter is 8 pixels or less, which leaves units of less than 2 pixels.
+    pub fn set_max_code_diameter(&mut self, diameter: usize) {
+        let f = diameter as f64 / 8.0;
+        self.update_config(|config| config.max_unit = f.ceil() as usize);
+    }
+
+    /// Maximum unit used to find candidates: the configured maximum, limited to the unit of the
+    /// largest code that fits in the image.
+    fn effective_max_unit(&self) -> isize {
+        let fits = self.width.min(self.height).div_ceil(8);
+        self.config.max_unit.min(fits) as isize
+    }
+
+    /// Sets the minimum [TopCode::confidence] (between 0 and 1) of the codes returned by the
+    /// scanner. Codes with a valid checksum but a lower confidence are dropped, trading recall for
+    /// precision. Defaults to 0, which keeps every valid code.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the confidence is not finite.
+    pub fn set_min_confidence(&mut self, confidence: f64) {
+        self.update_config(|config| config.min_confidence = confidence);
+    }
+
+    /// Sets the maximum number of TopCodes returned by the scanner. Decoding stops as soon as this
+    /// many codes are found, which saves time in scenes with many candidates when only a few
+    /// markers are needed. Codes are found from the top of the image down. Defaults to [None]
+    /// (unlimited).
+    pub fn set_max_codes(&mut self, max_codes: Option<usize>) {
+        self.config.max_codes = max_codes;
+    }
+
+    /// Sets the minimum distance in pixels between the centers of the TopCodes returned by the
+    /// scanner, e.g. when markers are mounted on pieces that cannot physically come closer. Of any
+    /// two codes closer than this, only the one with the higher [TopCode::confidence] is kept.
+    /// Unlike the suppression of candidates inside codes that were already found, this also
+    /// drops codes that do not overlap at all. Defaults to [None] (no minimum).
+    ///
+    /// With [Scanner::set_max_codes], decoding no longer stops early, since a code found later
+    /// may replace one found earlier.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the spacing is negative or not finite.
+    pub fn set_min_code_spacing(&mut self, spacing: Option<f64>) {
+        self.update_config(|config| config.min_code_spacing = spacing);
+    }
+
+    /// Sets what the scanner does with a candidate that lies within a code that was already found,
+    /// which matters for markers that overlap physically, such as tokens stacked on each other.
+    ///
+    /// - [OverlapStrategy::Suppress] skips the candidate, taking it for another center of the code
+    ///   that was found, so the marker on top is lost if its center lies within the marker below.
+    ///   This is the default.
+    /// - [OverlapStrategy::KeepHigherConfidence] decodes the candidate, unless it lies within the
+    ///   bullseye of a code that was found. A code that overlaps codes that were found, i.e. either
+    ///   center lies within the other code, replaces them if it meets
+    ///   [Scanner::set_min_confidence] and is more confident than all of them, and is dropped
+    ///   otherwise. Exactly one of markers that overlap is returned.
+    /// - [OverlapStrategy::KeepBoth] decodes the candidate, unless it lies within the bullseye of
+    ///   a code that was found, and keeps the code as well if it differs from every code it
+    ///   overlaps and meets [Scanner::set_min_confidence].
+    ///
+    /// Decoding overlapping candidates is ambiguous: a candidate in the rings of a damaged marker
+    /// can decode to another code that is not there, and two stacked markers with the same code
+    /// are reported once. Each marker also yields more candidates to decode, so scans are slower.
+    /// The strategies only apply to the sequential scans; the parallel scans always suppress.
+    pub fn set_overlap_strategy(&mut self, strategy: OverlapStrategy) {
+        self.config.overlap_strategy = strategy;
+    }
+
+    /// Sets whether the scanner returns the coordinates of codes relative to the center of the
+    /// image with the y-axis pointing up, as many graphics pipelines expect, instead of relative to
+    /// the top-left corner with the y-axis pointing down (the default). A code at pixel `(x, y)` is
+    /// then returned at `(x - width / 2, height / 2 - y)`.
+    ///
+    /// Only [TopCode::x] and [TopCode::y] are converted. [TopCode::orientation] is still measured
+    /// in image coordinates, and methods that read the image again, such as
+    /// [TopCode::geometric_orientation], expect image coordinates.
+    pub fn set_center_origin(&mut self, center_origin: bool) {
+        self.config.center_origin = center_origin;
+    }
+
+    /// Enables cross-checking the orientation of every decoded TopCode against the position of
+    /// its data ring edge in the image (see [TopCode::geometric_orientation]). The result is
+    /// reported in [TopCode::orientation_consistent]. Disabled by default.
+    pub fn set_check_orientation(&mut self, check: bool) {
+        self.config.check_orientation = check;
+    }
+
+    pub(crate) fn checks_orientation(&self) -> bool {
+        self.config.check_orientation
+    }
+
+    /// Sets the channel of the image from which the intensity of each pixel is computed. Scanning a
+    /// single channel can give much better contrast than the average of all three ([Channel::Luma],
+    /// the default) for markers printed in color on a colored background, e.g. the green channel
+    /// for red markers on green paper.
+    pub fn set_channel(&mut self, channel: Channel) {
+        self.config.channel = channel;
+    }
+
+    /// Sets the ratio of the local average intensity below which a pixel is considered black
+    /// during adaptive thresholding. Lower values classify fewer pixels as black. Defaults to
+    /// 0.975. See [Scanner::auto_tune_threshold] to pick a value from an image.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the ratio is not positive and finite.
+    pub fn set_threshold_ratio(&mut self, ratio: f64) {
+        self.update_config(|config| config.threshold_ratio = ratio);
+    }
+
+    pub fn threshold_ratio(&self) -> f64 {
+        self.config.threshold_ratio
+    }
+
+    /// Sets whether the adaptive threshold scans every other row from right to left (the default).
+    /// Alternating the direction spreads the lag of the running average over both sides of dark
+    /// regions, so rings are measured more symmetrically. When disabled, every row is scanned from
+    /// left to right, which makes the threshold map reproducible by tools that scan in a single
+    /// direction, but the lag always falls on the same side of every ring.
+    pub fn set_bidirectional(&mut self, bidirectional: bool) {
+        self.config.bidirectional = bidirectional;
+    }
+
+    /// Sets the number of samples taken across the diameter of a marker when reading each sector.
+    /// The default of 8 takes one sample in the middle of each ring of a TopCode. Multiples of the
+    /// marker width take several evenly spaced samples per ring and average them, which makes
+    /// large markers more robust to noise, at a proportional cost in decode time.
+    ///
+    /// The samples must still map onto the white, black and data rings, so when a marker is
+    /// decoded, the value is rounded down to a multiple of the width of its family, with at least
+    /// one sample per ring. The rounding depends on the family that is scanned, so the value is
+    /// kept as given, and [Scanner::config] reports it unrounded.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `samples` is 0.
+    pub fn set_radial_samples(&mut self, samples: usize) {
+        self.update_config(|config| config.radial_samples = samples);
+    }
+
+    pub(crate) fn radial_samples(&self) -> usize {
+        self.config.radial_samples
+    }
+
+    /// Sets the angle in radians by which every sector is supersampled. When positive, each sample
+    /// averages the ray through the sector with two more rays rotated by `±spread`, which reduces
+    /// aliasing on the data ring, especially for small markers whose rays run close to the pixel
+    /// axes. A spread of a fraction of a sector, such as 0.05, works well. Supersampling triples
+    /// the number of samples read per sector. Defaults to 0 (disabled).
+    ///
+    /// # Panics
+    ///
+    /// Panics if the spread is negative or not finite.
+    pub fn set_angular_spread(&mut self, spread: f64) {
+        self.update_config(|config| config.angular_spread = spread);
+    }
+
+    pub(crate) fn angular_spread(&self) -> f64 {
+        self.config.angular_spread
+    }
+
+    /// Sets whether the center of every code is estimated from the distances to the edges of its
+    /// bullseye to a fraction of a pixel. The center of a candidate is shifted by the difference
+    /// between the distances to the edges either side of it along both axes. By default, the
+    /// distances are counted in whole pixels, so the center of a code is only known to about a
+    /// third of a pixel. When enabled, each distance is refined by interpolating where the
+    /// averaged thresholded pixels around the edge cross midway between black and white, which
+    /// tracks markers that move by less than a pixel more smoothly. Disabled by default.
+    pub fn set_subpixel_centering(&mut self, subpixel: bool) {
+        self.config.subpixel_centering = subpixel;
+    }
+
+    pub(crate) fn subpixel_centering(&self) -> bool {
+        self.config.subpixel_centering
+    }
+
+    /// Sets how many times the decoder shifts the center of a candidate towards the middle of its
+    /// bullseye and measures the unit again when the ring distances around the first estimate are
+    /// too asymmetric. This rescues candidates whose center sample lands near a ring boundary, at
+    /// the cost of extra work for candidates that are rejected anyway. Defaults to 0 (disabled).
+    pub fn set_max_recenter_iterations(&mut self, iterations: usize) {
+        self.config.max_recenter_iterations = iterations;
+    }
+
+    pub(crate) fn max_recenter_iterations(&self) -> usize {
+        self.config.max_recenter_iterations
+    }
+
+    /// Sets whether the unit of a candidate may be estimated from three edges of its black bullseye
+    /// ring when the fourth is not found within the image or 100 pixels, e.g. because a cable or a
+    /// shadow crosses the code on one side. The criterion is relaxed to comparing the distance
+    /// opposite the missing edge, doubled, with the distances along the other axis, which must
+    /// agree within a unit. Disabled by default, which requires all four edges.
+    pub fn set_allow_missing_edge(&mut self, allow: bool) {
+        self.config.allow_missing_edge = allow;
+    }
+
+    pub(crate) fn allows_missing_edge(&self) -> bool {
+        self.config.allow_missing_edge
+    }
+
+    /// Sets whether a code with a single damaged sector is recovered, e.g. when a sticker peels
+    /// or a finger covers part of the marker. A sector is damaged if one of its bullseye ring
+    /// samples has the wrong color, which otherwise rejects the code, or if its data sample is
+    /// close to the threshold and the code fails the checksum. The bit of the damaged sector is
+    /// then tried both ways, and the code is accepted only if exactly one of them passes the
+    /// checksum of the family. The damaged sector adds nothing to [TopCode::confidence].
+    ///
+    /// Only a single sector can be recovered: codes with two or more damaged sectors are still
+    /// rejected, since the checksum of TopCodes, which counts the set bits, cannot tell which of
+    /// them to flip. Disabled by default.
+    pub fn set_sector_recovery(&mut self, recover: bool) {
+        self.config.recover_sector = recover;
+    }
+
+    pub(crate) fn recovers_sector(&self) -> bool {
+        self.config.recover_sector
+    }
+
+    /// Sets whether a candidate whose unit cannot be read from the black bullseye ring is still
+    /// decoded, e.g. when noise or something crossing the marker hides or shifts the edges of the
+    /// ring in more than one direction. The unit is then estimated from the distances from the
+    /// center to the edge of the white core of the bullseye, which is a unit wide either side of
+    /// the center, taking the median of the directions in which the edge is found. The code is
+    /// read with the units around the estimate as usual, and the best reading is kept, so a rough
+    /// estimate is enough. Disabled by default.
+    pub fn set_unit_recovery(&mut self, recover: bool) {
+        self.config.recover_unit = recover;
+    }
+
+    pub(crate) fn recovers_unit(&self) -> bool {
+        self.config.recover_unit
+    }
+
+    /// Sets the precision in radians to which the orientation of every code is refined, e.g. to
+    /// track the rotation of a dial. Codes are read at ten arc adjustments per sector and the most
+    /// confident reading is kept, but readings score about the same wherever the samples fall
+    /// within their sectors, so [TopCode::orientation] can be off by a good part of a sector. With
+    /// a precision, a golden-section search within half a sector either side of the best
+    /// adjustment then aligns the sectors with the bits that were read, sampling the whole span of
+    /// every sector. Each step of the search samples the data ring once more, so finer precisions
+    /// take a little longer. Defaults to [None] (no refinement).
+    ///
+    /// # Panics
+    ///
+    /// Panics if the precision is not positive and finite.
+    pub fn set_orientation_precision(&mut self, precision: Option<f64>) {
+        self.update_config(|config| config.orientation_precision = precision);
+    }
+
+    pub(crate) fn orientation_precision(&self) -> Option<f64> {
+        self.config.orientation_precision
+    }
+
+    /// Sets the largest difference between the horizontal and vertical diameters of the black
+    /// bullseye ring of a candidate, measured in units (see [TopCode::ring_asymmetry]). Candidates
+    /// that are more lopsided are rejected before decoding. Raising it accepts markers that are
+    /// more tilted away from the camera, at the cost of decoding more false positives, and
+    /// lowering it rejects more of both. Defaults to 1, i.e. the diameters may differ by one unit.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `units` is negative or not finite.
+    pub fn set_max_ring_asymmetry(&mut self, units: f64) {
+        self.update_config(|config| config.max_ring_asymmetry = units);
+    }
+
+    pub(crate) fn max_ring_asymmetry(&self) -> f64 {
+        self.config.max_ring_asymmetry
+    }
+
+    /// Sets whether the scanner learns the size of the markers from the first codes it decodes in
+    /// each scan. Once three codes are found, the median of their units is taken as the expected
+    /// unit, and the remaining candidates are only decoded if the widths of their rings along the
+    /// row match a unit within a factor of 1.5 of it. In a scene with many candidates, this skips
+    /// decoding the ones that are too large or small to be a marker, which saves time and drops
+    /// spurious codes.
+    ///
+    /// This assumes that all the markers in the scene appear at a similar size, e.g. markers of a
+    /// single print size seen from above. Markers of other sizes, or much closer to the camera,
+    /// are missed if they are found after the first three codes. Codes are found from the top of
+    /// the image down. The expected unit is learned again in every scan. It only applies to the
+    /// sequential scans; the parallel scans decode every candidate. Disabled by default.
+    pub fn set_auto_unit(&mut self, auto_unit: bool) {
+        self.config.auto_unit = auto_unit;
+    }
+
+    /// Sets whether the threshold of every pixel is kept in a buffer of its own after each scan,
+    /// which [Scanner::threshold_buffer] returns. The thresholded pixels only hold the running
+    /// sums the thresholds are computed from, so this is the way to reuse the threshold surface,
+    /// e.g. to compare it between frames or to render it, without recomputing it per pixel. It
+    /// costs a byte per pixel and a pass over the image per scan. Disabled by default.
+    pub fn set_keep_thresholds(&mut self, keep_thresholds: bool) {
+        self.update_config(|config| config.keep_thresholds = keep_thresholds);
+    }
+
+    /// Sets whether candidates of markers printed or seen at a low resolution, below 8 pixels per
+    /// unit, that fail to decode are decoded again from an upsampled copy of the image around them.
+    /// Once thresholded, the rings of such markers are only a few pixels wide, so the samples of
+    /// neighbouring rings overlap and the ring widths are lost to rounding. The region around the
+    /// candidate is instead upsampled four times with bilinear interpolation of the pixel
+    /// intensities, thresholded on its own and decoded there, which extends the distance at which a
+    /// camera reads the markers.
+    ///
+    /// Without upsampling, markers below about 3 pixels per unit are not decoded; with it, markers
+    /// of 2.5 pixels per unit are decoded reliably, and most down to 2. Thresholding the upsampled
+    /// region of a candidate costs about as much as thresholding a region of the image 16 times its
+    /// size, so a scene with many failing small candidates scans noticeably slower. Only
+    /// [Scanner::scan] upsamples, and `scan_par` with the `rayon` feature. Disabled by default.
+    pub fn set_upsample_small_markers(&mut self, upsample: bool) {
+        self.config.upsample_small_markers = upsample;
+    }
+
+    /// Sets the lens distortion of the camera, which is corrected when sampling the rings of each
+    /// candidate, instead of undistorting the whole image. Rings are then measured along straight
+    /// lines of the undistorted image, so codes near the corners of a wide-angle image still pass
+    /// the symmetry checks, and [TopCode::unit] is measured in undistorted pixels. Coordinates
+    /// are still reported in the image. Defaults to [None] (no correction).
+    ///
+    /// # Panics
+    ///
+    /// Panics if a coefficient of the distortion is not finite.
+    pub fn set_distortion(&mut self, distortion: Option<Distortion>) {
+        self.update_config(|config| config.distortion = distortion);
+    }
+
+    /// Sets the width of a pixel divided by its height, for sensors with non-square pixels on which
+    /// a circular marker appears as an ellipse. A ratio above 1 means the image is stretched
+    /// vertically. Vertical offsets are scaled by the ratio when sampling the rings of each
+    /// candidate, so [TopCode::unit] is always measured in horizontal pixels. Coordinates are still
+    /// reported in the image. Defaults to 1 (square pixels).
+    ///
+    /// This is a fixed correction for the whole image, applied after the lens distortion. It does
+    /// not account for markers that appear elliptical because they are viewed at an angle, which
+    /// would need the shape of each marker to be fitted instead.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the ratio is not positive and finite.
+    pub fn set_pixel_aspect_ratio(&mut self, ratio: f64) {
+        self.update_config(|config| config.pixel_aspect_ratio = ratio);
+    }
+
+    /// Maps a point of the image to the undistorted image with square pixels, if a distortion or
+    /// pixel aspect ratio is configured.
+    pub(crate) fn undistort(&self, x: f64, y: f64) -> (f64, f64) {
+        let (x, y) = match self.config.distortion {
+            Some(distortion) => distortion.undistort(self.dimensions(), x, y),
+            None => (x, y),
+        };
+        (x, y / self.config.pixel_aspect_ratio)
+    }
+
+    /// Maps a point of the undistorted image with square pixels back to the image, if a distortion
+    /// or pixel aspect ratio is configured.
+    pub(crate) fn distort(&self, x: f64, y: f64) -> (f64, f64) {
+        let y = y * self.config.pixel_aspect_ratio;
+        match self.config.distortion {
+            Some(distortion) => distortion.distort(self.dimensions(), x, y),
+            None => (x, y),
+        }
+    }
+
+    pub(crate) fn pixel_aspect_ratio(&self) -> f64 {
+        self.config.pixel_aspect_ratio
+    }
+
+    /// Approximate intensity of the image around (x, y), between 0 (black) and 255 (white). This is
+    /// the running average over the previous pixels of the scanline that the adaptive threshold
+    /// kept for this pixel, so it reflects the local brightness rather than the exact pixel value.
+    /// Only meaningful after a scan, and returns 0 for points outside the image.
+    pub fn intensity_at(&self, x: usize, y: usize) -> u8 {
+        let sum = self.pixel(x, y).map_or(0, PackedPixel::sum);
+        (sum / THRESHOLD_WINDOW as u32).min(0xff) as u8
+    }
+
+    /// Effective threshold of the last scan at (x, y): the pixel was classified black if its
+    /// intensity was below this value, and white otherwise. This is the local average of
+    /// [Scanner::intensity_at] over this row and the one above, multiplied by the
+    /// [threshold ratio](Scanner::set_threshold_ratio) (0.975 by default) and rounded up, so a
+    /// pixel only needs to be slightly darker than its surroundings to be black. Useful to see why
+    /// a pixel of a misread code was binarized the way it was.
+    ///
+    /// Saturates at 255, and returns 0 for points outside the image. After
+    /// [Scanner::scan_binary_image], the value only reflects the binarized image.
+    pub fn threshold_at(&self, x: usize, y: usize) -> u8 {
+        let threshold = self.adaptive_mean(x, y) as f64 * self.config.threshold_ratio;
+        threshold.ceil().min(255.0) as u8
+    }
+
+    /// Thresholds of the pixels of the last scan row by row, each as [Scanner::threshold_at]
+    /// returns it, if [kept](Scanner::set_keep_thresholds). Empty otherwise.
+    pub fn threshold_buffer(&self) -> &[u8] {
+        &self.thresholds
+    }
+
+    /// Returns the packed pixel at (x, y), or `None` if it is outside the image.
+    fn pixel(&self, x: usize, y: usize) -> Option<PackedPixel> {
+        if x >= self.width || y >= self.height {
+            return None;
+        }
+        self.data.get(y * self.width + x).copied()
+    }
+
+    /// Local average intensity the adaptive threshold compared the pixel at (x, y) against, before
+    /// applying the threshold ratio. Reconstructed from the running sums of this pixel and the one
+    /// above it, exactly as [Scanner::threshold] computes it.
+    /// Returns 0 for points outside the image.
+    pub(crate) fn adaptive_mean(&self, x: usize, y: usize) -> usize {
+        let sum = |y| self.pixel(x, y).map(|pixel| pixel.sum() as usize);
+        let s = THRESHOLD_WINDOW as usize;
+
+        match (sum(y), y.checked_sub(1).and_then(sum)) {
+            (Some(sum), Some(above)) => (sum + above) / (2 * s),
+            (Some(sum), None) => sum / s,
+            _ => 0,
+        }
+    }
+
+    /// Average of thresholded pixels in a 3x3 region around (x, y). Returned value is between 0
+    /// (black) and 255 (white).
+    pub(crate) fn get_sample_3x3(&self, x: usize, y: usize) -> usize {
+        if x < 1 || x + 1 >= self.width || y < 1 || y + 1 >= self.height {
+            return 0;
+        }
+
+        let mut sum = 0;
+        for j in y - 1..=y + 1 {
+            for i in x - 1..=x + 1 {
+                sum += 0xff * self.data[j * self.width + i].bit();
+            }
+        }
+
+        (sum / 9) as usize
+    }
+
+    /// Average of thresholded pixels in a 3x3 region around (x, y). Returned value is either 0
+    /// (black) or 1 (white).
+    pub(crate) fn get_bw_3x3(&self, x: usize, y: usize) -> u32 {
+        if x < 1 || x + 1 >= self.width || y < 1 || y + 1 >= self.height {
+            return 0;
+        }
+
+        let mut sum = 0;
+        for j in y - 1..=y + 1 {
+            for i in x - 1..=x + 1 {
+                sum += self.data[j * self.width + i].bit();
+            }
+        }
+
+        if sum >= 5 {
+            1
+        } else {
+            0
+        }
+    }
+
+    /// Perform Wellner adaptive thresholding to produce binary pixel data. Also mark candidate
+    /// SpotCode locations.
+    ///
+    /// "Adaptive Thresholding for the DigitalDesk"
+    /// EuroPARC Technical Report EPC-93-110
+    fn threshold<T: ?Sized>(
+        &mut self,
+        image_buffer: &T,
+        decode_rgb: impl Fn(&T, usize) -> (u32, u32, u32),
+    ) -> Vec<Candidate> {
+        let channel = self.config.channel;
+        self.threshold_intensity(|k| channel.intensity(decode_rgb(image_buffer, k)))
+    }
+
+    /// Thresholds the image like [Scanner::threshold], but computes the intensities of the pixels
+    /// of large images on the rayon thread pool first. Unlike the running sum, the intensities do
+    /// not depend on each other. On a single thread, the extra pass only costs time, so the pool
+    /// is only used when it has more than one thread.
+    #[cfg(feature = "rayon")]
+    fn threshold_par<T: ?Sized + Sync>(
+        &mut self,
+        image_buffer: &T,
+        decode_rgb: impl Fn(&T, usize) -> (u32, u32, u32) + Sync,
+    ) -> Vec<Candidate> {
+        use rayon::prelude::*;
+
+        if self.data.len() < PARALLEL_PACK_PIXELS || rayon::current_num_threads() < 2 {
+            return self.threshold(image_buffer, decode_rgb);
+        }
+
+        let channel = self.config.channel;
+        let intensities: Vec<u32> = (0..self.data.len())
+            .into_par_iter()
+            .map(|k| channel.intensity(decode_rgb(image_buffer, k)))
+            .collect();
+        self.threshold_intensity(|k| intensities[k])
+    }
+
+    /// Thresholds an image given the intensity (0-255) of the pixel at every index.
+    pub(crate) fn threshold_intensity(
+        &mut self,
+        intensity: impl Fn(usize) -> u32,
+    ) -> Vec<Candidate> {
+        self.stats = ScanStats::default();
+
+        // Images smaller than the smallest possible TopCode cannot contain any
+        if self.width < MIN_CODE_DIAMETER || self.height < MIN_CODE_DIAMETER {
+            return Vec::new();
+        }
+
+        let mut state = ThresholdState::default();
+        let mut data = std::mem::take(&mut self.data);
+        while state.row < self.height {
+            self.threshold_row(&mut data, 0, &mut state, &intensity);
+        }
+        self.data = data;
+
+        self.finish_threshold(state)
+    }
+
+    /// Thresholds horizontal bands of the image in parallel. Each band re-primes the running sum
+    /// by thresholding the [BAND_OVERLAP] rows above it first and discarding their results.
+    #[cfg(feature = "rayon")]
+    fn threshold_banded<T: ?Sized + Sync>(
+        &mut self,
+        image_buffer: &T,
+        decode_rgb: impl Fn(&T, usize) -> (u32, u32, u32) + Sync,
+        bands: usize,
+    ) -> Vec<Candidate> {
+        use rayon::prelude::*;
+
+        self.stats = ScanStats::default();
+
+        // Images smaller than the smallest possible TopCode cannot contain any
+        if self.width < MIN_CODE_DIAMETER || self.height < MIN_CODE_DIAMETER {
+            return Vec::new();
+        }
+
+        let width = self.width;
+        let band_rows = self.height.div_ceil(bands.max(1));
+        let mut data = std::mem::take(&mut self.data);
+
+        let states: Vec<ThresholdState> = data
+            .par_chunks_mut(band_rows * width)
+            .enumerate()
+            .map(|(band, chunk)| {
+                let start = band * band_rows;
+                let first = start.saturating_sub(BAND_OVERLAP);
+                let end = start + chunk.len() / width;
+
+                let mut rows = vec![PackedPixel::default(); (end - first) * width];
+                let mut state = ThresholdState {
+                    row: first,
+                    ..ThresholdState::default()
+                };
+                let channel = self.config.channel;
+                let decode = |k| channel.intensity(decode_rgb(image_buffer, k));
+                while state.row < start {
+                    self.threshold_row(&mut rows, first, &mut state, decode);
+                }
+                state.discard_results();
+                while state.row < end {
+                    self.threshold_row(&mut rows, first, &mut state, decode);
+                }
+
+                chunk.copy_from_slice(&rows[(start - first) * width..]);
+                state
+            })
+            .collect();
+
+        self.data = data;
+
+        let state = states
+            .into_iter()
+            .reduce(|mut merged, state| {
+                merged.append(state);
+                merged
+            })
+            .unwrap_or_default();
+        self.finish_threshold(state)
+    }
+
+    /// Thresholds the next row of the image, `state.row`, into `data`, which holds the rows of the
+    /// image from `first_row` on. The running sum carries over from the previous row, so rows must
+    /// be processed in order from the top.
+    fn threshold_row(
+        &self,
+        data: &mut [PackedPixel],
+        first_row: usize,
+        state: &mut ThresholdState,
+        intensity: impl Fn(usize) -> u32,
+    ) {
+        let s = THRESHOLD_WINDOW;
+        let j = state.row;
+        let forward = !self.config.bidirectional || j.is_multiple_of(2);
+
+        let mut pattern = RingPattern::default();
+
+        let mut k = if forward { 0 } else { self.width - 1 };
+        k += j * self.width;
+        let offset = first_row * self.width;
+
+        for i in 0..self.width {
+            // Calculate pixel intensity (0-255)
+            let mut a = intensity(k) as isize;
+            if a >= 0xff {
+                state.saturated_pixels += 1;
+            }
+
+            // Calculate the average sum as an approximate sum of the last s pixels
+            state.sum += a - (state.sum / s);
+
+            // Factor in sum from the previous row
+            let threshold = if k - offset >= self.width {
+                (state.sum + data[k - offset - self.width].sum() as isize) / (2 * s)
+            } else {
+                state.sum / s
+            };
+
+            // Compare the average sum to current pixel to decide black or white
+            a = if (a as f64) < (threshold as f64 * self.config.threshold_ratio) {
+                0
+            } else {
+                1
+            };
+
+            // Keep the binary value along with the running sum for the next row
+            data[k - offset] = PackedPixel::new(a == 1, state.sum);
+
+            if let Some(run) = pattern.push(a, self.effective_max_unit()) {
+                // Step back to the middle of the white region, staying on this row
+                let dk = run.step_back();
+                let x = if forward {
+                    i.checked_sub(dk)
+                } else {
+                    (self.width - 1 - i)
+                        .checked_add(dk)
+                        .filter(|&x| x < self.width)
+                };
+
+                if let Some(x) = x {
+                    state.add_candidate(x, j, &run);
+                }
+            }
+            if forward {
+                k += 1
+            } else {
+                k -= 1
+            };
+        }
+
+        state.row += 1;
+    }
+
+    fn finish_threshold(&mut self, mut state: ThresholdState) -> Vec<Candidate> {
+        if let Some(mask) = &self.ignore_mask {
+            let width = self.width;
+            state.candidates.retain(|c| !mask[c.y * width + c.x]);
+        }
+
+        self.stats = ScanStats {
+            pixels: self.width * self.height,
+            saturated_pixels: state.saturated_pixels,
+            candidates: state.candidates.len(),
+        };
+        #[cfg(feature = "diagnostics")]
+        {
+            self.candidate_features.clone_from(&state.candidates);
+        }
+        if self.config.keep_thresholds {
+            let mut thresholds = std::mem::take(&mut self.thresholds);
+            thresholds.clear();
+            thresholds.extend(
+                (0..self.width * self.height)
+                    .map(|i| self.threshold_at(i % self.width, i / self.width)),
+            );
+            self.thresholds = thresholds;
+        }
+
+        state.candidates
+    }
+
+    /// Scan the image line by line looking for TopCodes.
+    fn find_codes<C: CodeList>(&self, candidates: &[Candidate], family: &MarkerFamily) -> C {
+        self.find_codes_until(candidates, None, |c| self.decode_candidate(c, family))
+            .0
+    }
+
+    /// Decodes the candidates with `decode` like [Scanner::find_codes], stopping once the deadline,
+    /// if any, has passed. Also returns whether decoding was stopped by the deadline.
+    pub(crate) fn find_codes_until<C: CodeList>(
+        &self,
+        candidates: &[Candidate],
+        deadline: Option<Instant>,
+        decode: impl Fn(&Candidate) -> TopCode,
+    ) -> (C, bool) {
+        // Frames without markers, such as an idle camera, are done after thresholding
+        if candidates.is_empty() {
+            return (C::default(), false);
+        }
+
+        let mut spots = C::default();
+        let mut found = 0;
+        let mut expected_unit = None;
+        let mut truncated = false;
+
+        for (i, c) in candidates.iter().enumerate() {
+            if i.is_multiple_of(DEADLINE_CHECK_CANDIDATES)
+                && deadline.is_some_and(|deadline| Instant::now() >= deadline)
+            {
+                truncated = true;
+                break;
+            }
+            if expected_unit.is_some_and(|unit| !Self::matches_unit(c, unit)) {
+                continue;
+            }
+            let strategy = self.config.overlap_strategy;
+            let overlapping = self.overlaps(&spots, c.x, c.y);
+            if overlapping
+                && (strategy == OverlapStrategy::Suppress || self.in_bullseye(&spots, c.x, c.y))
+            {
+                continue;
+            }
+            let spot = decode(c);
+            match strategy {
+                OverlapStrategy::Suppress => {}
+                OverlapStrategy::KeepHigherConfidence => {
+                    if spot.is_valid() && spots.iter().any(|top| Self::intersect(top, &spot)) {
+                        if !self.accepts(&spot)
+                            || spots.iter().any(|top| {
+                                Self::intersect(top, &spot) && top.confidence >= spot.confidence
+                            })
+                        {
+                            continue;
+                        }
+                        spots.retain(|top| !Self::intersect(top, &spot));
+                        found = spots.iter().filter(|top| self.accepts(top)).count();
+                    }
+                }
+                OverlapStrategy::KeepBoth => {
+                    if overlapping && !self.distinct_overlap(&spots, &spot) {
+                        continue;
+                    }
+                }
+            }
+            if spot.is_valid() {
+                if self.accepts(&spot) {
+                    found += 1;
+                }
+                spots.push(spot);
+                if self.config.auto_unit
+                    && expected_unit.is_none()
+                    && spots.len() >= AUTO_UNIT_CODES
+                {
+                    expected_unit = Some(Self::median_unit(&spots));
+                }
+                if self.config.min_code_spacing.is_none()
+                    && self.config.max_codes.is_some_and(|max| found >= max)
+                {
+                    break;
+                }
+            }
+        }
+
+        (self.filter_codes(spots), truncated)
+    }
+
+    /// Returns true if the rings of a candidate measured along its row are within
+    /// [AUTO_UNIT_RANGE] of the given unit. The white run of a bullseye spans two units, and each
+    /// black run one. Candidates that were not measured always match.
+    fn matches_unit(candidate: &Candidate, unit: f64) -> bool {
+        let widths = candidate.b1 + candidate.w1 + candidate.b2;
+        if widths == 0 {
+            return true;
+        }
+        let ratio = widths as f64 / 4.0 / unit;
+        (1.0 / AUTO_UNIT_RANGE..=AUTO_UNIT_RANGE).contains(&ratio)
+    }
+
+    /// Median unit of the codes found so far.
+    fn median_unit(spots: &[TopCode]) -> f64 {
+        let mut units: Vec<f64> = spots.iter().map(|spot| spot.unit).collect();
+        units.sort_by(f64::total_cmp);
+        units[units.len() / 2]
+    }
+
+    /// Decodes the candidates across `threads` scoped threads and merges the results in candidate
+    /// order, so the output is identical to [Scanner::find_codes].
+    ///
+    /// Each thread only suppresses candidates that overlap codes it found itself. Candidates that
+    /// were skipped by a thread but do not overlap any code once merged are decoded on the calling
+    /// thread.
+    #[cfg(feature = "std-parallel")]
+    fn find_codes_parallel(
+        &self,
+        candidates: &[Candidate],
+        family: &MarkerFamily,
+        threads: usize,
+    ) -> Vec<TopCode> {
+        if candidates.is_empty() {
+            return Vec::new();
+        }
+
+        let chunk_size = candidates.len().div_ceil(threads.max(1)).max(1);
+
+        let decoded: Vec<Option<TopCode>> = std::thread::scope(|scope| {
+            let handles: Vec<_> = candidates
+                .chunks(chunk_size)
+                .map(|chunk| scope.spawn(move || self.decode_chunk(chunk, family)))
+                .collect();
+
+            handles
+                .into_iter()
+                .flat_map(|handle| handle.join().expect("Decode thread panicked"))
+                .collect()
+        });
+
+        let mut spots = Vec::with_capacity(candidates.len());
+
+        for (c, spot) in candidates.iter().zip(decoded) {
+            if !self.overlaps(&spots, c.x, c.y) {
+                let spot = spot.unwrap_or_else(|| self.decode_candidate(c, family));
+                if spot.is_valid() {
+                    spots.push(spot);
+                }
+            }
+        }
+
+        self.filter_codes(spots)
+    }
+
+    /// Decodes a slice of candidates, skipping those that overlap a code found earlier in the same
+    /// slice. Skipped candidates are returned as `None`.
+    #[cfg(feature = "std-parallel")]
+    fn decode_chunk(
+        &self,
+        candidates: &[Candidate],
+        family: &MarkerFamily,
+    ) -> Vec<Option<TopCode>> {
+        let mut spots = Vec::new();
+
+        candidates
+            .iter()
+            .map(|c| {
+                if self.overlaps(&spots, c.x, c.y) {
+                    return None;
+                }
+                let spot = self.decode_candidate(c, family);
+                if spot.is_valid() {
+                    spots.push(spot);
+                }
+                Some(spot)
+            })
+            .collect()
+    }
+
+    /// Drops the decoded codes that do not meet the configured criteria.
+    fn filter_codes<C: CodeList>(&self, mut spots: C) -> C {
+        spots.retain(|spot| self.accepts(spot));
+        if let Some(spacing) = self.config.min_code_spacing {
+            let keep = Self::spaced_codes(&spots, spacing);
+            let mut index = 0;
+            spots.retain(|_| {
+                index += 1;
+                keep[index - 1]
+            });
+        }
+        if let Some(max) = self.config.max_codes {
+            spots.truncate(max);
+        }
+        if self.config.center_origin {
+            let (cx, cy) = (self.width as f64 / 2.0, self.height as f64 / 2.0);
+            for spot in spots.iter_mut() {
+                spot.set_location(spot.x - cx, cy - spot.y);
+            }
+        }
+        spots
+    }
+
+    /// Flags the codes to keep so that no two kept codes are closer than `spacing`, preferring
+    /// codes with a higher confidence, then codes found earlier.
+    fn spaced_codes(spots: &[TopCode], spacing: f64) -> Vec<bool> {
+        let mut order: Vec<usize> = (0..spots.len()).collect();
+        order.sort_by(|&a, &b| spots[b].confidence.total_cmp(&spots[a].confidence));
+
+        let mut keep = vec![false; spots.len()];
+        for &i in &order {
+            let (x, y) = (spots[i].x, spots[i].y);
+            let crowded = (0..spots.len())
+                .any(|j| keep[j] && math::hypot(spots[j].x - x, spots[j].y - y) < spacing);
+            keep[i] = !crowded;
+        }
+        keep
+    }
+
+    /// Returns true if a valid code meets the configured criteria.
+    fn accepts(&self, spot: &TopCode) -> bool {
+        spot.confidence >= self.config.min_confidence
+    }
+
+    /// Decodes a single candidate. This only reads from the scanner, so it is safe to call from
+    /// multiple threads at once.
+    pub(crate) fn decode_candidate(&self, c: &Candidate, family: &MarkerFamily) -> TopCode {
+        let mut spot = TopCode::default();
+        spot.decode_family(self, c.x, c.y, family);
+        spot.scanline = Some(c.y);
+        spot
+    }
+
+    /// Returns true if (x, y) lies within a code that was already found. Symbols cannot overlap,
+    /// so such a candidate is another center of the same code, however large it is.
+    fn overlaps(&self, spots: &[TopCode], x: usize, y: usize) -> bool {
+        Region::contains(spots, x as f64, y as f64)
+    }
+
+    /// Returns true if the center of either code lies within the other.
+    fn intersect(a: &TopCode, b: &TopCode) -> bool {
+        a.covers(b.x, b.y) || b.covers(a.x, a.y)
+    }
+
+    /// Returns true if (x, y) lies within the bullseye of a code that was already found, whose
+    /// candidates are always centers of that code.
+    fn in_bullseye(&self, spots: &[TopCode], x: usize, y: usize) -> bool {
+        spots
+            .iter()
+            .any(|top| math::hypot(top.x - x as f64, top.y - y as f64) < top.unit * 2.0)
+    }
+
+    /// Returns true if a code decoded from a candidate inside codes that were already found is a
+    /// marker of its own: a valid code with enough confidence that differs from every code it
+    /// overlaps, centered outside their bullseyes.
+    fn distinct_overlap(&self, spots: &[TopCode], spot: &TopCode) -> bool {
+        spot.is_valid()
+            && self.accepts(spot)
+            && spots
+                .iter()
+                .filter(|top| top.covers(spot.x, spot.y))
+                .all(|top| {
+                    top.code != spot.code
+                        && math::hypot(top.x - spot.x, top.y - spot.y) >= top.unit * 2.0
+                })
+    }
+
+    /// Counts the number of pixels from (x, y) until a color change is perceived.
+    pub(crate) fn dist(&self, x: usize, y: usize, dx: isize, dy: isize) -> isize {
+        let start = self.get_bw_3x3(x, y);
+
+        let mut i = x as isize + dx;
+        let mut j = y as isize + dy;
+
+        loop {
+            if i <= 1 || i >= self.width as isize - 1 || j <= 1 || j >= self.height as isize {
+                break;
+            }
+
+            let sample = self.get_bw_3x3(i as usize, j as usize);
+            if start + sample == 1 {
+                let x_dist = (i - x as isize).abs();
+                let y_dist = (j - y as isize).abs();
+                return x_dist + y_dist;
+            }
+
+            i += dx;
+            j += dy;
+        }
+
+        -1
+    }
+
+    /// Distance from (x, y) to the color change found by [Scanner::dist], to a fraction of a
+    /// pixel. The 3x3 averages of the thresholded pixels (see [Scanner::get_sample_3x3]) just
+    /// before and at the change are interpolated linearly to find where they cross midway between
+    /// black and white. For a straight edge across the steps this is the boundary between the
+    /// last pixel of one color and the first of the other, half a step short of [Scanner::dist],
+    /// and a slanted or ragged edge shifts it by the share of pixels of either color around the
+    /// crossing. Returns `None` if no color change is perceived.
+    pub(crate) fn dist_subpixel(&self, x: usize, y: usize, dx: isize, dy: isize) -> Option<f64> {
+        let dist = self.dist(x, y, dx, dy);
+        if dist < 0 {
+            return None;
+        }
+
+        let step = dx.abs() + dy.abs();
+        let steps = dist / step;
+        let sample = |k: isize| {
+            let i = (x as isize + k * dx) as usize;
+            let j = (y as isize + k * dy) as usize;
+            self.get_sample_3x3(i, j) as f64
+        };
+        let (before, at) = (sample(steps - 1), sample(steps));
+        let t = if before == at {
+            0.5
+        } else {
+            ((127.5 - before) / (at - before)).clamp(0.0, 1.0)
+        };
+
+        Some((steps - 1) as f64 * step as f64 + t * step as f64)
+    }
+
+    #[cfg(feature = "visualize")]
+    pub fn write_thresholding_image(&self, path: &str) {
+        let img = GrayImage::from_fn(self.width as u32, self.height as u32, |x, y| {
+            let index = (y * self.width as u32 + x) as usize;
+            let a = self.data[index].bit() * 0xff;
+            image::Luma([a as u8])
+        });
+        img.save(path).expect("Failed to save png image");
+    }
+
+    /// Draws the [threshold](Scanner::threshold_at) of every pixel of the last scan as a heatmap,
+    /// from black for the darkest thresholds through purple, red and yellow to white for the
+    /// brightest. Lighting gradients, shadows and glare show up as bands of color, which helps
+    /// tell why the codes in some region are not found, e.g. because the threshold there is too
+    /// close to the intensity of the white rings.
+    #[cfg(feature = "visualize")]
+    pub fn threshold_heatmap(&self) -> RgbImage {
+        RgbImage::from_fn(self.width as u32, self.height as u32, |x, y| {
+            heatmap_color(self.threshold_at(x as usize, y as usize))
+        })
+    }
+
+    /// Draws how many of the candidates, e.g. from [Scanner::scan_candidates], mark every pixel,
+    /// with the colors of [Scanner::threshold_heatmap]. Each candidate marks the disc its rings
+    /// span along its row, as far as the outer edges of the black runs, so a bullseye shows up as
+    /// a bright spot where the candidates of neighbouring rows pile up. The most marked pixels are
+    /// white and unmarked pixels black. This shows where the detector's attention goes, e.g.
+    /// candidates scattered over a textured background that slow the scan down, or a marker that
+    /// yields no candidates at all.
+    #[cfg(feature = "visualize")]
+    pub fn candidate_heatmap(&self, candidates: &[Candidate]) -> RgbImage {
+        let mut counts = vec![0u32; self.width * self.height];
+        for c in candidates {
+            let radius = ((c.b1 + c.w1 + c.b2) as f64 / 2.0).max(0.5);
+            let reach = radius.ceil() as usize;
+            for y in c.y.saturating_sub(reach)..(c.y + reach + 1).min(self.height) {
+                for x in c.x.saturating_sub(reach)..(c.x + reach + 1).min(self.width) {
+                    let (dx, dy) = (x as f64 - c.x as f64, y as f64 - c.y as f64);
+                    if dx.hypot(dy) <= radius {
+                        counts[y * self.width + x] += 1;
+                    }
+                }
+            }
+        }
+
+        let max = counts.iter().copied().max().unwrap_or(0).max(1);
+        RgbImage::from_fn(self.width as u32, self.height as u32, |x, y| {
+            let count = counts[y as usize * self.width + x as usize];
+            heatmap_color((count * 255 / max) as u8)
+        })
+    }
+}
+
+/// Color of a threshold in the heatmap, interpolated linearly between [HEATMAP_STOPS].
+#[cfg(feature = "visualize")]
+fn heatmap_color(value: u8) -> Rgb<u8> {
+    let position = value as f64 / 255.0 * (HEATMAP_STOPS.len() - 1) as f64;
+    let index = (position as usize).min(HEATMAP_STOPS.len() - 2);
+    let t = position - index as f64;
+    let (from, to) = (HEATMAP_STOPS[index], HEATMAP_STOPS[index + 1]);
+    Rgb(std::array::from_fn(|channel| {
+        (from[channel] as f64 + (to[channel] as f64 - from[channel] as f64) * t).round() as u8
+    }))
+}
+
+impl fmt::Debug for Scanner {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // The pixel data is far too large to print, so only its size is shown
+        f.debug_struct("Scanner")
+            .field("width", &self.width)
+            .field("height", &self.height)
+            .field("pixels", &self.data.len())
+            .field("config", &self.config)
+            .field("stats", &self.stats)
+            .finish()
+    }
+}
 
 #[cfg(test)]
 mod test {
     use super::*;
-    use image::io::Reader as ImageReader;
+    use crate::test_utils::{assert_matches_fixture, render, render_sectors, rgb};
+    use image::ImageReader;
+
+    fn setup(asset_name: &str) -> (Scanner, Vec<u8>) {
+        let img = ImageReader::open(format!("assets/{}.png", asset_name))
+            .unwrap()
+            .decode()
+            .unwrap();
+        let (width, height) = (img.width() as usize, img.height() as usize);
+        let image_raw = img.into_rgb8().into_raw();
+        (Scanner::new(width, height), image_raw)
+    }
+
+    #[test]
+    fn it_can_scan_a_source_image_accurately() {
+        let (mut scanner, buffer) = setup("source");
+        let topcodes = scanner.scan(&buffer, |buffer, index| {
+            (
+                buffer[index * 3] as u32,
+                buffer[index * 3 + 1] as u32,
+                buffer[index * 3 + 2] as u32,
+            )
+        });
+
+        assert_matches_fixture("source", &topcodes);
+    }
+
+    #[test]
+    fn it_can_scan_a_photo_accurately() {
+        let (mut scanner, buffer) = setup("photo");
+        let topcodes = scanner.scan(&buffer, |buffer, index| {
+            (
+                buffer[index * 3] as u32,
+                buffer[index * 3 + 1] as u32,
+                buffer[index * 3 + 2] as u32,
+            )
+        });
+
+        assert_matches_fixture("photo", &topcodes);
+    }
+
+    #[test]
+    fn it_can_scan_synthetic_codes() {
+        let buffer = render(
+            200,
+            100,
+            &[(31, 50.0, 50.0, 5.0, 0.0), (93, 150.0, 50.0, 4.0, 1.0)],
+        );
+        let mut scanner = Scanner::new(200, 100);
+        let topcodes = scanner.scan(buffer.as_slice(), rgb);
+
+        let codes: Vec<_> = topcodes.iter().map(|topcode| topcode.code).collect();
+        assert_eq!(vec![Some(93), Some(31)], codes);
+    }
+
+    #[test]
+    fn it_can_scan_a_single_color_channel() {
+        // A red marker on green paper, whose channels average to the same intensity
+        let mut buffer = render(100, 100, &[(31, 50.0, 50.0, 5.0, 0.0)]);
+        for pixel in buffer.chunks_exact_mut(3) {
+            let color = if pixel[0] == 0 {
+                [0xff, 0, 0]
+            } else {
+                [0, 0xff, 0]
+            };
+            pixel.copy_from_slice(&color);
+        }
+        let mut scanner = Scanner::new(100, 100);
+        assert!(scanner.scan(buffer.as_slice(), rgb).is_empty());
+
+        scanner.set_channel(Channel::Green);
+        let topcodes = scanner.scan(buffer.as_slice(), rgb);
+
+        assert_eq!(1, topcodes.len());
+        assert_eq!(Some(31), topcodes[0].code);
+    }
+
+    #[test]
+    #[cfg(feature = "smallvec")]
+    fn small_scans_match_scans() {
+        let buffer = render(
+            200,
+            100,
+            &[(31, 50.0, 50.0, 6.0, 0.0), (93, 150.0, 50.0, 6.0, 1.0)],
+        );
+        let mut scanner = Scanner::new(200, 100);
+
+        let topcodes = scanner.scan(buffer.as_slice(), rgb);
+        let small = scanner.scan_small(buffer.as_slice(), rgb);
+
+        assert_eq!(2, small.len());
+        assert!(!small.spilled());
+        assert_eq!(topcodes.as_slice(), small.as_slice());
+    }
+
+    #[test]
+    fn it_can_scan_an_intensity_buffer() {
+        let buffer = render(
+            200,
+            100,
+            &[(31, 50.0, 50.0, 5.0, 0.0), (93, 150.0, 50.0, 4.0, 1.0)],
+        );
+        let intensity: Vec<u8> = buffer.chunks(3).map(|pixel| pixel[0]).collect();
+        let mut scanner = Scanner::new(200, 100);
+
+        let expected = scanner.scan(buffer.as_slice(), rgb);
+        assert_eq!(expected, scanner.scan_intensity(&intensity));
+    }
+
+    #[test]
+    #[should_panic(expected = "Intensity buffer length must be width * height")]
+    fn intensity_buffers_must_match_the_image() {
+        let mut scanner = Scanner::new(200, 100);
+        let _ = scanner.scan_intensity(&[0; 200]);
+    }
+
+    #[test]
+    fn codes_closer_than_the_minimum_spacing_are_suppressed() {
+        let buffer = render(
+            200,
+            100,
+            &[
+                (31, 50.0, 50.0, 5.0, 0.0),
+                (93, 95.0, 50.0, 5.0, 1.0),
+                (55, 160.0, 50.0, 5.0, 0.5),
+            ],
+        );
+        let mut scanner = Scanner::new(200, 100);
+        let topcodes = scanner.scan(buffer.as_slice(), rgb);
+        assert_eq!(3, topcodes.len());
+
+        scanner.set_min_code_spacing(Some(60.0));
+        let spaced = scanner.scan(buffer.as_slice(), rgb);
+
+        // Of the two close codes, the one read more confidently survives
+        let best = topcodes
+            .iter()
+            .filter(|topcode| topcode.code != Some(55))
+            .max_by(|a, b| a.confidence.total_cmp(&b.confidence))
+            .unwrap();
+        let mut codes: Vec<_> = spaced.iter().map(|topcode| topcode.code).collect();
+        codes.sort();
+        let mut expected = vec![best.code, Some(55)];
+        expected.sort();
+        assert_eq!(expected, codes);
+    }
+
+    #[test]
+    fn candidates_carry_their_ring_measurements() {
+        let buffer = render(
+            200,
+            100,
+            &[(31, 50.0, 50.0, 5.0, 0.0), (93, 150.0, 50.0, 4.0, 1.0)],
+        );
+        let mut scanner = Scanner::new(200, 100);
+
+        let candidates = scanner.scan_candidates(buffer.as_slice(), rgb);
+
+        assert_eq!(scanner.stats().candidates, candidates.len());
+        assert!(candidates.windows(2).all(|pair| pair[0].y <= pair[1].y));
+        // Through the middle of a bullseye, the row crosses the rings at their true width
+        let centered: Vec<_> = candidates
+            .iter()
+            .filter(|candidate| candidate.y == 50 && candidate.x % 100 == 50)
+            .collect();
+        assert_eq!(2, centered.len());
+        for candidate in &centered {
+            let unit = if candidate.x < 100 { 5 } else { 4 };
+            assert!(candidate.b1.abs_diff(unit) <= 1 && candidate.b2.abs_diff(unit) <= 1);
+            assert!(candidate.w1.abs_diff(2 * unit) <= 2);
+        }
+        let decoded = scanner.decode_at(centered[0].x, centered[0].y).unwrap();
+        assert_eq!(Some(31), decoded.code);
+    }
+
+    #[test]
+    fn codes_carry_the_row_of_their_candidate() {
+        let buffer = render(
+            200,
+            100,
+            &[(31, 50.0, 50.0, 5.0, 0.0), (93, 150.0, 40.0, 4.0, 1.0)],
+        );
+        let mut scanner = Scanner::new(200, 100);
+        let candidates = scanner.scan_candidates(buffer.as_slice(), rgb);
+
+        let topcodes = scanner.scan(buffer.as_slice(), rgb);
+
+        assert_eq!(2, topcodes.len());
+        for topcode in &topcodes {
+            // Candidates are decoded top-down, so a candidate above the center of the code
+            // confirms it
+            let row = topcode.scanline.unwrap();
+            assert!(candidates
+                .iter()
+                .any(|c| c.y == row && topcode.covers(c.x as f64, c.y as f64)));
+            assert!((row as f64) < topcode.y);
+        }
+
+        // The row stays in image coordinates
+        scanner.set_center_origin(true);
+        let centered = scanner.scan(buffer.as_slice(), rgb);
+        let rows = |codes: &[TopCode]| codes.iter().map(|t| t.scanline).collect::<Vec<_>>();
+        assert_eq!(rows(&topcodes), rows(&centered));
+        assert_eq!(Some(50), scanner.decode_at(50, 50).unwrap().scanline);
+        assert_eq!(None, TopCode::default().scanline);
+    }
+
+    #[test]
+    fn blank_images_have_no_candidates() {
+        let buffer = vec![0xc0; 200 * 100 * 3];
+        let mut scanner = Scanner::new(200, 100);
+
+        assert!(scanner.scan(buffer.as_slice(), rgb).is_empty());
+        assert_eq!(0, scanner.stats().candidates);
+    }
+
+    #[test]
+    fn it_can_decode_a_code_at_a_given_center() {
+        let buffer = render(
+            200,
+            100,
+            &[(31, 50.0, 50.0, 5.0, 0.0), (93, 150.0, 50.0, 4.0, 1.0)],
+        );
+        let mut scanner = Scanner::new(200, 100);
+        let topcodes = scanner.scan(buffer.as_slice(), rgb);
+
+        let decoded = scanner.decode_at(150, 50).unwrap();
+        assert_eq!(Some(93), decoded.code);
+        // Only the row the code was confirmed on differs from the scan
+        let scanned = topcodes.iter().find(|t| t.code == Some(93)).unwrap();
+        assert_eq!(
+            *scanned,
+            TopCode {
+                scanline: scanned.scanline,
+                ..decoded
+            }
+        );
+        assert_eq!(Some(31), scanner.decode_at(52, 49).unwrap().code);
+
+        // Blank paper and points outside the image
+        assert_eq!(None, scanner.decode_at(100, 10));
+        assert_eq!(None, scanner.decode_at(500, 50));
+    }
+
+    #[test]
+    fn blurred_codes_are_less_in_focus() {
+        let sharp = render(100, 100, &[(31, 50.0, 50.0, 8.0, 0.0)]);
+        let mut blurred = sharp.clone();
+        for y in 2..98 {
+            for x in 2..98 {
+                for channel in 0..3 {
+                    let mut sum = 0;
+                    for j in y - 2..=y + 2 {
+                        for i in x - 2..=x + 2 {
+                            sum += sharp[(j * 100 + i) * 3 + channel] as usize;
+                        }
+                    }
+                    blurred[(y * 100 + x) * 3 + channel] = (sum / 25) as u8;
+                }
+            }
+        }
+
+        let mut scanner = Scanner::new(100, 100);
+        let code = scanner.scan(sharp.as_slice(), rgb)[0];
+        let sharp_focus = code.focus(&scanner);
+        let code = scanner.scan(blurred.as_slice(), rgb)[0];
+        let blurred_focus = code.focus(&scanner);
+
+        assert!(sharp_focus <= 1.0 && blurred_focus > 0.0);
+        assert!(sharp_focus > blurred_focus * 1.2);
+    }
+
+    #[test]
+    fn it_can_estimate_the_unit_from_three_edges() {
+        // A thin black bar from the bullseye to the right border, like a cable, hides the right
+        // edge of the ring. The code is rotated so that the bar crosses a black sector of the data
+        // ring.
+        let arc = 2.0 * std::f64::consts::PI / 13.0;
+        let mut buffer = render(140, 140, &[(31, 70.0, 70.0, 8.0, -8.5 * arc)]);
+        for y in 69..=71 {
+            for x in 79..140 {
+                let index = (y * 140 + x) * 3;
+                buffer[index..index + 3].copy_from_slice(&[0; 3]);
+            }
+        }
+        let mut scanner = Scanner::new(140, 140);
+        assert!(scanner.scan(buffer.as_slice(), rgb).is_empty());
+
+        scanner.set_allow_missing_edge(true);
+        let topcodes = scanner.scan(buffer.as_slice(), rgb);
+
+        assert_eq!(1, topcodes.len());
+        assert_eq!(Some(31), topcodes[0].code);
+        assert!((topcodes[0].unit - 8.0).abs() < 1.0);
+    }
+
+    #[test]
+    fn it_can_estimate_the_unit_from_the_core() {
+        // A thin black bar across the whole marker, like a cable, hides the left and right edges
+        // of the ring
+        let mut buffer = render(140, 140, &[(55, 70.0, 70.0, 8.0, 0.4)]);
+        for y in 69..=71 {
+            for x in (0..62).chain(79..140) {
+                let index = (y * 140 + x) * 3;
+                buffer[index..index + 3].copy_from_slice(&[0; 3]);
+            }
+        }
+        let mut scanner = Scanner::new(140, 140);
+        scanner.set_allow_missing_edge(true);
+        assert!(scanner.scan(buffer.as_slice(), rgb).is_empty());
+
+        scanner.set_unit_recovery(true);
+        let topcodes = scanner.scan(buffer.as_slice(), rgb);
+
+        assert_eq!(1, topcodes.len());
+        assert_eq!(Some(55), topcodes[0].code);
+        assert!((topcodes[0].unit - 8.0).abs() < 1.0);
+    }
+
+    #[test]
+    fn stacked_markers_follow_the_overlap_strategy() {
+        // A small token resting on the data ring of a large one, with its center within the large
+        // one. The large token is found first, from the rows above.
+        let angle: f64 = 0.7;
+        let (x, y) = (70.0 + 28.0 * angle.cos(), 60.0 + 28.0 * angle.sin());
+        let buffer = render(
+            140,
+            120,
+            &[(31, 70.0, 60.0, 8.0, 0.2), (93, x, y, 3.0, 1.0)],
+        );
+        let mut scanner = Scanner::new(140, 120);
+        let topcodes = scanner.scan(buffer.as_slice(), rgb);
+        assert_eq!(1, topcodes.len());
+        assert_eq!(Some(31), topcodes[0].code);
+
+        scanner.set_overlap_strategy(OverlapStrategy::KeepBoth);
+        let both = scanner.scan(buffer.as_slice(), rgb);
+
+        assert_eq!(2, both.len());
+        assert_eq!(Some(31), both[0].code);
+        assert_eq!(Some(93), both[1].code);
+        assert!((both[1].x - x).abs() < 1.0 && (both[1].y - y).abs() < 1.0);
+
+        // Only the more confident of the two is kept
+        scanner.set_overlap_strategy(OverlapStrategy::KeepHigherConfidence);
+        let topcodes = scanner.scan(buffer.as_slice(), rgb);
+
+        let best = both
+            .iter()
+            .max_by(|a, b| a.confidence.total_cmp(&b.confidence))
+            .unwrap();
+        assert_eq!(1, topcodes.len());
+        assert_eq!(best.code, topcodes[0].code);
+
+        // Markers that do not overlap are still found once each
+        let buffer = render(
+            200,
+            100,
+            &[(31, 50.0, 50.0, 5.0, 0.0), (93, 150.0, 50.0, 4.0, 1.0)],
+        );
+        let mut scanner = Scanner::new(200, 100);
+        let expected = scanner.scan(buffer.as_slice(), rgb);
+        for strategy in [
+            OverlapStrategy::KeepHigherConfidence,
+            OverlapStrategy::KeepBoth,
+        ] {
+            scanner.set_overlap_strategy(strategy);
+            assert_eq!(
+                expected,
+                scanner.scan(buffer.as_slice(), rgb),
+                "{strategy:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn small_codes_next_to_each_other_are_all_found() {
+        // Symbols 24 pixels wide, 2 pixels apart
+        let buffer = render(
+            100,
+            60,
+            &[
+                (31, 24.0, 30.0, 3.0, 0.0),
+                (47, 50.0, 30.0, 3.0, 0.0),
+                (55, 76.0, 30.0, 3.0, 0.0),
+            ],
+        );
+        let mut scanner = Scanner::new(100, 60);
+        let mut codes: Vec<_> = scanner
+            .scan(buffer.as_slice(), rgb)
+            .iter()
+            .map(|topcode| topcode.code)
+            .collect();
+        codes.sort();
+
+        assert_eq!(vec![Some(31), Some(47), Some(55)], codes);
+    }
+
+    #[test]
+    fn candidates_overlap_codes_up_to_their_radius() {
+        let scanner = Scanner::new(300, 300);
+        let large = [TopCode::mock(31, 16.0, 0.0, 150.0, 150.0)];
+        let small = [TopCode::mock(31, 3.0, 0.0, 24.0, 30.0)];
+
+        // A candidate on the white ring of a large code is another center of the same code
+        assert!(scanner.overlaps(&large, 190, 150));
+        assert!(!scanner.overlaps(&large, 214, 150));
+
+        // The center of a small neighbour is not
+        assert!(scanner.overlaps(&small, 33, 30));
+        assert!(!scanner.overlaps(&small, 50, 30));
+    }
+
+    #[test]
+    fn it_can_correct_lens_distortion() {
+        // A code near the right edge of a wide-angle image, squeezed towards the center
+        let distortion = Distortion { k1: -0.3, k2: 0.0 };
+        let (width, height) = (400, 300);
+        let undistorted = render(width, height, &[(31, 340.0, 150.0, 6.0, 0.2)]);
+        let mut buffer = vec![0xff; width * height * 3];
+        for y in 0..height {
+            for x in 0..width {
+                let (ux, uy) = distortion.undistort((width, height), x as f64, y as f64);
+                let (ux, uy) = (ux.round() as usize, uy.round() as usize);
+                if ux < width && uy < height {
+                    let (i, j) = ((y * width + x) * 3, (uy * width + ux) * 3);
+                    buffer[i..i + 3].copy_from_slice(&undistorted[j..j + 3]);
+                }
+            }
+        }
+        let mut scanner = Scanner::new(width, height);
+        assert!(scanner.scan(buffer.as_slice(), rgb).is_empty());
+
+        scanner.set_distortion(Some(distortion));
+        let topcodes = scanner.scan(buffer.as_slice(), rgb);
+
+        assert_eq!(1, topcodes.len());
+        assert_eq!(Some(31), topcodes[0].code);
+        let (x, y) = distortion.distort((width, height), 340.0, 150.0);
+        assert!((topcodes[0].x - x).abs() < 2.0 && (topcodes[0].y - y).abs() < 2.0);
+    }
+
+    #[test]
+    fn it_can_correct_non_square_pixels() {
+        // A code seen by a sensor whose pixels are half as tall as they are wide
+        let (width, height) = (200, 100);
+        let square = render(width, height, &[(31, 100.0, 50.0, 6.0, 0.4)]);
+        let mut buffer = vec![0; width * height * 2 * 3];
+        for y in 0..height * 2 {
+            let (i, j) = (y * width * 3, y / 2 * width * 3);
+            buffer[i..i + width * 3].copy_from_slice(&square[j..j + width * 3]);
+        }
+        let mut scanner = Scanner::new(width, height * 2);
+        assert!(scanner.scan(buffer.as_slice(), rgb).is_empty());
+
+        scanner.set_pixel_aspect_ratio(2.0);
+        let topcodes = scanner.scan(buffer.as_slice(), rgb);
+
+        assert_eq!(1, topcodes.len());
+        assert_eq!(Some(31), topcodes[0].code);
+        assert!((topcodes[0].unit - 6.0).abs() < 0.5);
+        assert!((topcodes[0].x - 100.0).abs() < 2.0 && (topcodes[0].y - 100.0).abs() < 2.0);
+    }
+
+    #[test]
+    fn it_can_check_the_orientation_of_codes() {
+        let mut scanner = Scanner::new(100, 100);
+        scanner.set_check_orientation(true);
+
+        for angle in [0.0, 0.7, 2.0, 4.5] {
+            let buffer = render(100, 100, &[(55, 50.0, 50.0, 5.0, angle)]);
+            let topcodes = scanner.scan(buffer.as_slice(), rgb);
+
+            assert_eq!(Some(true), topcodes[0].orientation_consistent);
+
+            // An orientation off by a sector is measured back where the edge actually is
+            let mut shifted = topcodes[0];
+            shifted.orientation += 2.0 * std::f64::consts::PI / 13.0;
+            let measured = shifted.geometric_orientation(&scanner).unwrap();
+            assert!((measured - topcodes[0].orientation).abs() < 0.2);
+        }
+    }
+
+    #[test]
+    fn it_can_scan_a_different_marker_family() {
+        // Only accepts codes with exactly four 1 bits
+        let family = MarkerFamily {
+            checksum: |bits| bits.count_ones() == 4,
+            ..MarkerFamily::TOPCODE
+        };
+        let buffer = render(
+            200,
+            100,
+            &[(31, 50.0, 50.0, 5.0, 0.0), (15, 150.0, 50.0, 5.0, 0.0)],
+        );
+        let mut scanner = Scanner::new(200, 100);
+
+        let topcodes = scanner.scan_family(buffer.as_slice(), rgb, &family);
+        assert_eq!(
+            vec![Some(15)],
+            topcodes.iter().map(|t| t.code).collect::<Vec<_>>()
+        );
+
+        let topcodes = scanner.scan_family(buffer.as_slice(), rgb, &MarkerFamily::TOPCODE);
+        assert_eq!(topcodes, scanner.scan(buffer.as_slice(), rgb));
+        assert_eq!(Some(31), topcodes[0].code);
+    }
+
+    #[test]
+    fn data_ring_bits_can_be_mapped_to_codes() {
+        let family = MarkerFamily::TOPCODE.with_bit_mapping(MarkerFamily::from_gray);
+        // Gray codes of 31, 93 and 109, which have five 1 bits as the checksum requires
+        let rings = [16, 115, 91];
+        let buffer = render(
+            300,
+            100,
+            &[
+                (rings[0], 50.0, 50.0, 5.0, 0.0),
+                (rings[1], 150.0, 50.0, 5.0, 1.0),
+                (rings[2], 250.0, 50.0, 5.0, 2.0),
+            ],
+        );
+        let mut scanner = Scanner::new(300, 100);
+
+        let mut topcodes = scanner.scan_family(buffer.as_slice(), rgb, &family);
+        topcodes.sort_by(|a, b| a.x.total_cmp(&b.x));
+
+        // Each code is the number whose Gray code is printed on the ring
+        let codes: Vec<_> = topcodes.iter().filter_map(|t| t.code).collect();
+        assert_eq!(vec![31, 93, 109], codes);
+        for (code, ring) in codes.iter().zip(rings) {
+            assert_eq!(ring, code ^ (code >> 1));
+        }
+    }
+
+    #[test]
+    fn data_ring_bits_are_mapped_before_the_rotation_is_chosen() {
+        // Swaps sectors 0 and 7, which does not commute with rotating the ring
+        let family = MarkerFamily::TOPCODE.with_bit_mapping(|bits| {
+            let swapped = (bits ^ (bits >> 7)) & 1;
+            bits ^ (swapped | swapped << 7)
+        });
+        // Code 31 printed with the inverse mapping, whose lowest rotation is 79
+        let ring = 0b1001_1110;
+        let angles = [0.0, 1.0, 2.5, 4.0];
+        let markers: Vec<_> = angles
+            .iter()
+            .enumerate()
+            .map(|(i, &angle)| (ring, 50.0 + 100.0 * i as f64, 50.0, 5.0, angle))
+            .collect();
+        let buffer = render(400, 100, &markers);
+        let mut scanner = Scanner::new(400, 100);
+
+        let codes =
+            |topcodes: Vec<TopCode>| -> Vec<_> { topcodes.iter().map(|t| t.code).collect() };
+        let mapped = codes(scanner.scan_family(buffer.as_slice(), rgb, &family));
+        assert_eq!(vec![Some(31); angles.len()], mapped);
+        let unmapped = codes(scanner.scan(buffer.as_slice(), rgb));
+        assert_eq!(vec![Some(79); angles.len()], unmapped);
+    }
 
-    fn setup(asset_name: &str) -> (Scanner, Vec<u8>) {
-        let img = ImageReader::open(format!("assets/{}.png", asset_name))
-            .unwrap()
-            .decode()
-            .unwrap();
-        let (width, height) = (img.width() as usize, img.height() as usize);
-        let image_raw = img.into_rgb8().into_raw();
-        (Scanner::new(width, height), image_raw)
+    #[test]
+    fn worn_rings_are_less_uniform() {
+        let (width, height, unit) = (100, 100, 6.0);
+        let buffer = render(width, height, &[(31, 50.0, 50.0, unit, 0.3)]);
+        // Wear away the outer edge of the black ring on the right half of the marker
+        let mut worn = buffer.clone();
+        for y in 0..height {
+            for x in 53..width {
+                let r = (x as f64 - 50.0).hypot(y as f64 - 50.0) / unit;
+                if (1.6..2.0).contains(&r) {
+                    let i = (y * width + x) * 3;
+                    worn[i..i + 3].copy_from_slice(&[0xff; 3]);
+                }
+            }
+        }
+        let mut scanner = Scanner::new(width, height);
+
+        let clean = scanner.scan(buffer.as_slice(), rgb)[0];
+        let worn = scanner.scan(worn.as_slice(), rgb)[0];
+
+        assert_eq!(Some(31), worn.code);
+        assert!(clean.ring_uniformity > 0.95);
+        assert!(worn.ring_uniformity < clean.ring_uniformity - 0.05);
     }
 
     #[test]
-    fn it_can_scan_a_source_image_accurately() {
-        let (mut scanner, buffer) = setup("source");
-        let topcodes = scanner.scan(&buffer, |buffer, index| {
-            (
-                buffer[index * 3] as u32,
-                buffer[index * 3 + 1] as u32,
-                buffer[index * 3 + 2] as u32,
+    fn a_single_damaged_sector_can_be_recovered() {
+        let (width, height, unit, angle) = (100, 100, 6.0, 0.3);
+        let buffer = render(width, height, &[(55, 50.0, 50.0, unit, angle)]);
+        // Cover the white ring and the data ring of the given sectors, whose bits are all 1
+        let damage = |sectors: &[usize]| {
+            let arc = 2.0 * std::f64::consts::PI / 13.0;
+            let mut damaged = buffer.clone();
+            for y in 0..height {
+                for x in 0..width {
+                    let (dx, dy) = (x as f64 - 50.0, y as f64 - 50.0);
+                    let theta = (dy.atan2(dx) - angle).rem_euclid(2.0 * std::f64::consts::PI) / arc;
+                    let r = dx.hypot(dy) / unit;
+                    if (2.0..4.0).contains(&r)
+                        && sectors
+                            .iter()
+                            .any(|&s| (theta - s as f64 - 0.5).abs() < 0.35)
+                    {
+                        let i = (y * width + x) * 3;
+                        damaged[i..i + 3].copy_from_slice(&[0; 3]);
+                    }
+                }
+            }
+            damaged
+        };
+        let one = damage(&[1]);
+        let two = damage(&[1, 4]);
+        let mut scanner = Scanner::new(width, height);
+        let codes = |scanner: &mut Scanner, buffer: &[u8]| {
+            scanner
+                .scan(buffer, rgb)
+                .iter()
+                .map(|topcode| topcode.code)
+                .collect::<Vec<_>>()
+        };
+
+        assert!(!codes(&mut scanner, &one).contains(&Some(55)));
+
+        scanner.set_sector_recovery(true);
+        let clean = scanner.scan(buffer.as_slice(), rgb)[0];
+        let recovered = scanner.scan(one.as_slice(), rgb);
+        assert_eq!(1, recovered.len());
+        assert_eq!(Some(55), recovered[0].code);
+        assert!(recovered[0].confidence < clean.confidence);
+        assert!(!codes(&mut scanner, &two).contains(&Some(55)));
+    }
+
+    #[test]
+    fn subpixel_distances_interpolate_the_edge() {
+        // White left of column 30 on even rows and of column 31 on odd rows, so that the edge lies
+        // at 30.5 on average
+        let (width, height) = (60, 60);
+        let intensity: Vec<u8> = (0..width * height)
+            .map(|i| {
+                let (x, y) = (i % width, i / width);
+                if x < 30 + y % 2 {
+                    0xff
+                } else {
+                    0
+                }
+            })
+            .collect();
+        let mut scanner = Scanner::new(width, height);
+        let _ = scanner.scan_intensity(&intensity);
+
+        // The 3x3 samples around an even row span edges at 31, 30 and 31, and those around an odd
+        // row at 30, 31 and 30, so the boundary lies a third of a pixel either side of 30.5
+        assert_eq!(11, scanner.dist(20, 30, 1, 0));
+        assert_eq!(10, scanner.dist(20, 31, 1, 0));
+        let even = scanner.dist_subpixel(20, 30, 1, 0).unwrap();
+        let odd = scanner.dist_subpixel(20, 31, 1, 0).unwrap();
+        assert!((even - 10.17).abs() < 0.02, "{even}");
+        assert!((odd - 9.83).abs() < 0.02, "{odd}");
+        let back = scanner.dist_subpixel(40, 30, -1, 0).unwrap();
+        assert!((back - 9.83).abs() < 0.02, "{back}");
+
+        assert_eq!(None, scanner.dist_subpixel(20, 30, 0, -1));
+    }
+
+    #[test]
+    fn kept_thresholds_match_the_scan() {
+        let buffer = render(
+            200,
+            100,
+            &[(31, 50.0, 50.0, 5.0, 0.0), (93, 150.0, 50.0, 4.0, 1.0)],
+        );
+        let mut scanner = Scanner::new(200, 100);
+        let expected = scanner.scan(buffer.as_slice(), rgb);
+        assert!(scanner.threshold_buffer().is_empty());
+
+        scanner.set_keep_thresholds(true);
+        assert_eq!(expected, scanner.scan(buffer.as_slice(), rgb));
+
+        let thresholds = scanner.threshold_buffer();
+        assert_eq!(200 * 100, thresholds.len());
+        for (i, &threshold) in thresholds.iter().enumerate() {
+            assert_eq!(scanner.threshold_at(i % 200, i / 200), threshold);
+        }
+
+        scanner.set_keep_thresholds(false);
+        assert!(scanner.threshold_buffer().is_empty());
+
+        // Turning them off with the rest of the config drops them as well
+        let kept = ScannerConfig {
+            keep_thresholds: true,
+            ..ScannerConfig::default()
+        };
+        let base = scanner.memory_usage();
+        for turn_off in [
+            |scanner: &mut Scanner| scanner.reset_config(),
+            |scanner: &mut Scanner| scanner.set_config(ScannerConfig::default()),
+        ] {
+            scanner.set_config(kept);
+            let _ = scanner.scan(buffer.as_slice(), rgb);
+            assert!(!scanner.threshold_buffer().is_empty());
+
+            turn_off(&mut scanner);
+            assert!(scanner.threshold_buffer().is_empty());
+            assert_eq!(base, scanner.memory_usage());
+        }
+    }
+
+    #[test]
+    fn memory_usage_is_dominated_by_the_thresholded_image() {
+        let mut scanner = Scanner::new(200, 100);
+        let base = scanner.memory_usage();
+        assert!(base >= 200 * 100 * 4);
+
+        scanner.set_keep_thresholds(true);
+        scanner.ignore_rect(0, 0, 10, 10);
+        let buffer = render(200, 100, &[(31, 50.0, 50.0, 5.0, 0.0)]);
+        let _ = scanner.scan(buffer.as_slice(), rgb);
+
+        // A byte per pixel each for the mask and the thresholds, and the sector directions
+        assert!(scanner.memory_usage() >= base + 2 * 200 * 100 + 13 * 16);
+    }
+
+    #[test]
+    fn subpixel_centering_locates_codes_more_precisely() {
+        // Total distance from the true center of codes shifted by tenths of a pixel
+        let error = |subpixel: bool| -> f64 {
+            let mut scanner = Scanner::new(100, 100);
+            scanner.set_subpixel_centering(subpixel);
+            let mut error = 0.0;
+            for unit in [5.0, 6.5] {
+                for i in 0..10 {
+                    let (x, y) = (50.0 + i as f64 * 0.1, 49.0 + i as f64 * 0.07);
+                    let buffer = render(100, 100, &[(31, x, y, unit, 0.3)]);
+                    let topcodes = scanner.scan(buffer.as_slice(), rgb);
+                    assert_eq!(1, topcodes.len());
+                    error += (topcodes[0].x - x).hypot(topcodes[0].y - y);
+                }
+            }
+            error
+        };
+
+        assert!(!ScannerConfig::default().subpixel_centering);
+        assert!(error(true) < 0.8 * error(false));
+    }
+
+    #[test]
+    fn decoding_stops_at_the_deadline() {
+        let buffer = render(
+            200,
+            100,
+            &[(31, 50.0, 50.0, 5.0, 0.0), (93, 150.0, 50.0, 4.0, 1.0)],
+        );
+        let mut scanner = Scanner::new(200, 100);
+        let expected = scanner.scan(buffer.as_slice(), rgb);
+
+        let (topcodes, truncated) =
+            scanner.scan_with_deadline(buffer.as_slice(), rgb, Duration::from_secs(60));
+        assert_eq!(expected, topcodes);
+        assert!(!truncated);
+
+        let (topcodes, truncated) =
+            scanner.scan_with_deadline(buffer.as_slice(), rgb, Duration::ZERO);
+        assert!(topcodes.is_empty());
+        assert!(truncated);
+
+        // Nothing is cut short when there is nothing to decode
+        let blank = vec![0xff; 200 * 100 * 3];
+        let (topcodes, truncated) =
+            scanner.scan_with_deadline(blank.as_slice(), rgb, Duration::ZERO);
+        assert!(topcodes.is_empty() && !truncated);
+    }
+
+    #[test]
+    fn ignored_pixels_hold_no_candidates() {
+        let buffer = render(
+            200,
+            100,
+            &[(31, 50.0, 50.0, 5.0, 0.0), (93, 150.0, 50.0, 5.0, 0.0)],
+        );
+        let mut scanner = Scanner::new(200, 100);
+        let codes = |scanner: &mut Scanner| {
+            let mut codes: Vec<_> = scanner
+                .scan(buffer.as_slice(), rgb)
+                .iter()
+                .map(|topcode| topcode.code.unwrap())
+                .collect();
+            codes.sort();
+            codes
+        };
+
+        // Covering the center of the first code is enough, and the rest of the mask may fall
+        // outside the image
+        scanner.ignore_rect(40, 40, 20, 20);
+        scanner.ignore_rect(190, 90, 50, 50);
+        assert_eq!(vec![93], codes(&mut scanner));
+        assert!(scanner.stats().candidates > 0);
+
+        let mut mask = vec![false; 200 * 100];
+        mask[80 * 200..].fill(true);
+        scanner.set_ignore_mask(&mask);
+        assert_eq!(vec![31, 93], codes(&mut scanner));
+        mask.fill(true);
+        scanner.set_ignore_mask(&mask);
+        assert!(codes(&mut scanner).is_empty());
+        assert_eq!(0, scanner.stats().candidates);
+
+        scanner.clear_ignore_mask();
+        assert_eq!(vec![31, 93], codes(&mut scanner));
+    }
+
+    #[test]
+    #[should_panic(expected = "Ignore mask length must be width * height")]
+    fn ignore_masks_must_cover_the_image() {
+        Scanner::new(200, 100).set_ignore_mask(&[false; 100]);
+    }
+
+    #[test]
+    fn auto_unit_skips_markers_of_another_size() {
+        let buffer = render(
+            300,
+            200,
+            &[
+                (31, 50.0, 30.0, 5.0, 0.0),
+                (47, 150.0, 32.0, 5.5, 0.0),
+                (55, 250.0, 34.0, 4.5, 0.0),
+                (59, 100.0, 100.0, 5.0, 0.0),
+                (93, 200.0, 150.0, 10.0, 0.0),
+            ],
+        );
+        let mut scanner = Scanner::new(300, 200);
+        let codes = |scanner: &mut Scanner| {
+            let mut codes: Vec<_> = scanner
+                .scan(buffer.as_slice(), rgb)
+                .iter()
+                .map(|topcode| topcode.code.unwrap())
+                .collect();
+            codes.sort();
+            codes
+        };
+
+        assert_eq!(vec![31, 47, 55, 59, 93], codes(&mut scanner));
+
+        scanner.set_auto_unit(true);
+        assert_eq!(vec![31, 47, 55, 59], codes(&mut scanner));
+    }
+
+    #[test]
+    fn it_can_scan_markers_with_more_sectors() {
+        let family = MarkerFamily::with_sectors(15, |bits| bits.count_ones() == 6);
+        // Six 1 bits, and the lowest of its rotations
+        let code = 0b000_0011_0001_0111;
+        let buffer = render_sectors(200, 100, 15, &[(code, 60.0, 50.0, 6.0, 0.5)]);
+        let mut scanner = Scanner::new(200, 100);
+
+        let topcodes = scanner.scan_family(buffer.as_slice(), rgb, &family);
+
+        assert_eq!(1, topcodes.len());
+        assert_eq!(Some(code), topcodes[0].code);
+        assert!((topcodes[0].x - 60.0).abs() < 1.0 && (topcodes[0].y - 50.0).abs() < 1.0);
+    }
+
+    #[test]
+    fn codes_can_keep_any_number_of_samples() {
+        let buffer = render(100, 100, &[(31, 50.0, 50.0, 5.0, 0.0)]);
+        let mut scanner = Scanner::new(100, 100);
+        let expected = scanner.scan(buffer.as_slice(), rgb)[0];
+
+        let mut wide = TopCode::<12>::empty();
+        assert_eq!(Some(31), wide.decode(&scanner, 50, 50));
+        assert_eq!(expected.core, wide.core[..8]);
+        assert_eq!([0; 4], wide.core[8..]);
+
+        let mut narrow = TopCode::<4>::empty();
+        assert_eq!(Some(31), narrow.decode(&scanner, 50, 50));
+        assert_eq!(expected.core[..4], narrow.core);
+        assert_eq!(expected.unit, narrow.unit);
+    }
+
+    #[test]
+    fn it_handles_codes_at_the_image_boundary() {
+        let (width, height) = (120, 90);
+        for (x, y) in [
+            (0.0, 45.0),
+            (8.0, 45.0),
+            (22.0, 45.0),
+            (119.0, 45.0),
+            (98.0, 45.0),
+            (60.0, 0.0),
+            (60.0, 21.0),
+            (60.0, 89.0),
+            (60.0, 69.0),
+            (0.0, 0.0),
+            (119.0, 89.0),
+        ] {
+            let buffer = render(width, height, &[(31, x, y, 5.0, 0.0)]);
+            let mut scanner = Scanner::new(width, height);
+            let topcodes = scanner.scan(buffer.as_slice(), rgb);
+
+            for topcode in topcodes {
+                assert_eq!(Some(31), topcode.code);
+                assert!(topcode.x < width as f64 && topcode.y < height as f64);
+            }
+        }
+    }
+
+    #[test]
+    fn it_can_change_the_config_between_scans() {
+        let (mut scanner, buffer) = setup("photo");
+        let expected = scanner.scan(&buffer, |buffer, index| rgb(buffer, index));
+        assert_eq!(3, expected.len());
+
+        // The codes in the photo have units of about 20 pixels
+        let config = ScannerConfig {
+            max_unit: 10,
+            ..*scanner.config()
+        };
+        scanner.set_config(config);
+        assert_eq!(&config, scanner.config());
+        assert!(scanner
+            .scan(&buffer, |buffer, index| rgb(buffer, index))
+            .is_empty());
+
+        scanner.reset_config();
+        assert_eq!(
+            expected,
+            scanner.scan(&buffer, |buffer, index| rgb(buffer, index))
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "Invalid scanner config")]
+    fn it_rejects_invalid_configs() {
+        let mut scanner = Scanner::new(100, 100);
+        scanner.set_config(ScannerConfig {
+            radial_samples: 0,
+            ..ScannerConfig::default()
+        });
+    }
+
+    #[test]
+    #[should_panic(expected = "Invalid scanner config")]
+    fn setters_reject_invalid_values() {
+        let mut scanner = Scanner::new(100, 100);
+        scanner.set_pixel_aspect_ratio(-1.0);
+    }
+
+    #[test]
+    fn debug_output_omits_pixel_data() {
+        let scanner = Scanner::new(640, 480);
+
+        assert_eq!((640, 480), scanner.dimensions());
+        let debug = format!("{:?}", scanner);
+        assert!(
+            debug.starts_with(
+                "Scanner { width: 640, height: 480, pixels: 307200, config: ScannerConfig { max_unit: 80"
             )
+        );
+        assert!(debug.len() < 1000);
+    }
+
+    #[test]
+    fn it_reports_the_saturated_fraction() {
+        let (mut scanner, buffer) = setup("photo");
+        let _ = scanner.scan(buffer.as_slice(), rgb);
+        assert!(scanner.stats().saturated_fraction() < 0.1);
+        assert!(scanner.stats().candidates > 0);
+
+        let buffer = render(100, 100, &[(31, 50.0, 50.0, 5.0, 0.0)]);
+        let mut scanner = Scanner::new(100, 100);
+        let _ = scanner.scan(buffer.as_slice(), rgb);
+        let fraction = scanner.stats().saturated_fraction();
+        assert!(fraction > 0.5 && fraction < 1.0);
+    }
+
+    #[test]
+    fn it_scans_rows_fed_one_at_a_time() {
+        let (mut scanner, buffer) = setup("photo");
+        let expected = scanner.scan(&buffer, |buffer, index| rgb(buffer, index));
+        let stats = *scanner.stats();
+
+        // Feed the same image twice to check that the scanner resets in between
+        for _ in 0..2 {
+            for row in buffer.chunks(scanner.image_width() * 3) {
+                scanner.feed_row(row);
+            }
+            assert_eq!(expected, scanner.finish());
+            assert_eq!(&stats, scanner.stats());
+        }
+    }
+
+    #[test]
+    fn it_skips_rows_of_images_too_small_for_a_code() {
+        for (width, height) in [(0, 2), (8, 40), (40, 8)] {
+            let mut scanner = Scanner::new(width, height);
+            for _ in 0..height {
+                scanner.feed_row(&vec![0; width * 3]);
+            }
+            assert!(scanner.finish().is_empty());
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "Every row must be fed first")]
+    fn it_cannot_finish_a_partially_fed_image() {
+        let mut scanner = Scanner::new(20, 20);
+        scanner.feed_row(&[0; 60]);
+        let _ = scanner.finish();
+    }
+
+    #[test]
+    fn it_can_threshold_every_row_in_the_same_direction() {
+        let buffer = render(
+            200,
+            100,
+            &[(93, 50.0, 50.0, 5.0, 0.0), (31, 150.0, 50.0, 6.0, 1.0)],
+        );
+        let mut scanner = Scanner::new(200, 100);
+        scanner.set_bidirectional(false);
+        let topcodes = scanner.scan(buffer.as_slice(), rgb);
+
+        let codes: Vec<_> = topcodes.iter().map(|topcode| topcode.code).collect();
+        assert_eq!(vec![Some(31), Some(93)], codes);
+
+        // Odd rows are scanned the other way around by default, which changes the binary map
+        let mut bidirectional = Scanner::new(200, 100);
+        let _ = bidirectional.scan(buffer.as_slice(), rgb);
+        assert_ne!(bidirectional.data, scanner.data);
+    }
+
+    #[test]
+    fn it_stops_after_the_maximum_number_of_codes() {
+        let buffer = render(
+            300,
+            100,
+            &[
+                (31, 50.0, 40.0, 5.0, 0.0),
+                (93, 150.0, 50.0, 5.0, 0.0),
+                (55, 250.0, 60.0, 5.0, 0.0),
+            ],
+        );
+        let mut scanner = Scanner::new(300, 100);
+        let all = scanner.scan(buffer.as_slice(), rgb);
+        assert_eq!(3, all.len());
+
+        for max in 0..=3 {
+            scanner.set_max_codes(Some(max));
+            assert_eq!(&all[..max], scanner.scan(buffer.as_slice(), rgb));
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "visualize")]
+    fn it_can_scan_a_binary_image() {
+        let buffer = render(
+            200,
+            100,
+            &[(31, 50.0, 50.0, 5.0, 0.0), (93, 150.0, 50.0, 4.0, 1.0)],
+        );
+        let img = GrayImage::from_fn(200, 100, |x, y| {
+            let (r, _, _) = rgb(&buffer, (y * 200 + x) as usize);
+            image::Luma([if r > 0x80 { 0xff } else { 0 }])
         });
+        let mut scanner = Scanner::new(200, 100);
+        let topcodes = scanner.scan_binary_image(&img);
 
+        let codes: Vec<_> = topcodes.iter().map(|topcode| topcode.code).collect();
+        assert_eq!(vec![Some(31), Some(93)], codes);
+        assert_eq!(255, scanner.intensity_at(5, 5));
+    }
+
+    #[test]
+    #[cfg(feature = "visualize")]
+    fn threshold_heatmaps_show_lighting_gradients() {
+        // Lit from the right
+        let (width, height) = (120, 40);
+        let buffer: Vec<u8> = (0..width * height)
+            .flat_map(|i| {
+                let v = (i % width * 2 + 10) as u8;
+                [v, v, v]
+            })
+            .collect();
+        let mut scanner = Scanner::new(width, height);
+        let _ = scanner.scan(buffer.as_slice(), rgb);
+
+        let heatmap = scanner.threshold_heatmap();
+
+        assert_eq!((120, 40), heatmap.dimensions());
         assert_eq!(
-            topcodes,
-            vec![
-                TopCode {
-                    code: Some(55),
-                    unit: 48.8125,
-                    orientation: -0.07249829200591831,
-                    x: 1803.0,
-                    y: 878.0,
-                    core: [0, 255, 0, 255, 255, 0, 255, 255]
-                },
-                TopCode {
-                    code: Some(31),
-                    unit: 48.675,
-                    orientation: -0.07249829200591831,
-                    x: 618.0,
-                    y: 923.0,
-                    core: [0, 255, 0, 255, 255, 0, 255, 255]
-                },
-                TopCode {
-                    code: Some(93),
-                    unit: 39.825,
-                    orientation: -0.07249829200591831,
-                    x: 1275.3333333333333,
-                    y: 1704.0,
-                    core: [56, 255, 0, 255, 255, 0, 255, 255]
-                }
-            ]
+            heatmap_color(scanner.threshold_at(60, 20)),
+            heatmap[(60, 20)]
         );
+        let (dark, bright) = (heatmap[(20, 20)], heatmap[(110, 20)]);
+        assert!(dark[0] < bright[0] && dark[1] < bright[1]);
+        assert_eq!(Rgb([0, 0, 0]), heatmap_color(0));
+        assert_eq!(Rgb([255, 255, 255]), heatmap_color(255));
     }
 
     #[test]
-    fn it_can_scan_a_photo_accurately() {
+    #[cfg(feature = "visualize")]
+    fn candidate_heatmaps_show_where_candidates_pile_up() {
+        let buffer = render(200, 100, &[(31, 50.0, 50.0, 5.0, 0.0)]);
+        let mut scanner = Scanner::new(200, 100);
+        let candidates = scanner.scan_candidates(buffer.as_slice(), rgb);
+        assert!(candidates.len() > 1);
+
+        let heatmap = scanner.candidate_heatmap(&candidates);
+
+        assert_eq!((200, 100), heatmap.dimensions());
+        assert_eq!(Rgb([0, 0, 0]), heatmap[(150, 50)]);
+        assert_ne!(Rgb([0, 0, 0]), heatmap[(50, 50)]);
+        assert!(heatmap.pixels().any(|&pixel| pixel == Rgb([255, 255, 255])));
+
+        // Unmeasured candidates only mark their own pixel
+        let heatmap = scanner.candidate_heatmap(&[Candidate::new(10, 10)]);
+        assert_eq!(Rgb([255, 255, 255]), heatmap[(10, 10)]);
+        assert_eq!(Rgb([0, 0, 0]), heatmap[(11, 10)]);
+    }
+
+    #[test]
+    fn thresholds_explain_the_binarization_of_every_pixel() {
+        let (width, height) = (120, 80);
+        let buffer: Vec<u8> = (0..width * height)
+            .flat_map(|i| {
+                let v = ((i * 7919) % 97 + (i % width) + 20) as u8;
+                [v, v, v]
+            })
+            .collect();
+        let mut scanner = Scanner::new(width, height);
+        let _ = scanner.scan(buffer.as_slice(), rgb);
+
+        for y in 0..height {
+            for x in 0..width {
+                let intensity = buffer[(y * width + x) * 3];
+                let white = scanner.pixel(x, y).unwrap().is_white();
+                assert_eq!(white, intensity >= scanner.threshold_at(x, y));
+            }
+        }
+        assert_eq!(0, scanner.threshold_at(width, 0));
+    }
+
+    #[test]
+    #[cfg(all(feature = "visualize", feature = "rayon"))]
+    fn binary_images_are_packed_the_same_in_parallel() {
+        let (width, height) = (800, 400);
+        let codes = [(31, 100.0, 100.0, 8.0, 0.0), (93, 600.0, 300.0, 6.0, 1.0)];
+        let buffer = render(width, height, &codes);
+        let img = GrayImage::from_fn(width as u32, height as u32, |x, y| {
+            let (r, _, _) = rgb(&buffer, y as usize * width + x as usize);
+            image::Luma([r as u8])
+        });
+        let mut scanner = Scanner::new(width, height);
+
+        let serial = scanner.pack_binary_image(&img, false);
+        let serial_data = scanner.data.clone();
+        let parallel = scanner.pack_binary_image(&img, true);
+
+        assert_eq!(serial_data, scanner.data);
+        let positions = |state: &ThresholdState| -> Vec<(usize, usize)> {
+            state.candidates.iter().map(|c| (c.x, c.y)).collect()
+        };
+        assert!(!serial.candidates.is_empty());
+        assert_eq!(positions(&serial), positions(&parallel));
+        assert_eq!(2, scanner.scan_binary_image(&img).len());
+    }
+
+    #[test]
+    #[cfg(feature = "visualize")]
+    #[should_panic(expected = "Image dimensions must match the scanner")]
+    fn it_rejects_binary_images_of_the_wrong_size() {
+        let mut scanner = Scanner::new(200, 100);
+        let _ = scanner.scan_binary_image(&GrayImage::new(100, 200));
+    }
+
+    #[test]
+    fn it_can_take_several_samples_per_ring() {
+        let (mut scanner, buffer) = setup("photo");
+        let codes = |topcodes: Vec<TopCode>| -> Vec<_> {
+            topcodes.iter().map(|topcode| topcode.code).collect()
+        };
+        let expected = codes(scanner.scan(&buffer, |buffer, index| rgb(buffer, index)));
+
+        for samples in [24, 12, 4] {
+            scanner.set_radial_samples(samples);
+            let topcodes = scanner.scan(&buffer, |buffer, index| rgb(buffer, index));
+            assert_eq!(expected, codes(topcodes));
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "diagnostics")]
+    fn it_reports_the_ring_measurements_of_candidates() {
+        let buffer = render(100, 100, &[(31, 50.0, 50.0, 5.0, 0.0)]);
+        let mut scanner = Scanner::new(100, 100);
+        let _ = scanner.scan(buffer.as_slice(), rgb);
+
+        let features = scanner.candidate_features();
+        assert_eq!(scanner.stats().candidates, features.len());
+
+        // The row through the center crosses the black ring, the white center and the black ring
+        let center = features.iter().find(|f| (f.x, f.y) == (50, 50)).unwrap();
+        assert!(center.b1.abs_diff(5) <= 1 && center.b2.abs_diff(5) <= 1);
+        assert!(center.w1.abs_diff(10) <= 1);
+    }
+
+    #[test]
+    fn it_limits_the_maximum_unit_to_the_image_size() {
+        // Stripes 20 pixels wide look like rings, but only codes of units up to 13 fit
+        let stripes = |width: usize, height: usize| -> Vec<u8> {
+            (0..width * height)
+                .flat_map(|i| {
+                    [if (i % width / 20).is_multiple_of(2) {
+                        0xff
+                    } else {
+                        0
+                    }; 3]
+                })
+                .collect()
+        };
+
+        let mut scanner = Scanner::new(100, 100);
+        scanner.set_max_code_diameter(10_000);
+        let _ = scanner.scan(stripes(100, 100).as_slice(), rgb);
+        assert_eq!(0, scanner.stats().candidates);
+
+        let mut scanner = Scanner::new(400, 400);
+        scanner.set_max_code_diameter(10_000);
+        let _ = scanner.scan(stripes(400, 400).as_slice(), rgb);
+        assert!(scanner.stats().candidates > 0);
+    }
+
+    #[test]
+    #[cfg(feature = "rayon")]
+    fn it_can_threshold_bands_in_parallel() {
         let (mut scanner, buffer) = setup("photo");
+        let expected = scanner.scan(&buffer, |buffer, index| rgb(buffer, index));
+        let binary = |scanner: &Scanner| -> Vec<u32> {
+            scanner.data.iter().map(|pixel| pixel.bit()).collect()
+        };
+        let expected_binary = binary(&scanner);
+
+        for bands in [1, 3, 8] {
+            let topcodes = scanner.scan_banded(buffer.as_slice(), rgb, bands);
+            assert_eq!(expected, topcodes);
+            assert_eq!(expected_binary, binary(&scanner));
+        }
+    }
+
+    #[test]
+    fn decoders_need_not_be_sync() {
+        let buffer = render(100, 80, &[(31, 50.0, 40.0, 5.0, 0.0)]);
+        let mut scanner = Scanner::new(100, 80);
+        let decoded = std::cell::Cell::new(0);
+
         let topcodes = scanner.scan(&buffer, |buffer, index| {
+            decoded.set(decoded.get() + 1);
+            rgb(buffer, index)
+        });
+
+        assert_eq!(Some(31), topcodes[0].code);
+        assert!(decoded.get() >= 100 * 80);
+    }
+
+    #[test]
+    #[cfg(feature = "rayon")]
+    fn intensities_can_be_computed_in_parallel() {
+        let (mut scanner, buffer) = setup("photo");
+        let expected = scanner.scan(&buffer, |buffer, index| rgb(buffer, index));
+        let expected_data = scanner.data.clone();
+
+        let pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(2)
+            .build()
+            .unwrap();
+        let topcodes =
+            pool.install(|| scanner.scan_par(&buffer, |buffer, index| rgb(buffer, index)));
+
+        assert_eq!(expected, topcodes);
+        assert!(expected_data == scanner.data);
+    }
+
+    #[test]
+    fn it_can_return_coordinates_relative_to_the_center() {
+        let buffer = render(200, 100, &[(31, 50.0, 30.0, 5.0, 0.0)]);
+        let mut scanner = Scanner::new(200, 100);
+        let expected = scanner.scan(buffer.as_slice(), rgb);
+
+        scanner.set_center_origin(true);
+        let topcodes = scanner.scan(buffer.as_slice(), rgb);
+
+        assert_eq!(expected[0].x - 100.0, topcodes[0].x);
+        assert_eq!(50.0 - expected[0].y, topcodes[0].y);
+        assert!(topcodes[0].x < 0.0 && topcodes[0].y > 0.0);
+        assert_eq!(expected[0].orientation, topcodes[0].orientation);
+    }
+
+    #[test]
+    fn it_can_supersample_each_sector() {
+        let (mut scanner, buffer) = setup("photo");
+        let codes = |topcodes: Vec<TopCode>| -> Vec<_> {
+            topcodes.iter().map(|topcode| topcode.code).collect()
+        };
+        let expected = codes(scanner.scan(&buffer, |buffer, index| rgb(buffer, index)));
+
+        scanner.set_angular_spread(0.05);
+        let topcodes = scanner.scan(&buffer, |buffer, index| rgb(buffer, index));
+        assert_eq!(expected, codes(topcodes));
+
+        let buffer = render(
+            200,
+            100,
+            &[(31, 50.0, 50.0, 3.0, 0.0), (93, 150.0, 50.0, 3.0, 1.0)],
+        );
+        let mut scanner = Scanner::new(200, 100);
+        scanner.set_angular_spread(0.05);
+        let topcodes = scanner.scan(buffer.as_slice(), rgb);
+        assert_eq!(vec![Some(31), Some(93)], codes(topcodes));
+    }
+
+    #[test]
+    fn it_recenters_candidates_that_land_off_center() {
+        let buffer = render(100, 100, &[(31, 50.0, 50.0, 6.0, 0.0)]);
+        let mut scanner = Scanner::new(100, 100);
+        let _ = scanner.scan(buffer.as_slice(), rgb);
+
+        // A center sample inside the black ring measures lopsided ring distances
+        let decode = |scanner: &Scanner| {
+            TopCode::default().decode_family(scanner, 61, 50, &MarkerFamily::TOPCODE)
+        };
+        assert_eq!(None, decode(&scanner));
+
+        scanner.set_max_recenter_iterations(3);
+        assert_eq!(Some(31), decode(&scanner));
+    }
+
+    #[test]
+    fn it_finds_nothing_in_images_too_small_to_contain_a_code() {
+        for size in [0, 1, 10] {
+            let mut scanner = Scanner::new(size, size);
+            let buffer: Vec<u8> = (0..size * size * 3).map(|i| (i % 2 * 255) as u8).collect();
+            let topcodes = scanner.scan(&buffer, |buffer, index| {
+                (
+                    buffer[index * 3] as u32,
+                    buffer[index * 3 + 1] as u32,
+                    buffer[index * 3 + 2] as u32,
+                )
+            });
+
+            assert!(topcodes.is_empty());
+        }
+    }
+
+    #[test]
+    fn it_drops_codes_below_the_minimum_confidence() {
+        // A slightly blurred ring boundary lowers the confidence without breaking the checksum
+        let mut buffer = render(100, 100, &[(31, 50.0, 50.0, 5.0, 0.2)]);
+        for pixel in buffer.chunks_mut(3).skip(100 * 44).take(100) {
+            pixel.copy_from_slice(&[0x80; 3]);
+        }
+        let mut scanner = Scanner::new(100, 100);
+        let topcodes = scanner.scan(buffer.as_slice(), rgb);
+        assert_eq!(1, topcodes.len());
+
+        let confidence = topcodes[0].confidence;
+        assert!(confidence > 0.0 && confidence < 1.0);
+
+        scanner.set_min_confidence(confidence);
+        assert_eq!(topcodes, scanner.scan(buffer.as_slice(), rgb));
+
+        scanner.set_min_confidence(confidence + 0.001);
+        assert!(scanner.scan(buffer.as_slice(), rgb).is_empty());
+    }
+
+    #[test]
+    fn it_does_not_decode_outside_an_empty_image() {
+        let scanner = Scanner::new(0, 0);
+        let mut topcode = TopCode::default();
+
+        assert_eq!(None, topcode.decode(&scanner, 0, 0));
+    }
+
+    #[cfg(feature = "std-parallel")]
+    #[test]
+    fn it_finds_the_same_codes_when_decoding_in_parallel() {
+        let (mut scanner, buffer) = setup("photo");
+        let decode = |buffer: &Vec<u8>, index: usize| {
             (
                 buffer[index * 3] as u32,
                 buffer[index * 3 + 1] as u32,
                 buffer[index * 3 + 2] as u32,
             )
-        });
+        };
 
-        assert_eq!(
-            topcodes,
-            vec![
-                TopCode {
-                    code: Some(55),
-                    unit: 22.44375,
-                    orientation: -0.07249829200591831,
-                    x: 996.8333333333334,
-                    y: 493.5,
-                    core: [0, 255, 0, 255, 255, 0, 255, 255]
-                },
-                TopCode {
-                    code: Some(31),
-                    unit: 22.91875,
-                    orientation: 0.024166097335306114,
-                    x: 366.5,
-                    y: 510.0,
-                    core: [0, 255, 0, 255, 255, 0, 255, 255]
-                },
-                TopCode {
-                    code: Some(93),
-                    unit: 21.15,
-                    orientation: -0.07249829200591831,
-                    x: 718.8333333333334,
-                    y: 929.5,
-                    core: [113, 255, 0, 255, 255, 0, 255, 255]
-                }
-            ]
-        );
+        let serial = scanner.scan(&buffer, decode);
+        for threads in 1..=4 {
+            assert_eq!(serial, scanner.scan_parallel(&buffer, decode, threads));
+        }
     }
 }
@@ -0,0 +1,203 @@
+use crate::{
+    homography::compute_homography,
+    scanner::Scanner,
+    topcode::{Code, TopCode},
+};
+
+/// Result of checking the markers detected on a printed sheet against its layout with
+/// [validate_sheet]. Every list follows the order of the layout.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct SheetReport {
+    /// Codes detected within the tolerance of their position
+    pub found: Vec<Code>,
+    /// Codes detected further than the tolerance from their position, with the distance in grid
+    /// units
+    pub misplaced: Vec<(Code, f64)>,
+    /// Positions where a code other than the expected one was detected, as `(expected, detected)`
+    pub misprinted: Vec<(Code, Code)>,
+    /// Codes that were not detected, and whose position holds no other code either
+    pub missing: Vec<Code>,
+}
+
+impl SheetReport {
+    /// Returns true if every marker of the sheet was found in its position.
+    pub fn is_valid(&self) -> bool {
+        self.misplaced.is_empty() && self.misprinted.is_empty() && self.missing.is_empty()
+    }
+}
+
+impl Scanner {
+    /// Scans a photo of a printed sheet of markers and checks the codes found against the layout
+    /// of the sheet with [validate_sheet].
+    pub fn scan_sheet<T: ?Sized>(
+        &mut self,
+        image_buffer: &T,
+        decode_rgb: impl Fn(&T, usize) -> (u32, u32, u32),
+        layout: &[(Code, (f64, f64))],
+        tolerance: f64,
+    ) -> Option<SheetReport> {
+        let detected = self.scan(image_buffer, decode_rgb);
+        validate_sheet(layout, &detected, tolerance)
+    }
+}
+
+/// Checks the markers detected in a photo of a printed sheet against the layout of the sheet, e.g.
+/// to catch misprints before handing the markers out. `layout` gives the expected code at each
+/// position of the sheet in grid units, such as `(column, row)`, and `tolerance` is the distance in
+/// grid units a marker may be off its position.
+///
+/// The photo does not need to be taken straight on: the homography from the image to the grid is
+/// fitted to the markers of the layout that were detected (see [compute_homography]), leaving out
+/// the markers that do not fit the others while at least four remain, and every detection is
+/// mapped to the grid before it is compared. A detection of an unexpected code, or of
+/// a code that was already found, is reported as misprinted if it lies on the position of a
+/// marker that was not detected, and is ignored otherwise.
+///
+/// Returns `None` if fewer than four markers of the layout were detected, or if they do not
+/// determine the homography, since the photo cannot be related to the sheet then.
+pub fn validate_sheet(
+    layout: &[(Code, (f64, f64))],
+    detected: &[TopCode],
+    tolerance: f64,
+) -> Option<SheetReport> {
+    let distance = |(x, y): (f64, f64), (u, v): (f64, f64)| (x - u).hypot(y - v);
+    let project = |h: &[f64; 9], topcode: &TopCode| {
+        let (x, y) = (topcode.x, topcode.y);
+        let w = h[6] * x + h[7] * y + h[8];
+        (
+            (h[0] * x + h[1] * y + h[2]) / w,
+            (h[3] * x + h[4] * y + h[5]) / w,
+        )
+    };
+
+    // Leave out the marker that lies furthest off the homography fitted to the others until
+    // every marker fits, so that a misplaced marker does not skew the positions of the others
+    let mut anchors: Vec<(Code, &TopCode, (f64, f64))> = layout
+        .iter()
+        .filter_map(|&(code, position)| {
+            let topcode = detected.iter().find(|topcode| topcode.code == Some(code))?;
+            Some((code, topcode, position))
+        })
+        .collect();
+    let fit = |anchors: &[(Code, &TopCode, (f64, f64))], skip: Option<usize>| {
+        let correspondences: Vec<_> = anchors
+            .iter()
+            .enumerate()
+            .filter(|&(i, _)| Some(i) != skip)
+            .map(|(_, &(code, _, position))| (code, position))
+            .collect();
+        compute_homography(&correspondences, detected)
+    };
+    while anchors.len() > 4 {
+        let worst = (0..anchors.len())
+            .filter_map(|i| {
+                let (_, topcode, position) = anchors[i];
+                let h = fit(&anchors, Some(i))?;
+                Some((i, distance(project(&h, topcode), position)))
+            })
+            .max_by(|(_, a), (_, b)| a.total_cmp(b));
+        match worst {
+            Some((i, off)) if off > tolerance => {
+                anchors.remove(i);
+            }
+            _ => break,
+        }
+    }
+    let h = fit(&anchors, None)?;
+    let to_grid = |topcode: &TopCode| project(&h, topcode);
+
+    let mut report = SheetReport::default();
+    let mut matched = vec![false; detected.len()];
+    let mut placed = vec![false; layout.len()];
+    for (i, &(code, position)) in layout.iter().enumerate() {
+        let Some(j) = detected
+            .iter()
+            .position(|topcode| topcode.code == Some(code))
+        else {
+            continue;
+        };
+        matched[j] = true;
+        placed[i] = true;
+        let off = distance(to_grid(&detected[j]), position);
+        if off <= tolerance {
+            report.found.push(code);
+        } else {
+            report.misplaced.push((code, off));
+        }
+    }
+
+    for (i, &(code, position)) in layout.iter().enumerate() {
+        if placed[i] {
+            continue;
+        }
+        let other = detected.iter().enumerate().find(|&(j, topcode)| {
+            !matched[j]
+                && topcode.code.is_some()
+                && distance(to_grid(topcode), position) <= tolerance
+        });
+        match other {
+            Some((j, topcode)) => {
+                matched[j] = true;
+                report
+                    .misprinted
+                    .push((code, topcode.code.unwrap_or_default()));
+            }
+            None => report.missing.push(code),
+        }
+    }
+
+    Some(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{render, rgb};
+
+    #[test]
+    fn reports_the_markers_that_differ_from_the_layout() {
+        let codes = [31, 47, 55, 59, 61, 79, 87, 91, 93];
+        let layout: Vec<_> = codes
+            .iter()
+            .enumerate()
+            .map(|(i, &code)| (code, ((i % 3) as f64, (i / 3) as f64)))
+            .collect();
+
+        // A sheet printed with 79 shifted by a third of a cell, 87 misprinted as 103 and 93 left
+        // out, photographed with 60 pixels between markers
+        let pixels = |(column, row): (f64, f64)| (40.0 + 60.0 * column, 40.0 + 60.0 * row);
+        let mut printed = Vec::new();
+        for &(code, position) in &layout {
+            let (x, y) = pixels(position);
+            match code {
+                79 => printed.push((79, x, y + 20.0, 5.0, 0.3)),
+                87 => printed.push((103, x, y, 5.0, 0.3)),
+                93 => {}
+                code => printed.push((code, x, y, 5.0, 0.3)),
+            }
+        }
+        let buffer = render(200, 200, &printed);
+        let mut scanner = Scanner::new(200, 200);
+
+        let report = scanner
+            .scan_sheet(buffer.as_slice(), rgb, &layout, 0.2)
+            .unwrap();
+
+        assert!(!report.is_valid());
+        assert_eq!(vec![31, 47, 55, 59, 61, 91], report.found);
+        assert_eq!(1, report.misplaced.len());
+        assert_eq!(79, report.misplaced[0].0);
+        assert!((report.misplaced[0].1 - 1.0 / 3.0).abs() < 0.1);
+        assert_eq!(vec![(87, 103)], report.misprinted);
+        assert_eq!(vec![93], report.missing);
+    }
+
+    #[test]
+    fn needs_enough_markers_to_locate_the_sheet() {
+        let layout = [(31, (0.0, 0.0)), (47, (1.0, 0.0)), (55, (0.0, 1.0))];
+        let detected =
+            layout.map(|(code, (x, y))| TopCode::mock(code, 5.0, 0.0, x * 60.0, y * 60.0));
+
+        assert_eq!(None, validate_sheet(&layout, &detected, 0.2));
+    }
+}
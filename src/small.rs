@@ -0,0 +1,176 @@
+use crate::{
+    candidate::Candidate, config::ScannerConfig, family::MarkerFamily, scanner::Scanner,
+    topcode::TopCode,
+};
+
+/// Unit in pixels below which a candidate that fails to decode is decoded again from an upsampled
+/// region, see [Scanner::set_upsample_small_markers].
+const SMALL_MARKER_UNIT: f64 = 8.0;
+
+/// Factor by which the region around a small candidate is upsampled along each axis.
+const UPSAMPLING: usize = 4;
+
+/// Radius in units of the region upsampled around a small candidate. A TopCode spans 4 units either
+/// side of its center, and the rest leaves the adaptive threshold room to settle.
+const REGION_UNITS: f64 = 6.0;
+
+impl Scanner {
+    /// Decodes the candidates like [Scanner::find_codes], decoding the small candidates that fail
+    /// again from an upsampled region of the image.
+    pub(crate) fn find_small_codes<T: ?Sized>(
+        &self,
+        image_buffer: &T,
+        decode_rgb: impl Fn(&T, usize) -> (u32, u32, u32),
+        candidates: &[Candidate],
+    ) -> Vec<TopCode> {
+        let family = &MarkerFamily::TOPCODE;
+        self.find_codes_until(candidates, None, |c| {
+            let spot = self.decode_candidate(c, family);
+            match small_unit(c) {
+                Some(unit) if !spot.is_valid() => {
+                    self.decode_upsampled(image_buffer, &decode_rgb, c, unit)
+                }
+                _ => spot,
+            }
+        })
+        .0
+    }
+
+    /// Upsamples the region around a candidate, thresholds it and decodes the candidate there. The
+    /// returned code is mapped back to the coordinates and unit of the image.
+    fn decode_upsampled<T: ?Sized>(
+        &self,
+        image_buffer: &T,
+        decode_rgb: impl Fn(&T, usize) -> (u32, u32, u32),
+        c: &Candidate,
+        unit: f64,
+    ) -> TopCode {
+        let (width, height) = self.dimensions();
+        let reach = (unit * REGION_UNITS).ceil() as usize + 1;
+        let (left, top) = (c.x.saturating_sub(reach), c.y.saturating_sub(reach));
+        let (right, bottom) = ((c.x + reach).min(width - 1), (c.y + reach).min(height - 1));
+        let (region_width, region_height) = (right - left + 1, bottom - top + 1);
+
+        let channel = self.config().channel;
+        let intensities: Vec<f64> = (top..=bottom)
+            .flat_map(|y| (left..=right).map(move |x| (x, y)))
+            .map(|(x, y)| channel.intensity(decode_rgb(image_buffer, y * width + x)) as f64)
+            .collect();
+
+        // Pixel centers of the upsampled region fall between those of the image, so that the
+        // region covers exactly the same area
+        let k = UPSAMPLING as f64;
+        let source = |i: usize, size: usize| {
+            let s = ((i as f64 + 0.5) / k - 0.5).clamp(0.0, (size - 1) as f64);
+            let low = (s.floor() as usize).min(size.saturating_sub(2));
+            (low, (low + 1).min(size - 1), s - low as f64)
+        };
+        let upsampled_width = region_width * UPSAMPLING;
+        let upsampled_height = region_height * UPSAMPLING;
+        let mut patch = Scanner::new(upsampled_width, upsampled_height);
+        patch.set_config(ScannerConfig {
+            max_unit: self.config().max_unit * UPSAMPLING,
+            max_codes: None,
+            min_code_spacing: None,
+            center_origin: false,
+            keep_thresholds: false,
+            upsample_small_markers: false,
+            distortion: None,
+            ..*self.config()
+        });
+        let _ = patch.threshold_intensity(|index| {
+            let (x0, x1, tx) = source(index % upsampled_width, region_width);
+            let (y0, y1, ty) = source(index / upsampled_width, region_height);
+            let at = |x, y| intensities[y * region_width + x];
+            let upper = at(x0, y0) * (1.0 - tx) + at(x1, y0) * tx;
+            let lower = at(x0, y1) * (1.0 - tx) + at(x1, y1) * tx;
+            (upper * (1.0 - ty) + lower * ty).round() as u32
+        });
+
+        let center = Candidate::new(
+            (c.x - left) * UPSAMPLING + UPSAMPLING / 2,
+            (c.y - top) * UPSAMPLING + UPSAMPLING / 2,
+        );
+        let mut spot = patch.decode_candidate(&center, &MarkerFamily::TOPCODE);
+        if spot.is_valid() {
+            spot.x = left as f64 + (spot.x + 0.5) / k - 0.5;
+            spot.y = top as f64 + (spot.y + 0.5) / k - 0.5;
+            spot.unit /= k;
+            spot.scanline = Some(c.y);
+        }
+        spot
+    }
+}
+
+/// Unit of a candidate estimated from its rings along the row, if it is below
+/// [SMALL_MARKER_UNIT]. The white run of a bullseye spans two units, and each black run one.
+fn small_unit(c: &Candidate) -> Option<f64> {
+    let unit = (c.b1 + c.w1 + c.b2) as f64 / 4.0;
+    (unit > 0.0 && unit < SMALL_MARKER_UNIT).then_some(unit)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{render, rgb};
+
+    /// Renders markers at four times the size and averages every 4x4 block of pixels, like a
+    /// camera sensor seeing the markers from afar.
+    fn render_far(width: usize, height: usize, codes: &[(u32, f64, f64, f64, f64)]) -> Vec<u8> {
+        let near: Vec<_> = codes
+            .iter()
+            .map(|&(code, x, y, unit, angle)| (code, x * 4.0, y * 4.0, unit * 4.0, angle))
+            .collect();
+        let near = render(width * 4, height * 4, &near);
+        let mut far = vec![0; width * height * 3];
+        for (i, pixel) in far.iter_mut().enumerate() {
+            let (x, y, channel) = (i / 3 % width, i / 3 / width, i % 3);
+            let sum: usize = (0..16)
+                .map(|j| near[((y * 4 + j / 4) * width * 4 + x * 4 + j % 4) * 3 + channel] as usize)
+                .sum();
+            *pixel = (sum / 16) as u8;
+        }
+        far
+    }
+
+    #[test]
+    fn distant_markers_are_decoded_from_an_upsampled_region() {
+        let codes = [(31, 30.3, 30.6, 2.2, 0.3), (93, 90.6, 29.4, 2.2, 1.2)];
+        let buffer = render_far(120, 60, &codes);
+        let mut scanner = Scanner::new(120, 60);
+        assert!(scanner.scan(buffer.as_slice(), rgb).is_empty());
+
+        scanner.set_upsample_small_markers(true);
+        let mut topcodes = scanner.scan(buffer.as_slice(), rgb);
+        topcodes.sort_by_key(|topcode| topcode.code);
+
+        assert_eq!(2, topcodes.len());
+        for (topcode, &(code, x, y, unit, _)) in topcodes.iter().zip(&codes) {
+            assert_eq!(Some(code), topcode.code);
+            assert!((topcode.x - x).abs() < 1.0 && (topcode.y - y).abs() < 1.0);
+            assert!((topcode.unit - unit).abs() < 0.5, "{}", topcode.unit);
+        }
+    }
+
+    #[test]
+    fn large_markers_are_not_upsampled() {
+        let candidate = Candidate {
+            x: 50,
+            y: 50,
+            b1: 8,
+            w1: 16,
+            b2: 8,
+        };
+        assert_eq!(None, small_unit(&candidate));
+        assert_eq!(None, small_unit(&Candidate::new(50, 50)));
+        assert_eq!(
+            Some(2.5),
+            small_unit(&Candidate {
+                b1: 2,
+                w1: 6,
+                b2: 2,
+                ..candidate
+            })
+        );
+    }
+}
@@ -0,0 +1,212 @@
+use std::sync::mpsc::Receiver;
+
+#[cfg(feature = "image")]
+use std::{
+    io,
+    path::{Path, PathBuf},
+};
+
+use crate::{scanner::Scanner, topcode::TopCode};
+
+/// A source of RGB frames (3 bytes per pixel) to scan one after another, such as a list of files,
+/// a webcam or a video decoder, so that any of them can feed [scan_source].
+///
+/// Frames are returned with their width and height, which may change from one frame to the next.
+/// Besides the sources below, the receiving end of a channel is a source, which lets a capture
+/// thread feed the frames it grabs; it ends once every sender is dropped.
+pub trait FrameSource {
+    /// Returns the next frame and its width and height, or `None` once the source is exhausted.
+    fn next_frame(&mut self) -> Option<(Vec<u8>, usize, usize)>;
+}
+
+impl FrameSource for Receiver<(Vec<u8>, usize, usize)> {
+    fn next_frame(&mut self) -> Option<(Vec<u8>, usize, usize)> {
+        self.recv().ok()
+    }
+}
+
+impl<S: FrameSource + ?Sized> FrameSource for &mut S {
+    fn next_frame(&mut self) -> Option<(Vec<u8>, usize, usize)> {
+        (**self).next_frame()
+    }
+}
+
+/// A source that returns copies of frames held in memory, in order.
+#[derive(Clone, Debug)]
+pub struct SliceSource<'a> {
+    frames: &'a [(Vec<u8>, usize, usize)],
+}
+
+impl<'a> SliceSource<'a> {
+    /// Creates a source returning the given frames, each with its width and height.
+    pub fn new(frames: &'a [(Vec<u8>, usize, usize)]) -> Self {
+        Self { frames }
+    }
+}
+
+impl FrameSource for SliceSource<'_> {
+    fn next_frame(&mut self) -> Option<(Vec<u8>, usize, usize)> {
+        let ((frame, width, height), rest) = self.frames.split_first()?;
+        self.frames = rest;
+        Some((frame.clone(), *width, *height))
+    }
+}
+
+/// A source that decodes the images in a directory, in the order of their file names, such as the
+/// numbered frames exported from a video. Images are turned upright according to their Exif
+/// orientation like [decode_oriented](crate::decode_oriented) does. Files that cannot be decoded
+/// as an image are skipped.
+#[cfg(feature = "image")]
+#[derive(Clone, Debug)]
+pub struct DirectorySource {
+    paths: std::vec::IntoIter<PathBuf>,
+}
+
+#[cfg(feature = "image")]
+impl DirectorySource {
+    /// Lists the files in a directory, without descending into subdirectories. Fails if the
+    /// directory cannot be read.
+    pub fn open(dir: impl AsRef<Path>) -> io::Result<Self> {
+        let mut paths = Vec::new();
+        for entry in std::fs::read_dir(dir)? {
+            let path = entry?.path();
+            if path.is_file() {
+                paths.push(path);
+            }
+        }
+        paths.sort();
+        Ok(Self {
+            paths: paths.into_iter(),
+        })
+    }
+}
+
+#[cfg(feature = "image")]
+impl FrameSource for DirectorySource {
+    fn next_frame(&mut self) -> Option<(Vec<u8>, usize, usize)> {
+        self.paths.find_map(|path| {
+            let reader = image::ImageReader::open(path).ok()?;
+            let img = crate::exif::decode_oriented(reader).ok()?;
+            let (width, height) = (img.width() as usize, img.height() as usize);
+            Some((img.into_rgb8().into_raw(), width, height))
+        })
+    }
+}
+
+/// Scans every frame of a source with the scanner, returning an iterator over the TopCodes found
+/// in each frame, in order. Frames are only pulled from the source as the iterator advances, so
+/// an endless source such as a webcam can be scanned as it delivers frames.
+///
+/// When a frame does not have the dimensions of the scanner, a new scanner with the same
+/// [config](Scanner::config) is allocated for it and used for the following frames.
+pub fn scan_source(
+    mut scanner: Scanner,
+    mut source: impl FrameSource,
+) -> impl Iterator<Item = Vec<TopCode>> {
+    std::iter::from_fn(move || {
+        let (frame, width, height) = source.next_frame()?;
+        if scanner.dimensions() != (width, height) {
+            let config = *scanner.config();
+            scanner = Scanner::new(width, height);
+            scanner.set_config(config);
+        }
+
+        Some(scanner.scan(frame.as_slice(), |buffer, index| {
+            (
+                buffer[index * 3] as u32,
+                buffer[index * 3 + 1] as u32,
+                buffer[index * 3 + 2] as u32,
+            )
+        }))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::mpsc;
+
+    use super::*;
+    use crate::test_utils::render;
+
+    /// A source that renders a single code in the middle of frames of the given dimensions.
+    struct MockSource {
+        frames: Vec<(u32, usize, usize)>,
+        pulled: usize,
+    }
+
+    impl FrameSource for MockSource {
+        fn next_frame(&mut self) -> Option<(Vec<u8>, usize, usize)> {
+            let &(code, width, height) = self.frames.get(self.pulled)?;
+            self.pulled += 1;
+            let (x, y) = (width as f64 / 2.0, height as f64 / 2.0);
+            Some((
+                render(width, height, &[(code, x, y, 6.0, 0.5)]),
+                width,
+                height,
+            ))
+        }
+    }
+
+    fn codes(topcodes: impl Iterator<Item = Vec<TopCode>>) -> Vec<Vec<Option<u32>>> {
+        topcodes
+            .map(|frame| frame.iter().map(|topcode| topcode.code).collect())
+            .collect()
+    }
+
+    #[test]
+    fn frames_are_pulled_as_they_are_scanned() {
+        let mut source = MockSource {
+            frames: vec![(31, 80, 80), (55, 80, 80), (93, 120, 90)],
+            pulled: 0,
+        };
+        let mut scanner = Scanner::new(80, 80);
+        scanner.set_min_confidence(0.5);
+
+        let mut topcodes = scan_source(scanner, &mut source);
+        assert_eq!(vec![Some(31)], codes(topcodes.by_ref().take(1))[0]);
+        let rest = codes(topcodes);
+
+        // The larger frame gets a scanner of its own
+        assert_eq!(vec![vec![Some(55)], vec![Some(93)]], rest);
+        assert_eq!(3, source.pulled);
+    }
+
+    #[test]
+    fn slices_and_channels_are_sources() {
+        let frames: Vec<_> = [31, 47]
+            .iter()
+            .map(|&code| (render(80, 80, &[(code, 40.0, 40.0, 6.0, 0.0)]), 80, 80))
+            .collect();
+        let topcodes = scan_source(Scanner::new(80, 80), SliceSource::new(&frames));
+        assert_eq!(vec![vec![Some(31)], vec![Some(47)]], codes(topcodes));
+
+        let (sender, receiver) = mpsc::channel();
+        for frame in frames.iter().rev() {
+            sender.send(frame.clone()).unwrap();
+        }
+        drop(sender);
+        let topcodes = scan_source(Scanner::new(80, 80), receiver);
+        assert_eq!(vec![vec![Some(47)], vec![Some(31)]], codes(topcodes));
+    }
+
+    #[cfg(feature = "image")]
+    #[test]
+    fn directories_are_read_in_name_order() {
+        let dir = std::env::temp_dir().join(format!("topcodes-source-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        for (name, code) in [("frame2.png", 55), ("frame1.png", 31)] {
+            let buffer = render(80, 80, &[(code, 40.0, 40.0, 6.0, 0.0)]);
+            image::RgbImage::from_raw(80, 80, buffer)
+                .unwrap()
+                .save(dir.join(name))
+                .unwrap();
+        }
+        std::fs::write(dir.join("notes.txt"), "not an image").unwrap();
+
+        let source = DirectorySource::open(&dir).unwrap();
+        let topcodes = codes(scan_source(Scanner::new(80, 80), source));
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(vec![vec![Some(31)], vec![Some(55)]], topcodes);
+    }
+}
@@ -0,0 +1,135 @@
+use crate::{scanner::Scanner, topcode::TopCode};
+
+/// Largest relative change of the threshold ratio between the runs of [Scanner::scan_stable].
+const RATIO_SPREAD: f64 = 0.02;
+
+impl Scanner {
+    /// Scans a single noisy image `runs` times and returns only the TopCodes found in at least
+    /// `min_agreement` of the runs, e.g. for low-light or microscopy captures where a single scan
+    /// reads the odd spurious code. Each run uses a threshold ratio (see
+    /// [Scanner::set_threshold_ratio]) spread evenly up to 2% either side of the configured one.
+    ///
+    /// Detections of the same code in different runs are merged when their centers lie within
+    /// each other, and the returned code averages their position, unit, orientation and
+    /// confidence. Codes are returned in the order they were first found. This takes `runs` times
+    /// as long as [Scanner::scan], and the scanner's settings are left unchanged.
+    #[must_use = "the codes found are only returned, not kept by the scanner"]
+    pub fn scan_stable<T: ?Sized>(
+        &mut self,
+        image_buffer: &T,
+        decode_rgb: impl Fn(&T, usize) -> (u32, u32, u32),
+        runs: usize,
+        min_agreement: usize,
+    ) -> Vec<TopCode> {
+        let ratio = self.threshold_ratio();
+        let detections: Vec<Vec<TopCode>> = (0..runs)
+            .map(|run| {
+                let offset = if runs > 1 {
+                    2.0 * run as f64 / (runs - 1) as f64 - 1.0
+                } else {
+                    0.0
+                };
+                self.set_threshold_ratio(ratio * (1.0 + RATIO_SPREAD * offset));
+                self.scan(image_buffer, &decode_rgb)
+            })
+            .collect();
+        self.set_threshold_ratio(ratio);
+
+        merge_runs(&detections, min_agreement)
+    }
+}
+
+/// Groups the detections of the same code across runs and averages the groups of at least
+/// `min_agreement` detections.
+fn merge_runs(runs: &[Vec<TopCode>], min_agreement: usize) -> Vec<TopCode> {
+    let mut groups: Vec<Vec<&TopCode>> = Vec::new();
+    for topcode in runs.iter().flatten() {
+        let group = groups.iter_mut().find(|group| {
+            let first = group[0];
+            first.code == topcode.code
+                && (first.covers(topcode.x, topcode.y) || topcode.covers(first.x, first.y))
+        });
+        match group {
+            Some(group) => group.push(topcode),
+            None => groups.push(vec![topcode]),
+        }
+    }
+
+    groups
+        .into_iter()
+        .filter(|group| group.len() >= min_agreement.max(1))
+        .map(|group| {
+            let n = group.len() as f64;
+            let mean = |field: fn(&TopCode) -> f64| group.iter().map(|t| field(t)).sum::<f64>() / n;
+            let (sin, cos) = group.iter().fold((0.0, 0.0), |(sin, cos), topcode| {
+                (
+                    sin + topcode.orientation.sin(),
+                    cos + topcode.orientation.cos(),
+                )
+            });
+
+            TopCode {
+                unit: mean(|t| t.unit),
+                orientation: sin.atan2(cos),
+                x: mean(|t| t.x),
+                y: mean(|t| t.y),
+                confidence: mean(|t| t.confidence),
+                ..*group[0]
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f64::consts::PI;
+
+    use super::*;
+    use crate::test_utils::{render, rgb};
+
+    #[test]
+    fn only_codes_found_by_enough_runs_are_kept() {
+        let runs = vec![
+            vec![
+                TopCode::mock(31, 5.0, PI - 0.1, 50.0, 50.0),
+                TopCode::mock(55, 5.0, 0.0, 150.0, 50.0),
+            ],
+            vec![TopCode::mock(31, 6.0, -PI + 0.1, 52.0, 50.0)],
+            vec![
+                TopCode::mock(93, 5.0, 0.0, 150.0, 50.0),
+                TopCode::mock(31, 4.0, PI, 51.0, 53.0),
+            ],
+        ];
+
+        let merged = merge_runs(&runs, 2);
+
+        assert_eq!(1, merged.len());
+        assert_eq!(Some(31), merged[0].code);
+        assert!((merged[0].x - 51.0).abs() < 1e-9 && (merged[0].y - 51.0).abs() < 1e-9);
+        assert!((merged[0].unit - 5.0).abs() < 1e-9);
+        // Averaged across the wrap rather than to 0
+        assert!((merged[0].orientation.abs() - PI).abs() < 1e-9);
+
+        assert_eq!(3, merge_runs(&runs, 1).len());
+    }
+
+    #[test]
+    fn clean_images_are_stable() {
+        let buffer = render(
+            200,
+            100,
+            &[(31, 50.0, 50.0, 5.0, 0.0), (93, 150.0, 50.0, 4.0, 1.0)],
+        );
+        let mut scanner = Scanner::new(200, 100);
+        let expected = scanner.scan(buffer.as_slice(), rgb);
+
+        let stable = scanner.scan_stable(buffer.as_slice(), rgb, 5, 5);
+
+        assert_eq!(expected.len(), stable.len());
+        for (expected, stable) in expected.iter().zip(&stable) {
+            assert_eq!(expected.code, stable.code);
+            assert!((expected.x - stable.x).abs() < 1.0 && (expected.y - stable.y).abs() < 1.0);
+        }
+        assert_eq!(0.975, scanner.threshold_ratio());
+    }
+}
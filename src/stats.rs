@@ -0,0 +1,22 @@
+/// Statistics gathered during the last scan of a [Scanner](crate::Scanner).
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct ScanStats {
+    /// Number of pixels in the scanned image
+    pub pixels: usize,
+    /// Number of pixels at the maximum intensity (255)
+    pub saturated_pixels: usize,
+    /// Number of candidate TopCode centers found while thresholding
+    pub candidates: usize,
+}
+
+impl ScanStats {
+    /// Fraction of the image (between 0 and 1) that is blown out to pure white. The adaptive
+    /// threshold cannot find any edges in uniformly saturated regions, so TopCodes there are
+    /// invisible to the scanner. A large value suggests the lighting or exposure is too bright.
+    pub fn saturated_fraction(&self) -> f64 {
+        if self.pixels == 0 {
+            return 0.0;
+        }
+        self.saturated_pixels as f64 / self.pixels as f64
+    }
+}
@@ -0,0 +1,223 @@
+//! Helpers for rendering synthetic TopCodes and comparing scan results in tests.
+
+use std::{env, f64::consts::PI, fs};
+
+use crate::topcode::{Code, TopCode, SECTORS, WIDTH};
+
+/// Largest difference between the floating point fields of a TopCode and its fixture.
+const FIXTURE_TOLERANCE: f64 = 1e-6;
+
+/// Renders an RGB image with a white background and a TopCode for each `(code, x, y, unit,
+/// angle)`. The data sector for bit `i` of `code` starts at `angle + i * ARC`.
+pub(crate) fn render(width: usize, height: usize, codes: &[(Code, f64, f64, f64, f64)]) -> Vec<u8> {
+    render_sectors(width, height, SECTORS, codes)
+}
+
+/// Renders markers with the given number of data sectors, but otherwise laid out like TopCodes.
+pub(crate) fn render_sectors(
+    width: usize,
+    height: usize,
+    sectors: usize,
+    codes: &[(Code, f64, f64, f64, f64)],
+) -> Vec<u8> {
+    let mut buffer = vec![0xff; width * height * 3];
+    for &(code, x, y, unit, angle) in codes {
+        draw(&mut buffer, width, height, sectors, code, x, y, unit, angle);
+    }
+    buffer
+}
+
+/// Draws a single marker with the given number of data sectors into an RGB buffer.
+#[allow(clippy::too_many_arguments)]
+fn draw(
+    buffer: &mut [u8],
+    width: usize,
+    height: usize,
+    sectors: usize,
+    code: Code,
+    x: f64,
+    y: f64,
+    unit: f64,
+    angle: f64,
+) {
+    let arc = 2.0 * PI / sectors as f64;
+    let reach = (unit * 4.0).ceil() as isize;
+
+    for j in -reach..=reach {
+        for i in -reach..=reach {
+            let (px, py) = (x.round() as isize + i, y.round() as isize + j);
+            if px < 0 || py < 0 || px >= width as isize || py >= height as isize {
+                continue;
+            }
+
+            let (dx, dy) = (px as f64 - x, py as f64 - y);
+            let r = dx.hypot(dy) / unit;
+            let white = if r < 1.0 {
+                true
+            } else if r < 2.0 {
+                false
+            } else if r < 3.0 {
+                true
+            } else if r < 4.0 {
+                let theta = (dy.atan2(dx) - angle).rem_euclid(2.0 * PI);
+                let sector = (theta / arc) as usize % sectors;
+                (code >> sector) & 0x01 == 1
+            } else {
+                continue;
+            };
+
+            let index = (py as usize * width + px as usize) * 3;
+            let value = if white { 0xff } else { 0 };
+            buffer[index..index + 3].copy_from_slice(&[value; 3]);
+        }
+    }
+}
+
+/// Decodes a pixel from an RGB buffer.
+pub(crate) fn rgb(buffer: &[u8], index: usize) -> (u32, u32, u32) {
+    (
+        buffer[index * 3] as u32,
+        buffer[index * 3 + 1] as u32,
+        buffer[index * 3 + 2] as u32,
+    )
+}
+
+/// Whether two TopCodes are equal, allowing their floating point fields to differ by at most
+/// `tolerance`. All other fields must match exactly.
+pub(crate) fn approx_eq(a: &TopCode, b: &TopCode, tolerance: f64) -> bool {
+    let close = |a: f64, b: f64| (a - b).abs() <= tolerance;
+    a.code == b.code
+        && close(a.unit, b.unit)
+        && close(a.orientation, b.orientation)
+        && close(a.x, b.x)
+        && close(a.y, b.y)
+        && a.center_intensity == b.center_intensity
+        && close(a.confidence, b.confidence)
+        && a.orientation_consistent == b.orientation_consistent
+        && close(a.ring_uniformity, b.ring_uniformity)
+        && a.scanline == b.scanline
+        && a.core == b.core
+}
+
+/// Formats TopCodes as a fixture, one code per line with its fields separated by spaces in
+/// declaration order. Missing codes, orientation checks and scanlines are written as `-`.
+pub(crate) fn format_fixture(codes: &[TopCode]) -> String {
+    let mut fixture = String::new();
+    for code in codes {
+        let mut fields = vec![
+            code.code.map_or("-".to_string(), |code| code.to_string()),
+            code.unit.to_string(),
+            code.orientation.to_string(),
+            code.x.to_string(),
+            code.y.to_string(),
+            code.center_intensity.to_string(),
+            code.confidence.to_string(),
+            code.orientation_consistent
+                .map_or("-".to_string(), |consistent| consistent.to_string()),
+            code.ring_uniformity.to_string(),
+            code.scanline.map_or("-".to_string(), |row| row.to_string()),
+        ];
+        fields.extend(code.core.iter().map(|sample| sample.to_string()));
+        fixture.push_str(&fields.join(" "));
+        fixture.push('\n');
+    }
+    fixture
+}
+
+/// Parses a fixture written by [format_fixture].
+pub(crate) fn parse_fixture(fixture: &str) -> Vec<TopCode> {
+    fixture
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            assert_eq!(fields.len(), 10 + WIDTH, "Malformed fixture line: {}", line);
+            let mut core = [0; WIDTH];
+            for (sample, field) in core.iter_mut().zip(&fields[10..]) {
+                *sample = field.parse().unwrap();
+            }
+            TopCode {
+                code: (fields[0] != "-").then(|| fields[0].parse().unwrap()),
+                unit: fields[1].parse().unwrap(),
+                orientation: fields[2].parse().unwrap(),
+                x: fields[3].parse().unwrap(),
+                y: fields[4].parse().unwrap(),
+                center_intensity: fields[5].parse().unwrap(),
+                confidence: fields[6].parse().unwrap(),
+                orientation_consistent: (fields[7] != "-").then(|| fields[7].parse().unwrap()),
+                ring_uniformity: fields[8].parse().unwrap(),
+                scanline: (fields[9] != "-").then(|| fields[9].parse().unwrap()),
+                width: WIDTH,
+                core,
+            }
+        })
+        .collect()
+}
+
+/// Asserts that `codes` match the fixture `assets/fixtures/<name>.txt` within a small tolerance.
+/// When the `UPDATE_FIXTURES` environment variable is set, the fixture is rewritten from `codes`
+/// instead, so that intended changes to the detection math only require reviewing the diff.
+pub(crate) fn assert_matches_fixture(name: &str, codes: &[TopCode]) {
+    let path = format!("assets/fixtures/{}.txt", name);
+    if env::var_os("UPDATE_FIXTURES").is_some() {
+        fs::write(&path, format_fixture(codes)).unwrap();
+        return;
+    }
+
+    let expected = parse_fixture(&fs::read_to_string(&path).unwrap());
+    let matches = expected.len() == codes.len()
+        && expected
+            .iter()
+            .zip(codes)
+            .all(|(expected, code)| approx_eq(expected, code, FIXTURE_TOLERANCE));
+    assert!(
+        matches,
+        "Scan results differ from {}\nexpected: {:#?}\nactual: {:#?}",
+        path, expected, codes
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fixtures_round_trip() {
+        let codes = [
+            TopCode {
+                code: Some(31),
+                unit: 22.91875,
+                orientation: 0.024166097335306114,
+                x: 366.5,
+                y: 510.0,
+                center_intensity: 95,
+                confidence: 0.9240489358136417,
+                orientation_consistent: Some(true),
+                ring_uniformity: 0.9375,
+                scanline: Some(498),
+                width: WIDTH,
+                core: [0, 255, 0, 255, 255, 0, 255, 255],
+            },
+            TopCode::default(),
+        ];
+
+        assert_eq!(parse_fixture(&format_fixture(&codes)), codes);
+    }
+
+    #[test]
+    fn approx_eq_tolerates_small_float_differences() {
+        let code = TopCode::new(31);
+        let nudged = TopCode {
+            x: code.x + 1e-9,
+            ..code
+        };
+        let moved = TopCode {
+            x: code.x + 1e-3,
+            ..code
+        };
+
+        assert!(approx_eq(&code, &nudged, FIXTURE_TOLERANCE));
+        assert!(!approx_eq(&code, &moved, FIXTURE_TOLERANCE));
+        assert!(!approx_eq(&code, &TopCode::new(47), FIXTURE_TOLERANCE));
+    }
+}
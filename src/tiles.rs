@@ -0,0 +1,109 @@
+use crate::{scanner::Scanner, topcode::TopCode};
+
+/// Scans an RGB atlas (3 bytes per pixel) that packs views of `tile_width` by `tile_height`
+/// pixels side by side, such as several camera views rendered into one texture, and returns the
+/// TopCodes found in each tile. Every tile is scanned on its own, as if it were a separate image,
+/// so codes that straddle two tiles are not found, and the coordinates of the codes are local to
+/// their tile.
+///
+/// Tiles are indexed in row-major order: left to right along the top row of tiles, then along
+/// each row below, so the tile in column `column` and row `row` has index
+/// `row * (width / tile_width) + column`. Tiles are returned in that order with their index, and
+/// tiles without codes are left out. When the atlas is not a whole number of tiles wide or high,
+/// the partial tiles along its right and bottom edges are skipped.
+///
+/// A single scanner of the size of a tile is reused for every tile.
+///
+/// # Panics
+///
+/// Panics if the buffer is not `3 * width * height` bytes long, or if a tile is empty or larger
+/// than the atlas.
+pub fn scan_tiles(
+    buffer: &[u8],
+    width: usize,
+    height: usize,
+    tile_width: usize,
+    tile_height: usize,
+) -> Vec<(usize, Vec<TopCode>)> {
+    assert_eq!(
+        buffer.len(),
+        width * height * 3,
+        "Buffer length must be 3 * width * height"
+    );
+    assert!(
+        tile_width > 0 && tile_height > 0 && tile_width <= width && tile_height <= height,
+        "Tiles must be non-empty and fit in the atlas"
+    );
+
+    let (columns, rows) = (width / tile_width, height / tile_height);
+    let mut scanner = Scanner::new(tile_width, tile_height);
+    let mut tiles = Vec::new();
+
+    for row in 0..rows {
+        for column in 0..columns {
+            let (left, top) = (column * tile_width, row * tile_height);
+            let topcodes = scanner.scan(buffer, |buffer, index| {
+                let (x, y) = (left + index % tile_width, top + index / tile_width);
+                let pixel = (y * width + x) * 3;
+                (
+                    buffer[pixel] as u32,
+                    buffer[pixel + 1] as u32,
+                    buffer[pixel + 2] as u32,
+                )
+            });
+            if !topcodes.is_empty() {
+                tiles.push((row * columns + column, topcodes));
+            }
+        }
+    }
+
+    tiles
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::render;
+
+    #[test]
+    fn tiles_are_scanned_in_row_major_order() {
+        // A 2x2 atlas of 100x80 tiles, with a code in every tile but the top right one, and a
+        // strip that is not a whole tile along the right edge
+        let (width, height, tile_width, tile_height) = (230, 160, 100, 80);
+        let atlas = render(
+            width,
+            height,
+            &[
+                (31, 50.0, 40.0, 4.0, 0.0),
+                (55, 40.0, 120.0, 4.0, 0.5),
+                (93, 160.0, 110.0, 4.0, 1.0),
+                (47, 215.0, 40.0, 1.5, 0.0),
+            ],
+        );
+
+        let tiles = scan_tiles(&atlas, width, height, tile_width, tile_height);
+
+        let found: Vec<_> = tiles
+            .iter()
+            .map(|(index, topcodes)| {
+                assert_eq!(1, topcodes.len());
+                let topcode = topcodes[0];
+                (*index, topcode.code, topcode.x.round(), topcode.y.round())
+            })
+            .collect();
+        assert_eq!(
+            vec![
+                (0, Some(31), 50.0, 40.0),
+                (2, Some(55), 40.0, 40.0),
+                (3, Some(93), 60.0, 30.0),
+            ],
+            found
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "Tiles must be non-empty and fit in the atlas")]
+    fn tiles_must_fit_in_the_atlas() {
+        let _ = scan_tiles(&[0; 30], 5, 2, 6, 2);
+    }
+}
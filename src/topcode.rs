@@ -1,21 +1,34 @@
 use std::f64::consts::PI;
 
-use crate::scanner::Scanner;
+use crate::{
+    family::{MarkerFamily, MAX_WIDTH},
+    math,
+    scanner::Scanner,
+};
 
 /// Number of sectors in the data ring
 pub(crate) const SECTORS: usize = 13;
 
 /// Width of the code in units (ring widths)
-const WIDTH: usize = 8;
+pub(crate) const WIDTH: usize = 8;
 
 /// The default diameter for a TopCode
 const DEFAULT_DIAMETER: f64 = 72.0;
 
-/// Span of a data sector in radians
-const ARC: f64 = 2.0 * PI / (SECTORS as f64);
-
 const MAX_PIXELS: usize = 100;
 
+/// Number of samples taken across every sector when refining the orientation (see
+/// [Scanner::set_orientation_precision])
+const ALIGNMENT_SAMPLES: usize = 16;
+
+/// Smallest intensity range along a profile through the bullseye for [TopCode::focus] to measure
+/// its edges
+const MIN_FOCUS_CONTRAST: i32 = 16;
+
+/// Data samples closer than this to the middle intensity are ambiguous when recovering a sector
+/// (see [Scanner::set_sector_recovery]).
+const AMBIGUOUS_MARGIN: usize = 32;
+
 /// An unsigned integer representing a symbol code of a given TopCode. Since TopCodes never exceed
 /// Valid TopCodes are 13 bits in size, but invalid ones may be more, so this is represented as a
 /// u32.
@@ -32,8 +45,12 @@ pub type Code = u32;
 ///
 /// Each TopCode encodes a 13-bit number in a single data ring on the outer edge of the symbol.
 /// Zero is represented by a black sector and one is represented by a white sector.
+///
+/// `N` is the number of samples across the diameter kept from the last sector read when decoding,
+/// which defaults to the width of a TopCode. Markers of a wider [MarkerFamily] can be decoded into
+/// a `TopCode` with a matching `N` (see [TopCode::empty]) to keep every sample.
 #[derive(Clone, Copy, Debug, PartialEq)]
-pub struct TopCode {
+pub struct TopCode<const N: usize = WIDTH> {
     /// The symbol's code, if valid
     pub code: Option<Code>,
     /// Width of a single ring
@@ -44,20 +61,35 @@ pub struct TopCode {
     pub x: f64,
     /// Vertical center of a symbol
     pub y: f64,
-    /// Buffer used to decode sectors
-    pub(crate) core: [usize; WIDTH],
+    /// Approximate image intensity at the center of the symbol, between 0 (black) and 255 (white).
+    /// See [Scanner::intensity_at].
+    pub center_intensity: u8,
+    /// How closely the sampled rings matched an ideal TopCode, between 0 (no match) and 1 (perfect
+    /// match)
+    pub confidence: f64,
+    /// Whether the orientation measured from the edges of the data ring agrees with the one
+    /// derived from the code's bits. This is `None` unless the scanner checks orientations (see
+    /// [Scanner::set_check_orientation]) and the edge could be measured.
+    pub orientation_consistent: Option<bool>,
+    /// How evenly the rings of the bullseye are printed, between 0 (degraded) and 1 (perfectly
+    /// uniform), e.g. to flag worn markers for reprinting. This combines how closely the ring
+    /// samples of every sector matched their expected color with how well the width of the
+    /// bullseye agrees between the horizontal and vertical axis.
+    pub ring_uniformity: f64,
+    /// Row of the image on which the scanner confirmed the candidate this code was decoded from,
+    /// e.g. to correlate detections with the direction the threshold ran along that row. This is
+    /// the confirmation row, not the center of the code, and stays in image coordinates even with
+    /// [Scanner::set_center_origin]. `None` for codes that were not decoded by a scanner.
+    pub scanline: Option<usize>,
+    /// Width in units of the [MarkerFamily] the code was decoded as
+    pub(crate) width: usize,
+    /// Samples across the diameter of the last sector read
+    pub(crate) core: [usize; N],
 }
 
 impl Default for TopCode {
     fn default() -> Self {
-        Self {
-            code: None,
-            unit: DEFAULT_DIAMETER / WIDTH as f64,
-            orientation: 0.0,
-            x: 0.0,
-            y: 0.0,
-            core: [0; WIDTH],
-        }
+        Self::empty()
     }
 }
 
@@ -79,11 +111,58 @@ impl TopCode {
             unit,
             x,
             y,
+            center_intensity: 0,
+            confidence: 1.0,
+            orientation_consistent: None,
+            ring_uniformity: 1.0,
+            scanline: None,
+            width: WIDTH,
+        }
+    }
+
+    /// Only codes with a checksum of 5 are valid.
+    pub fn checksum(mut bits: Code) -> bool {
+        let mut sum = 0;
+        for _i in 0..SECTORS {
+            sum += bits & 0x01;
+            bits >>= 1;
         }
+
+        sum == 5
     }
+}
 
+impl<const N: usize> TopCode<N> {
+    /// Creates a TopCode that keeps `N` samples of the last sector it decodes, e.g.
+    /// `TopCode::<16>::empty()` for markers 16 units wide. Equivalent to [TopCode::default] for the
+    /// default `N`.
+    pub fn empty() -> Self {
+        Self {
+            code: None,
+            unit: DEFAULT_DIAMETER / WIDTH as f64,
+            orientation: 0.0,
+            x: 0.0,
+            y: 0.0,
+            center_intensity: 0,
+            confidence: 0.0,
+            orientation_consistent: None,
+            ring_uniformity: 0.0,
+            scanline: None,
+            width: WIDTH,
+            core: [0; N],
+        }
+    }
+
+    /// Returns the radius of the symbol in pixels, half the width of its [MarkerFamily].
     pub fn radius(&self) -> f64 {
-        self.unit * WIDTH as f64 / 2.0
+        self.unit * self.width as f64 / 2.0
+    }
+
+    /// Returns the smallest axis-aligned box containing the symbol as `(min_x, min_y, max_x,
+    /// max_y)`.
+    pub fn bounding_box(&self) -> (f64, f64, f64, f64) {
+        let r = self.radius();
+        (self.x - r, self.y - r, self.x + r, self.y + r)
     }
 
     pub fn to_json(&self) -> String {
@@ -100,6 +179,58 @@ impl TopCode {
         self.y = y;
     }
 
+    /// Returns true if both detections are of the same code, regardless of where they are and how
+    /// they are rotated. [PartialEq] compares every field, so two detections of the same marker
+    /// are rarely equal.
+    pub fn eq_ignoring_position(&self, other: &Self) -> bool {
+        self.code == other.code
+    }
+
+    /// Linearly interpolates the position, unit and orientation between this detection (`t = 0`)
+    /// and another detection of the same code (`t = 1`), e.g. to render between scans or smooth
+    /// out jitter. The orientation turns along the shortest arc, so interpolating across the ±π
+    /// boundary does not spin the long way around. Other fields are taken from `self`.
+    pub fn lerp(&self, other: &Self, t: f64) -> Self {
+        let turn = (other.orientation - self.orientation + PI).rem_euclid(2.0 * PI) - PI;
+
+        Self {
+            unit: self.unit + (other.unit - self.unit) * t,
+            orientation: self.orientation + turn * t,
+            x: self.x + (other.x - self.x) * t,
+            y: self.y + (other.y - self.y) * t,
+            ..*self
+        }
+    }
+
+    /// Returns the distance in pixels between the centers of this code and another one.
+    pub fn distance_to(&self, other: &Self) -> f64 {
+        (self.x - other.x).hypot(self.y - other.y)
+    }
+
+    /// Returns the pose of this code in the local frame of a `reference` code, as `(x, y,
+    /// orientation)`. The position is measured in units of the reference code, with the axes
+    /// rotated by its orientation, and the orientation is relative to the reference, between -π
+    /// and π. This is useful for building a coordinate system from a known anchor marker.
+    pub fn relative_to(&self, reference: &Self) -> (f64, f64, f64) {
+        let (sin, cos) = reference.orientation.sin_cos();
+        let dx = (self.x - reference.x) / reference.unit;
+        let dy = (self.y - reference.y) / reference.unit;
+        let orientation = (self.orientation - reference.orientation + PI).rem_euclid(2.0 * PI) - PI;
+
+        (dx * cos + dy * sin, dy * cos - dx * sin, orientation)
+    }
+
+    /// Maps a point `(u, v)` in the local frame of this code to image coordinates. The local frame
+    /// is measured in units, centered on the code and rotated by its orientation, so this is the
+    /// inverse of [TopCode::relative_to] taking this code as the reference.
+    pub fn project(&self, u: f64, v: f64) -> (f64, f64) {
+        let (sin, cos) = self.orientation.sin_cos();
+        (
+            self.x + (u * cos - v * sin) * self.unit,
+            self.y + (u * sin + v * cos) * self.unit,
+        )
+    }
+
     /// Returns true if the code was successfully decoded and is not too close to the edges of the
     /// image.
     pub fn is_valid(&self) -> bool {
@@ -108,54 +239,141 @@ impl TopCode {
 
     /// Decodes a symbol given any point (cx, by) inside the center circle (bullseye) of the code.
     pub fn decode(&mut self, scanner: &Scanner, cx: usize, cy: usize) -> Option<Code> {
-        let up = scanner.dist(cx, cy, 0, -1)
-            + scanner.dist(cx.saturating_sub(1), cy, 0, -1)
-            + scanner.dist(cx + 1, cy, 0, -1);
-        let down = scanner.dist(cx, cy, 0, 1)
-            + scanner.dist(cx.saturating_sub(1), cy, 0, 1)
-            + scanner.dist(cx + 1, cy, 0, 1);
-        let left = scanner.dist(cx, cy, -1, 0)
-            + scanner.dist(cx, cy.saturating_sub(1), -1, 0)
-            + scanner.dist(cx, cy + 1, -1, 0);
-        let right = scanner.dist(cx, cy, 1, 0)
-            + scanner.dist(cx, cy.saturating_sub(1), 1, 0)
-            + scanner.dist(cx, cy + 1, 1, 0);
+        self.decode_family(scanner, cx, cy, &MarkerFamily::TOPCODE)
+    }
 
+    /// Decodes a symbol of the given [MarkerFamily] given any point (cx, cy) inside the center
+    /// circle (bullseye) of the code.
+    ///
+    /// Returns `None` without sampling the image if the point, or the center estimated from it,
+    /// lies outside the image.
+    pub fn decode_family(
+        &mut self,
+        scanner: &Scanner,
+        cx: usize,
+        cy: usize,
+        family: &MarkerFamily,
+    ) -> Option<Code> {
         self.x = cx as f64;
         self.y = cy as f64;
-        self.x += (right - left) as f64 / 6.0;
-        self.y += (down - up) as f64 / 6.0;
+        self.width = family.width;
         self.code = None;
+        self.confidence = 0.0;
+        self.orientation_consistent = None;
+        self.ring_uniformity = 0.0;
+        self.center_intensity = 0;
+        self.unit = -1.0;
+        if !self.is_inside(scanner) {
+            return None;
+        }
+
+        // Distances to the first edge along three neighbouring lines in each direction, to a
+        // fraction of a pixel if enabled. The center is only moved along an axis when every edge
+        // on that axis was found, since a missing edge (-1) would pull the center towards it.
+        let subpixel = scanner.subpixel_centering();
+        let edges = |lines: [(usize, usize); 3], dx, dy| {
+            lines
+                .into_iter()
+                .map(|(x, y)| {
+                    if subpixel {
+                        scanner.dist_subpixel(x, y, dx, dy)
+                    } else {
+                        let dist = scanner.dist(x, y, dx, dy);
+                        (dist >= 0).then_some(dist as f64)
+                    }
+                })
+                .sum::<Option<f64>>()
+        };
+        let columns = [(cx, cy), (cx.saturating_sub(1), cy), (cx + 1, cy)];
+        let rows = [(cx, cy), (cx, cy.saturating_sub(1)), (cx, cy + 1)];
+        if let (Some(left), Some(right)) = (edges(rows, -1, 0), edges(rows, 1, 0)) {
+            self.x += (right - left) / 6.0;
+        }
+        if let (Some(up), Some(down)) = (edges(columns, 0, -1), edges(columns, 0, 1)) {
+            self.y += (down - up) / 6.0;
+        }
         self.unit = self.read_unit(scanner); // Try to make this an option. Consider a valid vs. invalid TopCode enum.
 
-        if self.unit < 0.0 {
+        // Move towards the middle of the bullseye and try again while the ring distances are too
+        // lopsided to trust.
+        for _ in 0..scanner.max_recenter_iterations() {
+            if self.unit >= 0.0 {
+                break;
+            }
+            let Some([left, right, up, down]) = self.ring_distances(scanner) else {
+                break;
+            };
+            if left == right && up == down {
+                break;
+            }
+            self.x += (right - left) as f64 / 2.0;
+            self.y += (down - up) as f64 / 2.0;
+            self.unit = self.read_unit(scanner);
+        }
+
+        // Leave finding the exact unit to the readings below when the rings cannot be measured
+        if self.unit < 0.0 && scanner.recovers_unit() && self.is_inside(scanner) {
+            self.unit = self.core_unit(scanner).unwrap_or(-1.0);
+        }
+
+        if !self.is_inside(scanner) || !self.unit.is_finite() {
+            self.unit = -1.0;
             return None;
         }
 
-        let mut max_c = 0;
-        let mut max_a = 0.0;
-        let mut max_u = 0.0;
+        self.center_intensity =
+            scanner.intensity_at(self.x.round() as usize, self.y.round() as usize);
+
+        if self.unit <= 0.0 {
+            return None;
+        }
+
+        let mut best: Option<(CodeReading, f64, f64)> = None;
 
         // Try different unit and arc adjustments. Save the one that produces a maximum confidence
         // reading....
         for u in -2..=2 {
             for a in 0..10 {
-                let arc_adjustment = a as f64 * ARC * 0.1;
+                let arc_adjustment = a as f64 * family.arc() * 0.1;
                 let unit = self.unit + (self.unit * 0.05 * u as f64);
-                let c = self.read_code(scanner, unit, arc_adjustment);
-                if c > max_c {
-                    max_c = c;
-                    max_a = arc_adjustment;
-                    max_u = unit;
+                let Some(reading) = self.read_code(scanner, family, unit, arc_adjustment) else {
+                    continue;
+                };
+                if best
+                    .as_ref()
+                    .is_none_or(|(max, _, _)| reading.confidence > max.confidence)
+                {
+                    best = Some((reading, arc_adjustment, unit));
                 }
             }
         }
 
-        // One last call to [read_code] to reset orientation and code.
-        if max_c > 0 {
+        // Align the sectors of the best reading with the sample rays
+        if let (Some(precision), Some((reading, a, u))) = (scanner.orientation_precision(), &best) {
+            let refined = self.refine_arc(scanner, family, *u, *a, reading.code, precision);
+            if let Some((reading, a)) = refined {
+                best = Some((reading, a, *u));
+            }
+        }
+
+        // Keep the best reading and reset the orientation from it.
+        if let Some((reading, max_a, max_u)) = best {
             self.unit = max_u;
-            self.read_code(scanner, self.unit, max_a);
-            self.code = self.code.map(|code| self.rotate_lowest(code, max_a));
+            // Keep as many samples as fit, and clear the rest
+            let len = reading.width.min(N);
+            self.core = [0; N];
+            self.core[..len].copy_from_slice(&reading.core[..len]);
+            self.code = self.rotate_lowest(family, reading.code, max_a);
+            self.confidence = reading.confidence as f64 / family.max_confidence();
+            self.ring_uniformity = reading.rings * self.ring_symmetry(scanner);
+
+            if scanner.checks_orientation() && self.code.is_some() {
+                self.orientation_consistent =
+                    self.measure_orientation(scanner, family).map(|angle| {
+                        let difference = (angle - self.orientation + PI).rem_euclid(2.0 * PI) - PI;
+                        difference.abs() <= family.arc() / 2.0
+                    });
+            }
         }
 
         self.code
@@ -163,181 +381,480 @@ impl TopCode {
 
     /// Attempts to decode the binary pixels of an image into a code value.
     ///
-    /// The `unit` is the width of a single ring and `arc_adjustment` corrects the rotation.
-    fn read_code(&mut self, scanner: &Scanner, unit: f64, arc_adjustment: f64) -> usize {
+    /// The `unit` is the width of a single ring and `arc_adjustment` corrects the rotation. This
+    /// only samples into local buffers, so readings of different candidates are independent of each
+    /// other. Returns [None] if the rings do not match or no rotation of the bits is a valid code
+    /// of the family.
+    fn read_code(
+        &self,
+        scanner: &Scanner,
+        family: &MarkerFamily,
+        unit: f64,
+        arc_adjustment: f64,
+    ) -> Option<CodeReading> {
         let mut c = 0;
+        let mut rings = 0;
+        let mut ring_samples = 0;
         let mut bits = 0;
+        let mut core = [0; MAX_WIDTH];
+        // Bits of the sectors whose rings are damaged, and of those whose data is ambiguous
+        let recover = scanner.recovers_sector();
+        let mut damaged: Code = 0;
+        let mut ambiguous: Code = 0;
+
+        let width = family.width;
+        let data_ring = family.ring(family.data_ring);
+        let per_ring = (scanner.radial_samples() / width).max(1);
+        let directions = scanner.sector_directions(family);
+        let (sin_adjustment, cos_adjustment) = math::sin_cos(arc_adjustment);
+        let (cx, cy) = scanner.undistort(self.x, self.y);
 
-        for sector in (0..SECTORS).rev() {
-            let sector_f = sector as f64;
-            let dx = (ARC * sector_f + arc_adjustment).cos();
-            let dy = (ARC * sector_f + arc_adjustment).sin();
+        // Rotations of the extra rays either side of each sample ray, if any
+        let spread = scanner.angular_spread();
+        let rays: &[(f64, f64)] = if spread > 0.0 {
+            let (sin_spread, cos_spread) = math::sin_cos(spread);
+            &[
+                (0.0, 1.0),
+                (-sin_spread, cos_spread),
+                (sin_spread, cos_spread),
+            ]
+        } else {
+            &[(0.0, 1.0)]
+        };
 
-            // Take 8 samples across the diameter of the symbol
-            for i in 0..WIDTH {
+        'sectors: for sector in (0..family.sectors).rev() {
+            // Rotate the precomputed direction of the sector by the adjustment
+            let (sin, cos) = directions[sector];
+            let dx = cos * cos_adjustment - sin * sin_adjustment;
+            let dy = sin * cos_adjustment + cos * sin_adjustment;
+
+            // Take one sample per ring across the diameter of the symbol, averaging evenly spaced
+            // readings within the ring when more than one sample per ring is configured, and
+            // readings along the spread rays when supersampling
+            for (i, sample) in core.iter_mut().enumerate().take(width) {
                 let i_f = i as f64;
-                let dist = (i_f - 3.5) * unit;
+                let mut total = 0;
+                for &(sin_ray, cos_ray) in rays {
+                    let rx = dx * cos_ray - dy * sin_ray;
+                    let ry = dy * cos_ray + dx * sin_ray;
+                    for s in 0..per_ring {
+                        let offset = (s as f64 + 0.5) / per_ring as f64 - 0.5;
+                        let dist = (i_f + offset - (width - 1) as f64 / 2.0) * unit;
 
-                let sx = (self.x + dx * dist).round() as usize;
-                let sy = (self.y + dy * dist).round() as usize;
-                self.core[i] = scanner.get_sample_3x3(sx, sy);
+                        let (sx, sy) = scanner.distort(cx + rx * dist, cy + ry * dist);
+                        total += scanner.get_sample_3x3(sx.round() as usize, sy.round() as usize);
+                    }
+                }
+                *sample = total / (per_ring * rays.len());
             }
 
-            // White rings
-            if self.core[1] <= 128
-                || self.core[3] <= 128
-                || self.core[4] <= 128
-                || self.core[6] <= 128
-            {
-                return 0;
+            // Check the rings before counting them, so a damaged sector adds no confidence
+            let intact = core.iter().enumerate().take(width).all(|(i, &sample)| {
+                let ring = family.ring(i);
+                ring >= data_ring || (ring.is_multiple_of(2) == (sample > 128))
+            });
+            let data = core[family.data_ring];
+            let bit = if data > 128 { 1 } else { 0 };
+            bits <<= 1;
+            bits += bit;
+            if !intact {
+                if !recover {
+                    return None;
+                }
+                damaged |= 1 << sector;
+                continue 'sectors;
             }
-
-            // Black ring
-            if self.core[2] > 128 || self.core[5] > 128 {
-                return 0;
+            if data.abs_diff(128) < AMBIGUOUS_MARGIN {
+                ambiguous |= 1 << sector;
             }
 
-            // Compute confidence interval in core sample
-            c += self.core[1] // White rings
-                + self.core[3]
-                + self.core[4]
-                + self.core[6]
-                + (0xff - self.core[2]) // Black ring
-                + (0xff - self.core[5]);
+            for (i, &sample) in core.iter().enumerate().take(width) {
+                let ring = family.ring(i);
+                if ring >= data_ring {
+                    continue;
+                }
+
+                // Compute confidence interval in core sample
+                if ring.is_multiple_of(2) {
+                    // White rings
+                    c += sample;
+                    rings += sample;
+                } else {
+                    // Black rings
+                    c += 0xff - sample;
+                    rings += 0xff - sample;
+                }
+                ring_samples += 1;
+            }
 
             // Data rings
-            c += (self.core[7] as isize * 2 - 0xff).abs() as usize;
+            c += (data as isize * 2 - 0xff).unsigned_abs();
 
             // Opposite data ring
-            c += (0xff - (self.core[0] as isize * 2 - 0xff)) as usize;
+            let opposite = core[width - 1 - family.data_ring];
+            c += (0xff - (opposite as isize * 2 - 0xff)) as usize;
+        }
 
-            let bit = if self.core[7] > 128 { 1 } else { 0 };
-            bits <<= 1;
-            bits += bit;
+        if recover && damaged == 0 && !family.is_code(bits) {
+            damaged = ambiguous;
+        }
+        if damaged != 0 {
+            // Try both values of the single damaged sector, and keep the one that is valid
+            if damaged.count_ones() > 1 {
+                return None;
+            }
+            let candidates = [bits & !damaged, bits | damaged];
+            let mut valid = candidates.into_iter().filter(|&bits| family.is_code(bits));
+            bits = valid.next()?;
+            if valid.next().is_some() {
+                return None;
+            }
+        }
+        if !family.is_code(bits) || c == 0 {
+            return None;
         }
 
-        if Self::checksum(bits) {
-            self.code = Some(bits);
-            c
-        } else {
-            self.code = None;
-            0
+        // The samples of the last sector read (sector 0) are reported in [TopCode::core]
+        Some(CodeReading {
+            confidence: c,
+            rings: rings as f64 / (ring_samples.max(1) * 0xff) as f64,
+            code: bits,
+            core,
+            width,
+        })
+    }
+
+    /// Searches the arc adjustments within half a sector of `around` for the one that best aligns
+    /// the data ring with the `bits` read at `around`, with a golden-section search, until the
+    /// bracket is narrower than `precision`. Returns the reading at the best adjustment and the
+    /// adjustment, or `None` if the bits read there differ.
+    ///
+    /// The readings themselves score about the same wherever the samples fall within their
+    /// sectors, so the alignment is scored by sampling the whole span of every sector instead,
+    /// which peaks when the sectors are centered on the sample rays.
+    fn refine_arc(
+        &self,
+        scanner: &Scanner,
+        family: &MarkerFamily,
+        unit: f64,
+        around: f64,
+        bits: Code,
+        precision: f64,
+    ) -> Option<(CodeReading, f64)> {
+        let arc = family.arc();
+        let radius = (family.data_ring as f64 - (family.width - 1) as f64 / 2.0) * unit;
+        let (cx, cy) = scanner.undistort(self.x, self.y);
+        let score = |a: f64| {
+            let mut score = 0;
+            for sector in 0..family.sectors {
+                let white = (bits >> sector) & 0x01 == 1;
+                for i in 0..ALIGNMENT_SAMPLES {
+                    let t = (i as f64 + 0.5) / ALIGNMENT_SAMPLES as f64 - 0.5;
+                    let (sin, cos) = math::sin_cos(arc * (sector as f64 + t) + a);
+                    let (sx, sy) = scanner.distort(cx + cos * radius, cy + sin * radius);
+                    let sample = scanner.get_sample_3x3(sx.round() as usize, sy.round() as usize);
+                    score += if white { sample } else { 0xff - sample };
+                }
+            }
+            score
+        };
+
+        let ratio = (5f64.sqrt() - 1.0) / 2.0;
+        let (mut low, mut high) = (around - arc / 2.0, around + arc / 2.0);
+        let mut c = high - ratio * (high - low);
+        let mut d = low + ratio * (high - low);
+        let (mut score_c, mut score_d) = (score(c), score(d));
+
+        while high - low > precision {
+            if score_c >= score_d {
+                (high, d, score_d) = (d, c, score_c);
+                c = high - ratio * (high - low);
+                score_c = score(c);
+            } else {
+                (low, c, score_c) = (c, d, score_d);
+                d = low + ratio * (high - low);
+                score_d = score(d);
+            }
         }
+
+        let a = (low + high) / 2.0;
+        let reading = self.read_code(scanner, family, unit, a)?;
+        (reading.code == bits).then_some((reading, a))
+    }
+
+    /// Measures the orientation of a decoded symbol from the image instead of its bits.
+    ///
+    /// The lowest rotation of a valid code always starts with a white sector (bit 0) that follows
+    /// a black sector, so the orientation can be found by locating that black-to-white edge on the
+    /// data ring. The data ring is sampled in small angular steps up to a sector and a quarter
+    /// either side of where [TopCode::orientation] places the edge, and the closest edge is used.
+    /// The result uses the same convention as [TopCode::orientation], or is `None` if no edge was
+    /// found.
+    pub fn geometric_orientation(&self, scanner: &Scanner) -> Option<f64> {
+        self.measure_orientation(scanner, &MarkerFamily::TOPCODE)
     }
 
-    /// Tries each of the possible rotations and returns the lowest.
-    fn rotate_lowest(&mut self, mut bits: Code, mut arc_adjustment: f64) -> Code {
-        let mut min = bits;
-        let mask = 0x1fff;
+    /// See [TopCode::geometric_orientation].
+    fn measure_orientation(&self, scanner: &Scanner, family: &MarkerFamily) -> Option<f64> {
+        // [TopCode::rotate_lowest] places the orientation 0.65 sectors before the sample that
+        // read bit 0, and that sample ideally sits in the middle of its sector.
+        let arc = family.arc();
+        let offset = arc * 0.15;
+        let expected = self.orientation + offset;
+        let radius = (family.data_ring as f64 - (family.width - 1) as f64 / 2.0) * self.unit;
+        let step = arc / 16.0;
+
+        let (cx, cy) = scanner.undistort(self.x, self.y);
 
-        arc_adjustment -= ARC * 0.65;
+        let mut previous = None;
+        let mut edge: Option<f64> = None;
 
-        self.orientation = 0.0;
+        for i in -20..=20 {
+            let angle = expected + step * i as f64;
+            let (sin, cos) = math::sin_cos(angle);
+            let (sx, sy) = scanner.distort(cx + cos * radius, cy + sin * radius);
+            let white = scanner.get_sample_3x3(sx.round() as usize, sy.round() as usize) > 128;
 
-        for i in 1..=SECTORS {
-            bits = ((bits << 1) & mask) | (bits >> (SECTORS - 1));
-            if bits < min {
-                min = bits;
-                self.orientation = i as f64 * -ARC;
+            if previous == Some(false) && white {
+                let candidate = angle - step / 2.0;
+                if edge.is_none_or(|e| (candidate - expected).abs() < (e - expected).abs()) {
+                    edge = Some(candidate);
+                }
             }
+            previous = Some(white);
         }
 
-        self.orientation += arc_adjustment;
-        min
+        edge.map(|edge| edge - offset)
     }
 
-    /// Only codes with a checksum of 5 are valid.
-    pub fn checksum(mut bits: Code) -> bool {
-        let mut sum = 0;
-        for _i in 0..SECTORS {
-            sum += bits & 0x01;
-            bits >>= 1;
+    /// Estimates how sharply the symbol is in focus, between 0 (blurred) and 1 (sharp), e.g. as the
+    /// objective of a camera control loop that should focus on the markers rather than the scene.
+    ///
+    /// The intensity kept by the scanner (see [Scanner::intensity_at]) is read along the columns
+    /// through the bullseye, above and below its center, out to the white ring. For each profile,
+    /// the steepest step between neighbouring pixels is divided by the intensity range of the
+    /// whole profile, so an edge blurred over more pixels scores lower. The scanner keeps a running
+    /// average along each row rather than the pixels themselves, so even a perfectly sharp symbol
+    /// scores below 1 and the value is best compared across frames of the same scene. Returns 0 if
+    /// the rings have too little contrast to be measured.
+    pub fn focus(&self, scanner: &Scanner) -> f64 {
+        let (width, height) = scanner.dimensions();
+        let reach = (self.unit * 3.0).round() as isize;
+        let (cx, cy) = (self.x.round() as isize, self.y.round() as isize);
+
+        let mut total = 0.0;
+        let mut profiles = 0;
+        for dx in -1..=1 {
+            for dy in [-1, 1] {
+                let x = cx + dx;
+                let profile: Vec<i32> = (0..=reach)
+                    .map(|k| cy + dy * k)
+                    .take_while(|&y| x >= 0 && y >= 0 && x < width as isize && y < height as isize)
+                    .map(|y| scanner.intensity_at(x as usize, y as usize) as i32)
+                    .collect();
+
+                let (min, max) = profile.iter().fold((i32::MAX, i32::MIN), |(min, max), &v| {
+                    (min.min(v), max.max(v))
+                });
+                if profile.len() < 2 || max - min < MIN_FOCUS_CONTRAST {
+                    continue;
+                }
+
+                let steepest = profile
+                    .windows(2)
+                    .map(|pair| (pair[1] - pair[0]).abs())
+                    .max()
+                    .unwrap_or(0);
+                total += steepest as f64 / (max - min) as f64;
+                profiles += 1;
+            }
         }
 
-        sum == 5
+        if profiles == 0 {
+            0.0
+        } else {
+            total / profiles as f64
+        }
     }
 
-    /// Returns true if the given point is inside the bullseye
-    pub(crate) fn in_bullseye(&self, px: f64, py: f64) -> bool {
-        ((self.x - px) * (self.x - px) + (self.y - py) * (self.y - py)) <= (self.unit * self.unit)
+    /// Tries each of the possible rotations and returns the lowest valid code of the family among
+    /// them (see [MarkerFamily::bit_mapping]), orienting the symbol to match.
+    fn rotate_lowest(
+        &mut self,
+        family: &MarkerFamily,
+        bits: Code,
+        arc_adjustment: f64,
+    ) -> Option<Code> {
+        let (code, rotation) = family.lowest_code(bits)?;
+        let arc = family.arc();
+        self.orientation = rotation as f64 * -arc + (arc_adjustment - arc * 0.65);
+        Some(code)
+    }
+
+    /// Returns true if the given point is strictly inside the symbol, within its [radius].
+    ///
+    /// [radius]: TopCode::radius
+    pub(crate) fn covers(&self, px: f64, py: f64) -> bool {
+        math::hypot(self.x - px, self.y - py) < self.radius()
     }
 
     /// Determines the symbol's unit length by counting the number of pixels between the outer
     /// edges of the first black ring. North, south, east, and west readings are taken and the
     /// average is returned.
     fn read_unit(&self, scanner: &Scanner) -> f64 {
-        let sx = self.x.round() as usize;
-        let sy = self.y.round() as usize;
+        match self.ring_distances(scanner) {
+            Some([left, right, up, down]) => {
+                let u = (right + left + up + down) as f64 / 8.0;
+                if (right + left - up - down).abs() as f64 > u * scanner.max_ring_asymmetry() {
+                    -1.0
+                } else {
+                    u
+                }
+            }
+            None if scanner.allows_missing_edge() => self.read_unit_from_three_edges(scanner),
+            None => -1.0,
+        }
+    }
 
-        let image_width = scanner.image_width();
-        let image_height = scanner.image_height();
+    /// Estimates the unit from the distances between the center and the edge of the white core of
+    /// the bullseye, which is a unit in every direction, as the median of the directions in which
+    /// the edge is found. Returns [None] if it is found in fewer than two directions.
+    fn core_unit(&self, scanner: &Scanner) -> Option<f64> {
+        let (x, y) = (self.x.round() as usize, self.y.round() as usize);
+        let mut distances = [f64::INFINITY; 4];
+        let mut found = 0;
+        for (dx, dy) in [(-1, 0), (1, 0), (0, -1), (0, 1)] {
+            if let Some(distance) = scanner.dist_subpixel(x, y, dx, dy) {
+                distances[found] = distance;
+                found += 1;
+            }
+        }
+        if found < 2 {
+            return None;
+        }
 
-        let mut white_left = true;
-        let mut white_right = true;
-        let mut white_up = true;
-        let mut white_down = true;
+        let distances = &mut distances[..found];
+        distances.sort_by(f64::total_cmp);
+        Some((distances[(found - 1) / 2] + distances[found / 2]) / 2.0)
+    }
 
-        let mut dist_left = 0;
-        let mut dist_right = 0;
-        let mut dist_up = 0;
-        let mut dist_down = 0;
+    /// Estimates the unit from three of the four ring edges when the fourth is not found, e.g.
+    /// because something dark crosses the ring on that side. The distance in the direction opposite
+    /// the missing edge, doubled, must agree with the distances along the other axis within the
+    /// same tolerance [TopCode::read_unit] allows between the two axes.
+    fn read_unit_from_three_edges(&self, scanner: &Scanner) -> f64 {
+        let edges = [(-1, 0), (1, 0), (0, -1), (0, 1)]
+            .map(|direction| self.ring_edge(scanner, direction, usize::MAX));
 
-        for i in 1..=MAX_PIXELS {
-            if sx < 1 + i || sx + i >= image_width - 1 || sy < 1 + i || sy + i >= image_height - 1 {
-                return -1.0;
-            }
+        let (lone, pair) = match edges {
+            [None, Some(right), Some(up), Some(down)] => (right, up + down),
+            [Some(left), None, Some(up), Some(down)] => (left, up + down),
+            [Some(left), Some(right), None, Some(down)] => (down, left + right),
+            [Some(left), Some(right), Some(up), None] => (up, left + right),
+            _ => return -1.0,
+        };
 
-            // Left sample
-            let sample = scanner.get_bw_3x3(sx - i, sy);
-            if dist_left <= 0 {
-                if white_left && sample == 0 {
-                    white_left = false
-                } else if !white_left && sample == 1 {
-                    dist_left = i as isize;
-                }
-            }
+        let u = (lone + pair) as f64 / 6.0;
+        if (2 * lone - pair).abs() as f64 > u * scanner.max_ring_asymmetry() {
+            -1.0
+        } else {
+            u
+        }
+    }
 
-            // Right sample
-            let sample = scanner.get_bw_3x3(sx + i, sy);
-            if dist_right <= 0 {
-                if white_right && sample == 0 {
-                    white_right = false
-                } else if !white_right && sample == 1 {
-                    dist_right = i as isize;
-                }
-            }
+    /// Measures the distance from the center to the outer edge of the black bullseye ring in each
+    /// direction, as `[left, right, up, down]`. Returns [None] if any edge is not found before
+    /// running out of the image in any direction.
+    fn ring_distances(&self, scanner: &Scanner) -> Option<[isize; 4]> {
+        let limit = self.edge_limits(scanner).into_iter().min().unwrap_or(0);
 
-            // Up sample
-            let sample = scanner.get_bw_3x3(sx, sy - i);
-            if dist_up <= 0 {
-                if white_up && sample == 0 {
-                    white_up = false
-                } else if !white_up && sample == 1 {
-                    dist_up = i as isize;
-                }
-            }
+        let [left, right, up, down] = [(-1, 0), (1, 0), (0, -1), (0, 1)]
+            .map(|direction| self.ring_edge(scanner, direction, limit));
+        Some([left?, right?, up?, down?])
+    }
 
-            // Down sample
-            let sample = scanner.get_bw_3x3(sx, sy + i);
-            if dist_down <= 0 {
-                if white_down && sample == 0 {
-                    white_down = false
-                } else if !white_down && sample == 1 {
-                    dist_down = i as isize;
-                }
+    /// Distance from the center to the outer edge of the black bullseye ring in the direction
+    /// `(dx, dy)`, or [None] if the edge is not found within `limit` pixels, [MAX_PIXELS], or the
+    /// image.
+    fn ring_edge(
+        &self,
+        scanner: &Scanner,
+        (dx, dy): (isize, isize),
+        limit: usize,
+    ) -> Option<isize> {
+        let limits = self.edge_limits(scanner);
+        let bound = match (dx, dy) {
+            (-1, _) => limits[0],
+            (1, _) => limits[1],
+            (_, -1) => limits[2],
+            _ => limits[3],
+        };
+
+        // Vertical steps cover more of the image when pixels are wider than they are tall
+        let bound = match dy {
+            0 => bound,
+            _ => (bound as f64 / scanner.pixel_aspect_ratio()) as usize,
+        };
+
+        let (sx, sy) = (self.x.round() as usize, self.y.round() as usize);
+        let (cx, cy) = scanner.undistort(sx as f64, sy as f64);
+        let mut white = true;
+        for i in 1..=limit.min(bound) as isize {
+            let (x, y) = scanner.distort(cx + (dx * i) as f64, cy + (dy * i) as f64);
+            let sample = scanner.get_bw_3x3(x.round() as usize, y.round() as usize);
+            if white && sample == 0 {
+                white = false;
+            } else if !white && sample == 1 {
+                return Some(i);
             }
+        }
 
-            if dist_right > 0 && dist_left > 0 && dist_up > 0 && dist_down > 0 {
-                let u = (dist_right + dist_left + dist_up + dist_down) as f64 / 8.0;
-                return if (dist_right + dist_left - dist_up - dist_down).abs() as f64 > u {
-                    -1.0
-                } else {
-                    u
-                };
+        None
+    }
+
+    /// Difference between the horizontal and vertical diameters of the black bullseye ring around
+    /// the center of this code, measured in units, as the scanner compares it against
+    /// [Scanner::set_max_ring_asymmetry]. A marker facing the camera has an asymmetry close to 0,
+    /// and the asymmetry grows as it tilts away. Returns `None` if an edge of the ring is not
+    /// found.
+    pub fn ring_asymmetry(&self, scanner: &Scanner) -> Option<f64> {
+        let [left, right, up, down] = self.ring_distances(scanner)?;
+        let u = (right + left + up + down) as f64 / 8.0;
+        Some((right + left - up - down).abs() as f64 / u)
+    }
+
+    /// Ratio between the shorter and the longer of the horizontal and vertical diameters of the
+    /// bullseye, between 0 and 1. Returns 1 if an edge of the bullseye is not found, since the
+    /// diameters cannot be compared.
+    fn ring_symmetry(&self, scanner: &Scanner) -> f64 {
+        match self.ring_distances(scanner) {
+            Some([left, right, up, down]) => {
+                let (horizontal, vertical) = (left + right, up + down);
+                horizontal.min(vertical) as f64 / horizontal.max(vertical).max(1) as f64
             }
+            None => 1.0,
         }
+    }
+
+    /// Furthest distance that can be sampled from the center to the left, right, up and down,
+    /// keeping a pixel between the samples and the image border.
+    fn edge_limits(&self, scanner: &Scanner) -> [usize; 4] {
+        let sx = self.x.round() as usize;
+        let sy = self.y.round() as usize;
 
-        -1.0
+        [
+            sx.saturating_sub(1),
+            scanner.image_width().saturating_sub(sx + 2),
+            sy.saturating_sub(1),
+            scanner.image_height().saturating_sub(sy + 2),
+        ]
+        .map(|limit| limit.min(MAX_PIXELS))
+    }
+
+    /// Returns true if the center of the code is a finite point inside the image.
+    fn is_inside(&self, scanner: &Scanner) -> bool {
+        (0.0..scanner.image_width() as f64).contains(&self.x)
+            && (0.0..scanner.image_height() as f64).contains(&self.y)
     }
 
     /// A method used to draw the current TopCode. This should only be conditionally compiled for
@@ -349,9 +866,25 @@ impl TopCode {
     }
 }
 
+/// A successful reading of the data ring by [TopCode::read_code].
+struct CodeReading {
+    /// Sum of how clearly every sample matched its expected color
+    confidence: usize,
+    /// How clearly the samples inside the data ring matched their expected color on average,
+    /// between 0 and 1
+    rings: f64,
+    /// Bits read from the data ring, before rotation
+    code: Code,
+    /// Samples across the diameter of the last sector read, in the first `width` entries
+    core: [usize; MAX_WIDTH],
+    /// Width of the family that was read
+    width: usize,
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::test_utils::{render, rgb};
 
     #[test]
     fn checksum_is_valid() {
@@ -364,16 +897,183 @@ mod tests {
     }
 
     #[test]
-    fn point_is_in_bullseye() {
+    fn degenerate_candidates_are_rejected() {
+        // A code cut off by the left edge, and one whose bullseye is cut off by the bottom edge
+        let buffer = render(
+            100,
+            60,
+            &[(31, 4.0, 30.0, 5.0, 0.0), (55, 70.0, 57.0, 5.0, 0.0)],
+        );
+        let mut scanner = Scanner::new(100, 60);
+        let _ = scanner.scan(buffer.as_slice(), rgb);
+
+        for (cx, cy) in [
+            (0, 0),
+            (99, 59),
+            (4, 30),
+            (70, 57),
+            (100, 30),
+            (usize::MAX, usize::MAX),
+        ] {
+            let mut topcode = TopCode::default();
+            assert_eq!(None, topcode.decode(&scanner, cx, cy), "at {:?}", (cx, cy));
+            assert!(topcode.x.is_finite() && topcode.y.is_finite());
+            assert!(topcode.unit.is_finite());
+            assert_eq!(0.0, topcode.confidence);
+        }
+    }
+
+    #[test]
+    fn ring_asymmetry_limits_the_units_read() {
+        // A marker stretched vertically by 15%, as if tilted away from the camera
+        let (width, height) = (100, 115);
+        let source = render(width, 100, &[(31, 50.0, 50.0, 5.0, 0.0)]);
+        let buffer: Vec<u8> = (0..width * height)
+            .flat_map(|i| {
+                let (x, y) = (i % width, (i / width) as f64 / 1.15);
+                let j = (y as usize * width + x) * 3;
+                [source[j], source[j + 1], source[j + 2]]
+            })
+            .collect();
+        let mut scanner = Scanner::new(width, height);
+        let _ = scanner.scan(buffer.as_slice(), rgb);
+        let topcode = TopCode::mock(31, 5.0, 0.0, 50.0, 57.5);
+
+        let asymmetry = topcode.ring_asymmetry(&scanner).unwrap();
+        assert!((0.3..1.0).contains(&asymmetry), "{}", asymmetry);
+        assert!(topcode.read_unit(&scanner) > 0.0);
+
+        scanner.set_max_ring_asymmetry(asymmetry + 1e-9);
+        assert!(topcode.read_unit(&scanner) > 0.0);
+        scanner.set_max_ring_asymmetry(asymmetry - 1e-9);
+        assert_eq!(-1.0, topcode.read_unit(&scanner));
+
+        let square = TopCode::mock(31, 5.0, 0.0, 50.0, 50.0);
+        let source_scanner = {
+            let mut scanner = Scanner::new(width, 100);
+            let _ = scanner.scan(source.as_slice(), rgb);
+            scanner
+        };
+        assert_eq!(Some(0.0), square.ring_asymmetry(&source_scanner));
+    }
+
+    #[test]
+    fn lerp_interpolates_position_and_unit() {
+        let a = TopCode::mock(31, 4.0, 0.0, 10.0, 20.0);
+        let b = TopCode::mock(31, 8.0, 1.0, 30.0, 60.0);
+        let mid = a.lerp(&b, 0.5);
+
+        assert_eq!(
+            (6.0, 0.5, 20.0, 40.0),
+            (mid.unit, mid.orientation, mid.x, mid.y)
+        );
+        assert_eq!(a, a.lerp(&b, 0.0));
+    }
+
+    #[test]
+    fn lerp_takes_the_shortest_arc_across_the_wrap() {
+        let a = TopCode::mock(31, 4.0, PI - 0.1, 0.0, 0.0);
+        let b = TopCode::mock(31, 4.0, -PI + 0.1, 0.0, 0.0);
+
+        assert!((a.lerp(&b, 0.5).orientation - PI).abs() < 1e-9);
+        assert!((b.lerp(&a, 0.5).orientation + PI).abs() < 1e-9);
+        assert!((a.lerp(&b, 1.0).orientation - (PI + 0.1)).abs() < 1e-9);
+    }
+
+    #[test]
+    fn relative_to_an_unrotated_reference_scales_by_its_unit() {
+        let reference = TopCode::mock(31, 5.0, 0.0, 100.0, 100.0);
+        let other = TopCode::mock(93, 5.0, 0.5, 150.0, 80.0);
+
+        assert_eq!((10.0, -4.0, 0.5), other.relative_to(&reference));
+        assert_eq!((0.0, 0.0, 0.0), reference.relative_to(&reference));
+    }
+
+    #[test]
+    fn relative_to_a_rotated_reference_rotates_the_axes() {
+        let reference = TopCode::mock(31, 10.0, PI / 2.0, 100.0, 100.0);
+        let other = TopCode::mock(93, 10.0, -PI + 0.25, 100.0, 150.0);
+        let (x, y, orientation) = other.relative_to(&reference);
+
+        assert!((x - 5.0).abs() < 1e-9);
+        assert!(y.abs() < 1e-9);
+        assert!((orientation - (PI / 2.0 + 0.25)).abs() < 1e-9);
+    }
+
+    #[test]
+    fn project_is_the_inverse_of_relative_to() {
+        let reference = TopCode::mock(31, 4.0, 0.5, 10.0, 20.0);
+        let (x, y) = reference.project(1.5, -2.0);
+        let (u, v, _) = TopCode::mock(31, 4.0, 0.0, x, y).relative_to(&reference);
+
+        assert!((u - 1.5).abs() < 1e-9 && (v + 2.0).abs() < 1e-9);
+        assert_eq!((10.0, 20.0), reference.project(0.0, 0.0));
+    }
+
+    #[test]
+    fn same_code_elsewhere_is_equal_ignoring_position() {
+        let a = TopCode::mock(31, 4.0, 0.0, 10.0, 20.0);
+        let b = TopCode::mock(31, 8.0, 1.0, 30.0, 60.0);
+
+        assert_ne!(a, b);
+        assert!(a.eq_ignoring_position(&b));
+        assert!(!a.eq_ignoring_position(&TopCode::mock(93, 4.0, 0.0, 10.0, 20.0)));
+    }
+
+    #[test]
+    fn point_is_covered() {
         let topcode = TopCode::default();
-        assert!(topcode.in_bullseye(0.0, 0.0));
-        assert!(topcode.in_bullseye(topcode.unit, 0.0));
-        assert!(topcode.in_bullseye(0.0, topcode.unit));
+        assert!(topcode.covers(0.0, 0.0));
+        assert!(topcode.covers(topcode.unit, topcode.unit));
+        assert!(topcode.covers(0.0, topcode.radius() - 0.1));
     }
 
     #[test]
-    fn point_is_not_in_bullseye() {
+    fn radius_follows_the_width_of_the_family() {
+        let topcode = TopCode {
+            width: 12,
+            ..TopCode::mock(31, 4.0, 0.0, 0.0, 0.0)
+        };
+        assert_eq!(24.0, topcode.radius());
+        assert!(topcode.covers(20.0, 0.0));
+        assert!(!TopCode::mock(31, 4.0, 0.0, 0.0, 0.0).covers(20.0, 0.0));
+    }
+
+    #[test]
+    fn point_is_not_covered() {
         let topcode = TopCode::default();
-        assert!(!topcode.in_bullseye(topcode.unit, topcode.unit));
+        assert!(!topcode.covers(topcode.radius(), 0.0));
+        assert!(!topcode.covers(topcode.radius(), topcode.radius()));
+    }
+
+    #[test]
+    fn orientation_can_be_refined() {
+        // The orientation sits 0.15 sectors before the rendered angle: it is placed 0.65 sectors
+        // before the sample that read bit 0, which is centered in its sector
+        let offset = 0.15 * 2.0 * PI / SECTORS as f64;
+        let errors = |precision| {
+            let mut scanner = Scanner::new(100, 100);
+            scanner.set_orientation_precision(precision);
+            (0..12)
+                .map(|i| {
+                    let angle = 0.2 + i as f64 * 0.173;
+                    let buffer = render(100, 100, &[(31, 50.3, 49.6, 4.0, angle)]);
+                    let topcodes = scanner.scan(buffer.as_slice(), rgb);
+                    assert_eq!(Some(31), topcodes[0].code);
+                    let error = topcodes[0].orientation - (angle - offset);
+                    ((error + PI).rem_euclid(2.0 * PI) - PI).abs()
+                })
+                .collect::<Vec<_>>()
+        };
+        let mean = |errors: &[f64]| errors.iter().sum::<f64>() / errors.len() as f64;
+
+        let coarse = errors(None);
+        let refined = errors(Some(0.001));
+
+        assert!(refined.iter().all(|&error| error < 0.05), "{refined:?}");
+        assert!(
+            mean(&refined) < mean(&coarse) / 2.0,
+            "{refined:?} {coarse:?}"
+        );
     }
 }
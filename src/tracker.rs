@@ -0,0 +1,380 @@
+use std::{
+    collections::{BTreeMap, VecDeque},
+    f64::consts::PI,
+    fmt,
+};
+
+use crate::topcode::{Code, TopCode};
+
+/// A change in the set of markers reported by a [Tracker].
+#[derive(Clone, Debug, PartialEq)]
+pub enum TrackEvent {
+    /// The marker was present for long enough to be reported. Holds the latest detection.
+    Appeared(TopCode),
+    /// The marker was absent for long enough to be reported gone. Holds the last detection.
+    Disappeared(TopCode),
+}
+
+/// Follows markers across the frames of a video by their code, and reports when they appear and
+/// disappear. Markers sometimes drop out of a single frame because of noise, so the tracker can
+/// debounce them: a marker must be present for a number of consecutive frames before it is
+/// reported, and absent for a number of consecutive frames before it is reported gone. Both
+/// default to a single frame, which reports every change immediately.
+///
+/// The orientation of each marker can be stabilized over a window of recent frames (see
+/// [Tracker::set_orientation_window]).
+///
+/// Instead of going through the events returned by [Tracker::update], apps can register callbacks
+/// with [Tracker::on_enter], [Tracker::on_exit] and [Tracker::on_move], which are called during
+/// the update that reports the change.
+///
+/// Every code is assumed to identify a single marker. If a code is detected more than once in a
+/// frame, only the first detection is followed.
+pub struct Tracker {
+    frames_to_appear: usize,
+    frames_to_disappear: usize,
+    orientation_window: usize,
+    move_threshold: f64,
+    tracks: BTreeMap<Code, Track>,
+    on_enter: Vec<Callback>,
+    on_exit: Vec<Callback>,
+    on_move: Vec<Callback>,
+}
+
+/// A closure registered with [Tracker::on_enter], [Tracker::on_exit] or [Tracker::on_move].
+type Callback = Box<dyn FnMut(&TopCode) + Send>;
+
+/// A change of a marker found by [Tracker::update], which is reported to the callbacks.
+enum Change {
+    Entered,
+    Exited,
+    Moved,
+}
+
+/// The state of a single code followed by a [Tracker].
+#[derive(Debug)]
+struct Track {
+    /// Latest detection of the code, with the stabilized orientation
+    topcode: TopCode,
+    /// Orientations of the latest detections, oldest first
+    orientations: VecDeque<f64>,
+    /// Number of consecutive frames the code was detected in
+    present: usize,
+    /// Number of consecutive frames the code was missing from
+    absent: usize,
+    /// Whether the code was reported as appeared
+    reported: bool,
+    /// Position at which the code was last reported to have appeared or moved
+    anchor: (f64, f64),
+}
+
+impl Tracker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the number of consecutive frames a marker must be detected in before it is reported.
+    /// Values below 1 are treated as 1.
+    pub fn set_frames_to_appear(&mut self, frames: usize) {
+        self.frames_to_appear = frames.max(1);
+    }
+
+    /// Sets the number of consecutive frames a reported marker must be missing from before it is
+    /// reported gone. Until then, [Tracker::markers] keeps its last detection. Values below 1 are
+    /// treated as 1.
+    pub fn set_frames_to_disappear(&mut self, frames: usize) {
+        self.frames_to_disappear = frames.max(1);
+    }
+
+    /// Sets the number of recent detections over which the orientation of each marker is
+    /// stabilized. The reported orientation is the circular median of the orientations detected
+    /// in the window, which ignores a detection that jumps by a sector in a single frame, e.g. on
+    /// nearly symmetric codes. The median is measured along the circle, so orientations either
+    /// side of ±π are close. Defaults to 1, which reports the orientation of the latest detection.
+    /// Values below 1 are treated as 1.
+    pub fn set_orientation_window(&mut self, frames: usize) {
+        self.orientation_window = frames.max(1);
+    }
+
+    /// Sets the distance in pixels a reported marker must move before the [Tracker::on_move]
+    /// callbacks are called. The distance is measured from where the marker was when it appeared
+    /// or last moved, so a marker that drifts slowly is still reported once it has gone far
+    /// enough, and the jitter of a still marker is not. Defaults to 1 pixel.
+    pub fn set_move_threshold(&mut self, pixels: f64) {
+        self.move_threshold = pixels;
+    }
+
+    /// Registers a closure that is called with the latest detection of every marker reported as
+    /// appeared, i.e. for every [TrackEvent::Appeared].
+    ///
+    /// Callbacks are called during [Tracker::update], after the tracker has recorded the frame,
+    /// in ascending order of code, and for each change in the order they were registered. A
+    /// marker appears, moves or disappears at most once per update, so only one kind of callback
+    /// is called for it. Callbacks cannot reach the tracker, which is borrowed by the update, so
+    /// apps that need to change it in response should collect what they need and do so after the
+    /// update returns.
+    pub fn on_enter(&mut self, callback: impl FnMut(&TopCode) + Send + 'static) {
+        self.on_enter.push(Box::new(callback));
+    }
+
+    /// Registers a closure that is called with the last detection of every marker reported gone,
+    /// i.e. for every [TrackEvent::Disappeared]. See [Tracker::on_enter] for the order of the
+    /// calls.
+    pub fn on_exit(&mut self, callback: impl FnMut(&TopCode) + Send + 'static) {
+        self.on_exit.push(Box::new(callback));
+    }
+
+    /// Registers a closure that is called with the latest detection of every reported marker that
+    /// moved further than the [move threshold](Tracker::set_move_threshold). A marker is not
+    /// reported as moved in the update it appears in. See [Tracker::on_enter] for the order of the
+    /// calls.
+    pub fn on_move(&mut self, callback: impl FnMut(&TopCode) + Send + 'static) {
+        self.on_move.push(Box::new(callback));
+    }
+
+    /// Records the codes found by the scan of the next frame, and returns the markers that
+    /// appeared or disappeared with it, in ascending order of code. Codes that were not decoded
+    /// are ignored. The registered callbacks are called before this returns.
+    pub fn update(&mut self, codes: &[TopCode]) -> Vec<TrackEvent> {
+        let mut detected: BTreeMap<Code, &TopCode> = BTreeMap::new();
+        for topcode in codes {
+            if let Some(code) = topcode.code {
+                detected.entry(code).or_insert(topcode);
+            }
+        }
+
+        for (&code, &topcode) in &detected {
+            let track = self.tracks.entry(code).or_insert(Track {
+                topcode: *topcode,
+                orientations: VecDeque::new(),
+                present: 0,
+                absent: 0,
+                reported: false,
+                anchor: (topcode.x, topcode.y),
+            });
+            track.orientations.push_back(topcode.orientation);
+            while track.orientations.len() > self.orientation_window {
+                track.orientations.pop_front();
+            }
+            track.topcode = TopCode {
+                orientation: circular_median(&track.orientations),
+                ..*topcode
+            };
+            track.present += 1;
+            track.absent = 0;
+        }
+
+        let mut changes = Vec::new();
+        self.tracks.retain(|code, track| {
+            let topcode = track.topcode;
+            if detected.contains_key(code) {
+                let (x, y) = track.anchor;
+                if !track.reported && track.present >= self.frames_to_appear {
+                    track.reported = true;
+                    track.anchor = (topcode.x, topcode.y);
+                    changes.push((Change::Entered, topcode));
+                } else if track.reported
+                    && (topcode.x - x).hypot(topcode.y - y) > self.move_threshold
+                {
+                    track.anchor = (topcode.x, topcode.y);
+                    changes.push((Change::Moved, topcode));
+                }
+                return true;
+            }
+
+            track.present = 0;
+            track.absent += 1;
+            if !track.reported {
+                return false;
+            }
+            if track.absent >= self.frames_to_disappear {
+                changes.push((Change::Exited, topcode));
+                return false;
+            }
+            true
+        });
+
+        let mut events = Vec::new();
+        for (change, topcode) in changes {
+            let (callbacks, event) = match change {
+                Change::Entered => (&mut self.on_enter, Some(TrackEvent::Appeared(topcode))),
+                Change::Exited => (&mut self.on_exit, Some(TrackEvent::Disappeared(topcode))),
+                Change::Moved => (&mut self.on_move, None),
+            };
+            for callback in callbacks.iter_mut() {
+                callback(&topcode);
+            }
+            events.extend(event);
+        }
+        events
+    }
+
+    /// Returns the latest detection of every marker that is currently reported, in ascending order
+    /// of code.
+    pub fn markers(&self) -> impl Iterator<Item = &TopCode> + '_ {
+        self.tracks
+            .values()
+            .filter(|track| track.reported)
+            .map(|track| &track.topcode)
+    }
+
+    /// Forgets every marker without reporting them gone.
+    pub fn clear(&mut self) {
+        self.tracks.clear();
+    }
+}
+
+impl Default for Tracker {
+    fn default() -> Self {
+        Self {
+            frames_to_appear: 1,
+            frames_to_disappear: 1,
+            orientation_window: 1,
+            move_threshold: 1.0,
+            tracks: BTreeMap::new(),
+            on_enter: Vec::new(),
+            on_exit: Vec::new(),
+            on_move: Vec::new(),
+        }
+    }
+}
+
+impl fmt::Debug for Tracker {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // Closures cannot be printed, so only their number is shown
+        f.debug_struct("Tracker")
+            .field("frames_to_appear", &self.frames_to_appear)
+            .field("frames_to_disappear", &self.frames_to_disappear)
+            .field("orientation_window", &self.orientation_window)
+            .field("move_threshold", &self.move_threshold)
+            .field("tracks", &self.tracks)
+            .field("on_enter", &self.on_enter.len())
+            .field("on_exit", &self.on_exit.len())
+            .field("on_move", &self.on_move.len())
+            .finish()
+    }
+}
+
+/// Returns the angle with the smallest total distance along the circle to all the others, which
+/// is one of the given angles.
+fn circular_median(angles: &VecDeque<f64>) -> f64 {
+    let distance = |a: f64, b: f64| ((a - b + PI).rem_euclid(2.0 * PI) - PI).abs();
+    let total = |a: f64| angles.iter().map(|&b| distance(a, b)).sum::<f64>();
+
+    angles
+        .iter()
+        .copied()
+        .min_by(|&a, &b| total(a).total_cmp(&total(b)))
+        .unwrap_or(0.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn codes(events: &[TrackEvent]) -> Vec<(bool, Code)> {
+        events
+            .iter()
+            .map(|event| match event {
+                TrackEvent::Appeared(topcode) => (true, topcode.code.unwrap()),
+                TrackEvent::Disappeared(topcode) => (false, topcode.code.unwrap()),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn reports_every_change_by_default() {
+        let mut tracker = Tracker::new();
+        let a = TopCode::mock(31, 5.0, 0.0, 10.0, 10.0);
+        let b = TopCode::mock(55, 5.0, 0.0, 50.0, 10.0);
+
+        assert_eq!(
+            vec![(true, 31), (true, 55)],
+            codes(&tracker.update(&[b, a]))
+        );
+        assert!(tracker.update(&[a, b, TopCode::default()]).is_empty());
+        assert_eq!(vec![(false, 31)], codes(&tracker.update(&[b])));
+        assert_eq!(vec![&b], tracker.markers().collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn debounces_intermittent_detections() {
+        let mut tracker = Tracker::new();
+        tracker.set_frames_to_appear(2);
+        tracker.set_frames_to_disappear(3);
+        let a = TopCode::mock(31, 5.0, 0.0, 10.0, 10.0);
+        let moved = TopCode::mock(31, 5.0, 0.0, 12.0, 10.0);
+
+        // A single detection is noise
+        assert!(tracker.update(&[a]).is_empty());
+        assert!(tracker.update(&[]).is_empty());
+        assert!(tracker.update(&[a]).is_empty());
+        assert_eq!(vec![(true, 31)], codes(&tracker.update(&[moved])));
+
+        // Dropping out for fewer frames than required keeps the last detection
+        assert!(tracker.update(&[]).is_empty());
+        assert!(tracker.update(&[]).is_empty());
+        assert_eq!(vec![&moved], tracker.markers().collect::<Vec<_>>());
+        assert!(tracker.update(&[a]).is_empty());
+
+        assert!(tracker.update(&[]).is_empty());
+        assert!(tracker.update(&[]).is_empty());
+        let events = tracker.update(&[]);
+        assert_eq!(vec![TrackEvent::Disappeared(a)], events);
+        assert_eq!(0, tracker.markers().count());
+    }
+
+    #[test]
+    fn callbacks_follow_markers_in_code_order() {
+        use std::sync::{Arc, Mutex};
+
+        let mut tracker = Tracker::new();
+        tracker.set_move_threshold(5.0);
+        let log = Arc::new(Mutex::new(Vec::new()));
+        let logger = |kind| {
+            let log = Arc::clone(&log);
+            move |topcode: &TopCode| log.lock().unwrap().push((kind, topcode.code.unwrap()))
+        };
+        tracker.on_enter(logger("enter"));
+        tracker.on_exit(logger("exit"));
+        tracker.on_move(logger("move"));
+        let at = |code, x| TopCode::mock(code, 5.0, 0.0, x, 10.0);
+
+        tracker.update(&[at(55, 50.0), at(31, 10.0)]);
+        // Jitter below the threshold is not a move, but drifting past it is
+        tracker.update(&[at(55, 53.0), at(31, 10.0)]);
+        tracker.update(&[at(55, 56.0)]);
+        tracker.update(&[at(55, 57.0), at(93, 90.0)]);
+
+        assert_eq!(
+            vec![
+                ("enter", 31),
+                ("enter", 55),
+                ("exit", 31),
+                ("move", 55),
+                ("enter", 93),
+            ],
+            *log.lock().unwrap()
+        );
+    }
+
+    #[test]
+    fn orientations_are_stabilized_over_the_window() {
+        let mut tracker = Tracker::new();
+        tracker.set_orientation_window(3);
+        let arc = 2.0 * PI / 13.0;
+        let orientation = |tracker: &Tracker| tracker.markers().next().unwrap().orientation;
+
+        // A single frame off by a sector is ignored
+        for angle in [0.5, 0.52, 0.5 + arc, 0.49] {
+            tracker.update(&[TopCode::mock(31, 5.0, angle, 10.0, 10.0)]);
+            assert!((orientation(&tracker) - 0.5).abs() < 0.03);
+        }
+
+        // Orientations either side of the wrap are close
+        for angle in [PI - 0.02, -PI + 0.01, PI - 0.01] {
+            tracker.update(&[TopCode::mock(31, 5.0, angle, 10.0, 10.0)]);
+        }
+        assert!(orientation(&tracker).abs() > PI - 0.03);
+    }
+}
@@ -0,0 +1,163 @@
+use crate::{scanner::Scanner, topcode::TopCode};
+
+/// One of the eight ways a stored image can be rotated or flipped relative to the upright scene,
+/// e.g. because of how a camera sensor is mounted. Each variant names the transform that turns the
+/// stored image upright.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum ImageTransform {
+    /// The image is stored upright
+    #[default]
+    Identity,
+    /// Rotate the stored image a quarter turn clockwise
+    Rotate90,
+    /// Rotate the stored image half a turn
+    Rotate180,
+    /// Rotate the stored image a quarter turn counterclockwise
+    Rotate270,
+    /// Mirror the stored image left to right
+    FlipHorizontal,
+    /// Mirror the stored image top to bottom
+    FlipVertical,
+    /// Mirror the stored image across the diagonal from the top left to the bottom right corner
+    Transpose,
+    /// Mirror the stored image across the diagonal from the top right to the bottom left corner
+    Transverse,
+}
+
+impl ImageTransform {
+    /// All eight transforms.
+    pub const ALL: [ImageTransform; 8] = [
+        ImageTransform::Identity,
+        ImageTransform::Rotate90,
+        ImageTransform::Rotate180,
+        ImageTransform::Rotate270,
+        ImageTransform::FlipHorizontal,
+        ImageTransform::FlipVertical,
+        ImageTransform::Transpose,
+        ImageTransform::Transverse,
+    ];
+
+    /// Returns true if the transform swaps the width and height of the image.
+    pub fn swaps_axes(self) -> bool {
+        matches!(
+            self,
+            ImageTransform::Rotate90
+                | ImageTransform::Rotate270
+                | ImageTransform::Transpose
+                | ImageTransform::Transverse
+        )
+    }
+
+    /// Dimensions of the upright image for a stored image of the given dimensions, i.e. the
+    /// dimensions to create the scanner with for [Scanner::scan_transformed].
+    pub fn upright_dimensions(self, width: usize, height: usize) -> (usize, usize) {
+        if self.swaps_axes() {
+            (height, width)
+        } else {
+            (width, height)
+        }
+    }
+
+    /// Index in the stored image of the pixel at `(x, y)` in the upright image of the given
+    /// dimensions.
+    pub(crate) fn source_index(self, x: usize, y: usize, width: usize, height: usize) -> usize {
+        // Stored images that swap axes are `height` pixels wide
+        match self {
+            ImageTransform::Identity => y * width + x,
+            ImageTransform::Rotate90 => (width - 1 - x) * height + y,
+            ImageTransform::Rotate180 => (height - 1 - y) * width + (width - 1 - x),
+            ImageTransform::Rotate270 => x * height + (height - 1 - y),
+            ImageTransform::FlipHorizontal => y * width + (width - 1 - x),
+            ImageTransform::FlipVertical => (height - 1 - y) * width + x,
+            ImageTransform::Transpose => x * height + y,
+            ImageTransform::Transverse => (width - 1 - x) * height + (height - 1 - y),
+        }
+    }
+}
+
+impl Scanner {
+    /// Scans an image stored rotated or flipped, e.g. by a sideways-mounted camera, as if it were
+    /// upright, and returns the TopCodes found in it with their positions and orientations in the
+    /// upright image. The transform is applied by remapping the index of every pixel as it is
+    /// read, so no rotated copy of the image is made.
+    ///
+    /// The scanner must have the dimensions of the upright image, which
+    /// [ImageTransform::upright_dimensions] gives for the dimensions of the stored image.
+    #[must_use = "the codes found are only returned, not kept by the scanner"]
+    pub fn scan_transformed<T: ?Sized>(
+        &mut self,
+        image_buffer: &T,
+        decode_rgb: impl Fn(&T, usize) -> (u32, u32, u32),
+        transform: ImageTransform,
+    ) -> Vec<TopCode> {
+        let (width, height) = self.dimensions();
+        self.scan(image_buffer, |buffer, index| {
+            decode_rgb(
+                buffer,
+                transform.source_index(index % width, index / width, width, height),
+            )
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{render, rgb};
+
+    #[test]
+    fn quarter_turns_are_clockwise() {
+        // A stored image 3 pixels wide and 2 tall, turned upright into 2 wide and 3 tall
+        let (width, height) = ImageTransform::Rotate90.upright_dimensions(3, 2);
+        assert_eq!((2, 3), (width, height));
+
+        // The bottom left pixel of the stored image ends up in the top left corner
+        assert_eq!(
+            3,
+            ImageTransform::Rotate90.source_index(0, 0, width, height)
+        );
+        assert_eq!(
+            0,
+            ImageTransform::Rotate90.source_index(1, 0, width, height)
+        );
+        assert_eq!(
+            2,
+            ImageTransform::Rotate270.source_index(0, 0, width, height)
+        );
+    }
+
+    #[test]
+    fn every_transform_reads_the_upright_codes() {
+        let (width, height) = (200, 100);
+        let upright = render(
+            width,
+            height,
+            &[(31, 50.0, 50.0, 5.0, 0.3), (93, 150.0, 45.0, 4.0, 2.0)],
+        );
+        let expected = Scanner::new(width, height).scan(upright.as_slice(), rgb);
+        assert_eq!(2, expected.len());
+
+        for transform in ImageTransform::ALL {
+            // Store the image the way the transform turns upright
+            let mut stored = vec![0; upright.len()];
+            for y in 0..height {
+                for x in 0..width {
+                    let source = transform.source_index(x, y, width, height);
+                    let pixel = (y * width + x) * 3;
+                    stored[source * 3..source * 3 + 3].copy_from_slice(&upright[pixel..pixel + 3]);
+                }
+            }
+            let (stored_width, stored_height) = if transform.swaps_axes() {
+                (height, width)
+            } else {
+                (width, height)
+            };
+            let (width, height) = transform.upright_dimensions(stored_width, stored_height);
+            let mut scanner = Scanner::new(width, height);
+
+            let topcodes = scanner.scan_transformed(stored.as_slice(), rgb, transform);
+
+            assert_eq!(expected, topcodes, "{transform:?}");
+        }
+    }
+}
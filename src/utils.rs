@@ -1,21 +1,25 @@
-use crate::topcode::SECTORS;
+use crate::topcode::{Code, SECTORS};
 
-/// Debug method that prints the 13 least significant bits of an integer.
-pub(crate) fn print_bits(bits: isize) -> String {
-    let mut lsb = String::new();
+/// Renders the raw bit pattern of a [Code].
+pub trait ToBitString {
+    /// Returns the 13 least significant bits, most significant first, grouped in fours from the
+    /// least significant end (e.g. `0 0000 0001 1111` for 31).
+    fn to_bit_string(&self) -> String;
+}
 
-    for i in (0..SECTORS).rev() {
-        if ((bits >> 1) & 0x01) == 1 {
-            lsb.push('1');
-        } else {
-            lsb.push('0');
-        }
-        if (44 - i) % 4 == 0 {
-            lsb.push(' ');
+impl ToBitString for Code {
+    fn to_bit_string(&self) -> String {
+        let mut bits = String::new();
+
+        for i in (0..SECTORS).rev() {
+            bits.push(if (self >> i) & 0x01 == 1 { '1' } else { '0' });
+            if i > 0 && i % 4 == 0 {
+                bits.push(' ');
+            }
         }
-    }
 
-    format!("{}= {}", lsb, bits)
+        bits
+    }
 }
 
 #[cfg(test)]
@@ -24,6 +28,8 @@ mod tests {
 
     #[test]
     fn can_print_the_bits_of_the_13_least_significant_bits() {
-        assert_eq!("1 1111 1111 1111 = 31", print_bits(31))
+        assert_eq!("0 0000 0001 1111", 31.to_bit_string());
+        assert_eq!("1 0000 0000 0001", 0b1_0000_0000_0001.to_bit_string());
+        assert_eq!("1 1111 1111 1111", u32::MAX.to_bit_string());
     }
 }
@@ -0,0 +1,245 @@
+use image::{imageops, Rgb, RgbImage, Rgba, RgbaImage};
+
+use crate::{scanner::Scanner, topcode::TopCode};
+
+/// Colors and sizes used by [annotate] to draw TopCodes. Colors are RGBA, and their alpha blends
+/// them over the image.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct AnnotationStyle {
+    /// Fill of the disc covering each code
+    pub disc: Rgba<u8>,
+    /// Square drawn on the edge of the disc in the direction of the orientation
+    pub orientation: Rgba<u8>,
+    /// Square drawn on the center of each code
+    pub center: Rgba<u8>,
+    /// Side of the orientation and center squares in pixels
+    pub square_size: u32,
+}
+
+impl Default for AnnotationStyle {
+    fn default() -> Self {
+        Self {
+            disc: Rgba([255, 170, 0, 80]),
+            orientation: Rgba([0, 127, 255, 80]),
+            center: Rgba([255, 0, 0, 80]),
+            square_size: 5,
+        }
+    }
+}
+
+impl Scanner {
+    /// Scans an RGB image and returns the TopCodes found along with a copy of the image on which
+    /// they are drawn with [annotate], for the common case of checking what the scanner detects.
+    /// `buffer` holds the image as packed RGB rows of the scanner's dimensions.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the length of `buffer` does not match the dimensions of the scanner.
+    #[must_use = "the codes found are only returned, not kept by the scanner"]
+    pub fn scan_annotated(
+        &mut self,
+        buffer: &[u8],
+        style: &AnnotationStyle,
+    ) -> (Vec<TopCode>, RgbaImage) {
+        let (width, height) = self.dimensions();
+        assert_eq!(
+            width * height * 3,
+            buffer.len(),
+            "Image buffer length must be width * height * 3"
+        );
+        let topcodes = self.scan(buffer, |buffer, index| {
+            (
+                buffer[index * 3] as u32,
+                buffer[index * 3 + 1] as u32,
+                buffer[index * 3 + 2] as u32,
+            )
+        });
+
+        let mut img = RgbaImage::from_fn(width as u32, height as u32, |x, y| {
+            let index = (y as usize * width + x as usize) * 3;
+            Rgba([buffer[index], buffer[index + 1], buffer[index + 2], 255])
+        });
+        annotate(&mut img, &topcodes, style);
+        (topcodes, img)
+    }
+}
+
+/// Draws TopCodes over an image: a disc covering each code, a square on its edge in the direction
+/// of its orientation, and a square on its center. Shapes are clipped to the image.
+pub fn annotate(img: &mut RgbaImage, codes: &[TopCode], style: &AnnotationStyle) {
+    for code in codes {
+        let radius = code.radius();
+        fill(img, code.x, code.y, radius, style.disc, |dx, dy| {
+            dx * dx + dy * dy < radius * radius
+        });
+
+        let half = style.square_size as f64 / 2.0;
+        let (sin, cos) = code.orientation.sin_cos();
+        let (x, y) = (code.x + cos * radius, code.y + sin * radius);
+        fill(img, x, y, half, style.orientation, |_, _| true);
+        fill(img, code.x, code.y, half, style.center, |_, _| true);
+    }
+}
+
+/// Blends `color` over the pixels within `reach` of `(x, y)` on both axes for which `inside`
+/// holds, given their offset from `(x, y)`.
+fn fill(
+    img: &mut RgbaImage,
+    x: f64,
+    y: f64,
+    reach: f64,
+    color: Rgba<u8>,
+    inside: impl Fn(f64, f64) -> bool,
+) {
+    let (width, height) = img.dimensions();
+    let left = (x - reach).floor().max(0.0) as u32;
+    let top = (y - reach).floor().max(0.0) as u32;
+    let right = ((x + reach).ceil().max(0.0) as u32).min(width);
+    let bottom = ((y + reach).ceil().max(0.0) as u32).min(height);
+
+    let alpha = color[3] as f32 / 255.0;
+    for py in top..bottom {
+        for px in left..right {
+            if !inside(px as f64 + 0.5 - x, py as f64 + 0.5 - y) {
+                continue;
+            }
+            let pixel = img.get_pixel_mut(px, py);
+            for channel in 0..3 {
+                pixel[channel] =
+                    (pixel[channel] as f32 * (1.0 - alpha) + color[channel] as f32 * alpha) as u8;
+            }
+        }
+    }
+}
+
+/// Extracts the region of the image covered by a TopCode's [bounding box](TopCode::bounding_box),
+/// grown by `padding` pixels on every side. The crop is clamped to the bounds of the image, so
+/// codes near the edges produce smaller images.
+pub fn crop_marker(img: &RgbImage, code: &TopCode, padding: u32) -> RgbImage {
+    let (min_x, min_y, max_x, max_y) = code.bounding_box();
+    let padding = padding as f64;
+
+    let clamp = |value: f64, max: u32| value.clamp(0.0, max as f64) as u32;
+    let left = clamp((min_x - padding).floor(), img.width());
+    let top = clamp((min_y - padding).floor(), img.height());
+    let right = clamp((max_x + padding).ceil(), img.width());
+    let bottom = clamp((max_y + padding).ceil(), img.height());
+
+    imageops::crop_imm(img, left, top, right - left, bottom - top).to_image()
+}
+
+/// Samples the region of the image under a TopCode into a square patch of `out_size` pixels,
+/// rotated so that the code always appears in its canonical orientation, whatever its orientation
+/// and size in the image. The patch covers the whole symbol (see [TopCode::radius]), and each
+/// pixel is mapped through [TopCode::project] and interpolated bilinearly. Pixels that map outside
+/// the image are black.
+pub fn rectify(img: &RgbImage, code: &TopCode, out_size: u32) -> RgbImage {
+    let scale = code.width as f64 / out_size as f64;
+    let half = code.width as f64 / 2.0;
+
+    RgbImage::from_fn(out_size, out_size, |i, j| {
+        let u = (i as f64 + 0.5) * scale - half;
+        let v = (j as f64 + 0.5) * scale - half;
+        let (x, y) = code.project(u, v);
+        // Pixel centers are at integer coordinates in the image
+        imageops::interpolate_bilinear(img, (x - 0.5) as f32, (y - 0.5) as f32)
+            .unwrap_or(Rgb([0; 3]))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{render, rgb};
+
+    #[test]
+    fn crop_covers_the_code_and_padding() {
+        let img = RgbImage::new(100, 100);
+        let code = TopCode::mock(31, 5.0, 0.0, 50.0, 50.0);
+
+        assert_eq!((40, 40), crop_marker(&img, &code, 0).dimensions());
+        assert_eq!((50, 50), crop_marker(&img, &code, 5).dimensions());
+    }
+
+    #[test]
+    fn crop_is_clamped_to_the_image() {
+        let img = RgbImage::new(100, 100);
+        let code = TopCode::mock(31, 5.0, 0.0, 10.0, 95.0);
+
+        assert_eq!((30, 25), crop_marker(&img, &code, 0).dimensions());
+    }
+
+    #[test]
+    fn annotated_scans_draw_over_the_codes_found() {
+        let buffer = render(
+            120,
+            80,
+            &[(31, 40.0, 40.0, 5.0, 0.0), (93, 100.0, 70.0, 5.0, 0.5)],
+        );
+        let mut scanner = Scanner::new(120, 80);
+        let style = AnnotationStyle {
+            disc: Rgba([0, 255, 0, 255]),
+            ..AnnotationStyle::default()
+        };
+
+        let (topcodes, img) = scanner.scan_annotated(&buffer, &style);
+
+        assert_eq!(scanner.scan(buffer.as_slice(), rgb), topcodes);
+        assert_eq!((120, 80), img.dimensions());
+        // The disc covers the code, the center and orientation squares blend over it, and the
+        // rest of the image is copied as is
+        let code = topcodes.iter().find(|code| code.code == Some(31)).unwrap();
+        let (sin, cos) = code.orientation.sin_cos();
+        let (x, y) = (
+            code.x + cos * (code.radius() - 1.0),
+            code.y + sin * (code.radius() - 1.0),
+        );
+        let green = Rgba([0, 255, 0, 255]);
+        assert_eq!(green, *img.get_pixel(40, 30));
+        assert_ne!(green, *img.get_pixel(40, 40));
+        assert_ne!(green, *img.get_pixel(x as u32, y as u32));
+        assert_eq!(Rgba([255, 255, 255, 255]), *img.get_pixel(5, 5));
+    }
+
+    #[test]
+    fn rotated_codes_rectify_to_the_same_patch() {
+        let patches: Vec<RgbImage> = [(6.0, 0.3), (9.0, 2.5)]
+            .into_iter()
+            .map(|(unit, angle)| {
+                let buffer = render(120, 120, &[(31, 60.0, 60.0, unit, angle)]);
+                let code = Scanner::new(120, 120).scan(buffer.as_slice(), rgb)[0];
+                let img = RgbImage::from_raw(120, 120, buffer).unwrap();
+                rectify(&img, &code, 48)
+            })
+            .collect();
+
+        // Only pixels along the edges of the rings differ, as the unit and orientation of each
+        // code are estimated from the image
+        assert_eq!((48, 48), patches[0].dimensions());
+        let matching = patches[0]
+            .pixels()
+            .zip(patches[1].pixels())
+            .filter(|(a, b)| (a[0] as i32 - b[0] as i32).abs() < 128)
+            .count();
+        assert!(matching > 48 * 48 * 8 / 10, "{} pixels match", matching);
+    }
+
+    #[test]
+    fn rectified_patches_follow_the_width_of_the_family() {
+        let buffer = render(120, 120, &[(31, 60.0, 60.0, 5.0, 0.0)]);
+        let img = RgbImage::from_raw(120, 120, buffer).unwrap();
+        let code = TopCode::mock(31, 5.0, 0.0, 60.0, 60.0);
+        let wide = TopCode { width: 16, ..code };
+
+        // Twice the units at the same resolution: the symbol fills the middle of the patch, and
+        // the image around it fills the rest
+        let patch = rectify(&img, &code, 40);
+        let wide_patch = rectify(&img, &wide, 80);
+        for (i, j, pixel) in patch.enumerate_pixels() {
+            let wide_pixel = wide_patch.get_pixel(i + 20, j + 20);
+            assert!((pixel[0] as i32 - wide_pixel[0] as i32).abs() <= 1);
+        }
+        assert_eq!(img.get_pixel(60, 20), wide_patch.get_pixel(40, 0));
+        assert_eq!(img.get_pixel(20, 60), wide_patch.get_pixel(0, 40));
+    }
+}
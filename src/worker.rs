@@ -0,0 +1,194 @@
+use std::{
+    sync::{
+        mpsc::{self, Receiver, RecvError, TryRecvError},
+        Arc, Condvar, Mutex,
+    },
+    thread::{self, JoinHandle},
+};
+
+use crate::{scanner::Scanner, topcode::TopCode};
+
+/// The frame waiting to be scanned, shared between a [ScanThread] and its thread.
+#[derive(Default)]
+struct Slot {
+    /// Latest frame that was not picked up by the thread yet
+    frame: Option<Vec<u8>>,
+    /// Number of frames replaced by a newer one before they were scanned
+    dropped: usize,
+    /// Whether the handle was closed, after which the thread exits
+    closed: bool,
+}
+
+impl Slot {
+    /// Queues a frame, dropping the one that is still waiting.
+    fn put(&mut self, frame: Vec<u8>) {
+        if self.frame.replace(frame).is_some() {
+            self.dropped += 1;
+        }
+    }
+}
+
+/// A handle to a thread that owns a [Scanner] and scans the RGB frames sent to it, created with
+/// [spawn_scan_thread]. At most one frame waits to be scanned: sending a frame while another one is
+/// still waiting drops the older frame, so a slow scanner always works on the most recent image
+/// instead of falling further behind the camera.
+pub struct ScanThread {
+    slot: Arc<(Mutex<Slot>, Condvar)>,
+    results: Receiver<Vec<TopCode>>,
+    frame_len: usize,
+    handle: Option<JoinHandle<Scanner>>,
+}
+
+/// Spawns a thread that takes ownership of the scanner and scans every frame sent through the
+/// returned [ScanThread], in the order they were sent, sending the TopCodes of each frame back.
+pub fn spawn_scan_thread(mut scanner: Scanner) -> ScanThread {
+    let (width, height) = scanner.dimensions();
+    let slot = Arc::new((Mutex::new(Slot::default()), Condvar::new()));
+    let (sender, results) = mpsc::channel();
+
+    let shared = Arc::clone(&slot);
+    let handle = thread::spawn(move || {
+        let (lock, ready) = &*shared;
+        loop {
+            let frame = {
+                let mut slot = lock.lock().unwrap();
+                while slot.frame.is_none() && !slot.closed {
+                    slot = ready.wait(slot).unwrap();
+                }
+                match slot.frame.take() {
+                    Some(frame) => frame,
+                    None => break,
+                }
+            };
+
+            let topcodes = scanner.scan(frame.as_slice(), |buffer, index| {
+                (
+                    buffer[index * 3] as u32,
+                    buffer[index * 3 + 1] as u32,
+                    buffer[index * 3 + 2] as u32,
+                )
+            });
+            if sender.send(topcodes).is_err() {
+                break;
+            }
+        }
+        scanner
+    });
+
+    ScanThread {
+        slot,
+        results,
+        frame_len: width * height * 3,
+        handle: Some(handle),
+    }
+}
+
+impl ScanThread {
+    /// Queues an RGB frame (3 bytes per pixel) to be scanned, replacing the frame that is still
+    /// waiting, if any.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the frame does not match the dimensions of the scanner.
+    pub fn send(&self, frame: Vec<u8>) {
+        assert_eq!(
+            frame.len(),
+            self.frame_len,
+            "Frame length must be 3 * width * height"
+        );
+
+        let (lock, ready) = &*self.slot;
+        lock.lock().unwrap().put(frame);
+        ready.notify_one();
+    }
+
+    /// Blocks until the next frame has been scanned and returns its TopCodes. Fails if the thread
+    /// has stopped, e.g. because a scan panicked.
+    pub fn recv(&self) -> Result<Vec<TopCode>, RecvError> {
+        self.results.recv()
+    }
+
+    /// Returns the TopCodes of the next scanned frame if one is ready, without blocking.
+    pub fn try_recv(&self) -> Result<Vec<TopCode>, TryRecvError> {
+        self.results.try_recv()
+    }
+
+    /// Number of frames that were replaced by a newer frame before the thread could scan them.
+    pub fn dropped_frames(&self) -> usize {
+        self.slot.0.lock().unwrap().dropped
+    }
+
+    /// Scans the frame that is still waiting, if any, stops the thread and returns its scanner.
+    /// Results that were not received yet are discarded.
+    ///
+    /// # Panics
+    ///
+    /// Panics if a scan on the thread panicked.
+    pub fn join(mut self) -> Scanner {
+        self.close();
+        let handle = self.handle.take().unwrap();
+        handle.join().expect("The scan thread panicked")
+    }
+
+    fn close(&self) {
+        let (lock, ready) = &*self.slot;
+        lock.lock().unwrap().closed = true;
+        ready.notify_one();
+    }
+}
+
+impl Drop for ScanThread {
+    /// Stops the thread once the frame that is still waiting, if any, has been scanned, without
+    /// waiting for it.
+    fn drop(&mut self) {
+        self.close();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::render;
+
+    #[test]
+    fn frames_are_scanned_in_order() {
+        let worker = spawn_scan_thread(Scanner::new(100, 100));
+
+        for code in [31, 47, 55] {
+            worker.send(render(100, 100, &[(code, 50.0, 50.0, 5.0, 0.0)]));
+            let topcodes = worker.recv().unwrap();
+            assert_eq!(1, topcodes.len());
+            assert_eq!(Some(code), topcodes[0].code);
+        }
+
+        assert_eq!(0, worker.dropped_frames());
+        assert_eq!((100, 100), worker.join().dimensions());
+    }
+
+    #[test]
+    fn only_the_latest_waiting_frame_is_kept() {
+        let worker = spawn_scan_thread(Scanner::new(100, 100));
+        let (lock, ready) = &*worker.slot;
+
+        // Hold the lock while queueing, as if the thread was busy scanning an earlier frame
+        {
+            let mut slot = lock.lock().unwrap();
+            for code in [31, 47, 55, 59] {
+                slot.put(render(100, 100, &[(code, 50.0, 50.0, 5.0, 0.0)]));
+            }
+        }
+        ready.notify_one();
+
+        let topcodes = worker.recv().unwrap();
+        assert_eq!(Some(59), topcodes[0].code);
+        assert_eq!(3, worker.dropped_frames());
+        worker.join();
+    }
+
+    #[test]
+    #[should_panic(expected = "Frame length must be 3 * width * height")]
+    fn frames_must_match_the_scanner() {
+        let worker = spawn_scan_thread(Scanner::new(100, 100));
+        worker.send(vec![0; 100]);
+    }
+}